@@ -0,0 +1,108 @@
+//! Reads tables, columns and foreign keys through `sqlite_master` and PRAGMAs
+
+use diesel_d1::migrations::{BOOKMARKS_TABLE, CHECKPOINTS_TABLE, MIGRATIONS_TABLE, WRANGLER_MIGRATIONS_TABLE};
+use serde_json::Value;
+
+use crate::source::{Result, SchemaSource};
+
+/// Tables diesel-d1 keeps its own bookkeeping in
+const MIGRATION_TABLES: [&str; 4] = [MIGRATIONS_TABLE, WRANGLER_MIGRATIONS_TABLE, CHECKPOINTS_TABLE, BOOKMARKS_TABLE];
+
+/// Filters out tables that belong to SQLite, D1, Wrangler or diesel itself
+fn internal_tables_filter() -> String {
+    let migration_tables = MIGRATION_TABLES.map(|table| format!("'{table}'")).join(", ");
+    format!(
+        "name NOT LIKE 'sqlite\\_%' ESCAPE '\\' \
+        AND name NOT LIKE '\\_cf\\_%' ESCAPE '\\' \
+        AND name NOT IN ({migration_tables})"
+    )
+}
+
+#[derive(Debug, Clone)]
+pub struct Table {
+    pub name: String,
+    pub columns: Vec<Column>,
+    pub foreign_keys: Vec<ForeignKey>,
+}
+
+impl Table {
+    /// Primary key columns, in key order
+    pub fn primary_key(&self) -> Vec<&Column> {
+        let mut primary_key = self.columns.iter().filter(|column| column.primary_key > 0).collect::<Vec<_>>();
+        primary_key.sort_by_key(|column| column.primary_key);
+        primary_key
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Column {
+    pub name: String,
+    /// Declared type, as written in `CREATE TABLE`
+    pub declared_type: String,
+    pub not_null: bool,
+    /// Position in the primary key starting at 1, 0 if the column isn't part of it
+    pub primary_key: i64,
+}
+
+/// One column of a foreign key, keys spanning several columns are listed once per column
+#[derive(Debug, Clone)]
+pub struct ForeignKey {
+    pub column: String,
+    pub parent_table: String,
+    /// `None` when the key references the parent's primary key implicitly
+    pub parent_column: Option<String>,
+}
+
+pub fn load_tables(source: &mut dyn SchemaSource) -> Result<Vec<Table>> {
+    let names = source.query(&format!(
+        "SELECT name FROM sqlite_master WHERE type = 'table' AND {} ORDER BY name",
+        internal_tables_filter()
+    ))?;
+
+    names
+        .iter()
+        .map(|row| {
+            let name = text(&row[0]);
+            Ok(Table {
+                columns: load_columns(source, &name)?,
+                foreign_keys: load_foreign_keys(source, &name)?,
+                name,
+            })
+        })
+        .collect()
+}
+
+fn load_columns(source: &mut dyn SchemaSource, table: &str) -> Result<Vec<Column>> {
+    // cid, name, type, notnull, dflt_value, pk
+    let rows = source.query(&format!("PRAGMA table_info({})", quote_identifier(table)))?;
+    Ok(rows
+        .iter()
+        .map(|row| Column {
+            name: text(&row[1]),
+            declared_type: text(&row[2]),
+            not_null: row[3].as_i64() == Some(1),
+            primary_key: row[5].as_i64().unwrap_or_default(),
+        })
+        .collect())
+}
+
+fn load_foreign_keys(source: &mut dyn SchemaSource, table: &str) -> Result<Vec<ForeignKey>> {
+    // id, seq, table, from, to, on_update, on_delete, match
+    let rows = source.query(&format!("PRAGMA foreign_key_list({})", quote_identifier(table)))?;
+    Ok(rows
+        .iter()
+        .map(|row| ForeignKey {
+            parent_table: text(&row[2]),
+            column: text(&row[3]),
+            parent_column: row[4].as_str().map(str::to_owned),
+        })
+        .collect())
+}
+
+fn text(value: &Value) -> String {
+    value.as_str().unwrap_or_default().to_owned()
+}
+
+fn quote_identifier(identifier: &str) -> String {
+    format!("\"{}\"", identifier.replace('"', "\"\""))
+}
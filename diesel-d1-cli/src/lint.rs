@@ -0,0 +1,43 @@
+//! Lints migration directories with `diesel_d1::migrations::lint_migrations`
+
+use std::path::{Path, PathBuf};
+
+use diesel_d1::migrations::{lint_migrations, D1LintIssue, D1LintSeverity, WranglerMigrations};
+use diesel_migrations::FileBasedMigrations;
+
+use crate::source::Result;
+
+pub fn lint(paths: &[PathBuf]) -> Result<()> {
+    let mut errors = 0;
+
+    for path in paths {
+        for issue in lint_directory(path)? {
+            if issue.severity == D1LintSeverity::Error {
+                errors += 1;
+            }
+            eprintln!("{issue}\n");
+        }
+    }
+
+    if errors > 0 {
+        return Err(format!("{errors} statement(s) would be rejected by D1").into());
+    }
+    Ok(())
+}
+
+/// Wrangler keeps one `.sql` file per migration, diesel one directory
+pub fn is_wrangler_directory(path: &Path) -> Result<bool> {
+    Ok(std::fs::read_dir(path)
+        .map_err(|err| format!("unable to read {}: {err}", path.display()))?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .any(|path| path.is_file() && path.extension().is_some_and(|extension| extension == "sql")))
+}
+
+fn lint_directory(path: &Path) -> Result<Vec<D1LintIssue>> {
+    let issues = if is_wrangler_directory(path)? {
+        lint_migrations(&WranglerMigrations::from_dir(path)?)?
+    } else {
+        lint_migrations(&FileBasedMigrations::from_path(path)?)?
+    };
+    Ok(issues)
+}
@@ -0,0 +1,142 @@
+use std::{path::PathBuf, process::ExitCode};
+
+use clap::{Args, Parser, Subcommand};
+use source::{HttpApi, Result, SchemaSource, SqliteFile, DEFAULT_API_URL};
+
+mod introspect;
+mod lint;
+mod migrate;
+#[cfg(test)]
+mod mock_api;
+mod print_schema;
+mod source;
+
+#[derive(Parser)]
+#[command(name = "diesel-d1", version, about = "Command line tools for diesel-d1")]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Prints `table!` definitions for every table of a D1 database
+    PrintSchema {
+        #[command(flatten)]
+        source: SourceArgs,
+    },
+    /// Checks migrations for SQL that D1 rejects
+    ///
+    /// Takes diesel migration directories (one directory with `up.sql`/`down.sql` per
+    /// migration) as well as Wrangler ones (one `.sql` file per migration).
+    Lint {
+        #[arg(default_value = "migrations")]
+        paths: Vec<PathBuf>,
+    },
+    /// Runs pending migrations
+    ///
+    /// Remote databases get a Time Travel bookmark taken before each migration, which is
+    /// recorded with it so `restore-to-bookmark --version` can undo it.
+    Migrate {
+        #[arg(long, default_value = "migrations")]
+        migrations_dir: PathBuf,
+        #[command(flatten)]
+        source: SourceArgs,
+    },
+    /// Restores a remote database to a Time Travel bookmark
+    RestoreToBookmark {
+        /// Bookmark to restore to
+        #[arg(long, required_unless_present = "version", conflicts_with = "version")]
+        bookmark: Option<String>,
+        /// Restore to the bookmark taken before this migration was applied by `migrate`
+        #[arg(long)]
+        version: Option<String>,
+        #[command(flatten)]
+        source: SourceArgs,
+    },
+}
+
+/// Where the database is read from. Without any of these, the local database in
+/// `.wrangler/state` is used.
+#[derive(Args)]
+struct SourceArgs {
+    /// SQLite file holding the database
+    #[arg(long, conflicts_with_all = ["wrangler_state", "database_id"])]
+    database: Option<PathBuf>,
+
+    /// Wrangler state directory of a project, as used by `wrangler dev` and `--local`
+    #[arg(long, conflicts_with = "database_id")]
+    wrangler_state: Option<PathBuf>,
+
+    /// Cloudflare account id, to read a remote database through the HTTP API
+    #[arg(long, env = "CLOUDFLARE_ACCOUNT_ID", requires = "database_id")]
+    account_id: Option<String>,
+
+    /// D1 database id, to read a remote database through the HTTP API
+    #[arg(long, requires_all = ["account_id", "api_token"])]
+    database_id: Option<String>,
+
+    /// API token with D1 access, read access is enough for `print-schema`
+    #[arg(long, env = "CLOUDFLARE_API_TOKEN", hide_env_values = true)]
+    api_token: Option<String>,
+
+    /// Base URL of the Cloudflare API, e.g. to point at a mock server
+    #[arg(long, env = "CLOUDFLARE_API_BASE_URL", default_value = DEFAULT_API_URL)]
+    api_url: String,
+}
+
+impl SourceArgs {
+    fn open(self, writable: bool) -> Result<Box<dyn SchemaSource>> {
+        if self.database_id.is_some() {
+            return Ok(Box::new(self.open_http()?));
+        }
+
+        let database = match self.database {
+            Some(database) => database,
+            None => SqliteFile::find_in_wrangler_state(
+                &self.wrangler_state.unwrap_or_else(|| PathBuf::from(".wrangler/state")),
+            )?,
+        };
+        Ok(Box::new(SqliteFile::open(&database, writable)?))
+    }
+
+    /// The remote database, for features only the REST API has
+    fn open_http(&self) -> Result<HttpApi> {
+        match (&self.account_id, &self.database_id, &self.api_token) {
+            (Some(account_id), Some(database_id), Some(api_token)) => {
+                Ok(HttpApi::new(&self.api_url, account_id, database_id, api_token))
+            },
+            _ => Err("this needs a remote database, pass --account-id, --database-id and --api-token".into()),
+        }
+    }
+}
+
+fn main() -> ExitCode {
+    match run(Cli::parse()) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("error: {err}");
+            ExitCode::FAILURE
+        },
+    }
+}
+
+fn run(cli: Cli) -> Result<()> {
+    match cli.command {
+        Command::PrintSchema { source } => {
+            let mut source = source.open(false)?;
+            let tables = introspect::load_tables(source.as_mut())?;
+            print!("{}", print_schema::print_schema(&tables));
+        },
+        Command::Lint { paths } => lint::lint(&paths)?,
+        Command::Migrate { migrations_dir, source } => {
+            let mut source = source.open(true)?;
+            migrate::migrate(source.as_mut(), &migrations_dir)?;
+        },
+        Command::RestoreToBookmark { bookmark, version, source } => {
+            migrate::restore_to_bookmark(&mut source.open_http()?, bookmark, version)?;
+        },
+    }
+
+    Ok(())
+}
@@ -0,0 +1,204 @@
+//! Applies migrations from the command line, taking a Time Travel bookmark before each one
+
+use std::path::Path;
+
+use diesel::migration::{Migration, MigrationSource};
+use diesel_d1::{
+    backend::D1Backend,
+    migrations::{migration_sql, D1MigrationBookmark, D1MigrationsTable, WranglerMigrations, BOOKMARKS_TABLE},
+};
+use diesel_migrations::FileBasedMigrations;
+use serde_json::Value;
+
+use crate::{
+    lint::is_wrangler_directory,
+    source::{HttpApi, Result, SchemaSource},
+};
+
+/// Runs the pending migrations of `path`, each one in a single request with its record
+///
+/// Against a remote database, the bookmark of its state right before each migration is stored
+/// with it, for [`restore_to_bookmark`]. The REST API doesn't promise to apply a request
+/// atomically, so a failed migration may be left half applied; its bookmark undoes it.
+pub fn migrate(source: &mut dyn SchemaSource, path: &Path) -> Result<()> {
+    let (table, mut migrations): (_, Vec<Box<dyn Migration<D1Backend>>>) = if is_wrangler_directory(path)? {
+        (D1MigrationsTable::Wrangler, WranglerMigrations::from_dir(path)?.migrations()?)
+    } else {
+        (D1MigrationsTable::Diesel, FileBasedMigrations::from_path(path)?.migrations()?)
+    };
+
+    source.execute(&[table.create_sql(), D1MigrationBookmark::create_table_sql()])?;
+    let applied = source
+        .query(&table.select_sql())?
+        .into_iter()
+        .filter_map(|row| row.into_iter().next())
+        .filter_map(|version| version.as_str().map(str::to_owned))
+        .collect::<Vec<_>>();
+
+    migrations.retain(|migration| !applied.contains(&migration.name().version().to_string()));
+    migrations.sort_by_key(|migration| migration.name().version().as_owned());
+    if migrations.is_empty() {
+        eprintln!("no pending migrations");
+        return Ok(());
+    }
+
+    for migration in migrations {
+        let version = migration.name().version();
+        // taken again for every migration, so restoring one doesn't undo the ones before it
+        let bookmark = source.bookmark()?;
+        if let Some(bookmark) = &bookmark {
+            eprintln!("bookmark before {version}: {bookmark}");
+        }
+
+        let mut statements = migration_sql(&*migration)?;
+        statements.push(table.insert_sql(&version));
+        if let Some(bookmark) = &bookmark {
+            let record = D1MigrationBookmark {
+                version: version.to_string(),
+                bookmark: bookmark.clone(),
+            };
+            statements.push(record.insert_sql());
+        }
+
+        source
+            .execute(&statements)
+            .map_err(|err| match &bookmark {
+                Some(bookmark) => format!(
+                    "migration {version} failed: {err}\n\
+                    restore to bookmark {bookmark} to undo any part of it that was applied"
+                ),
+                None => format!("migration {version} failed: {err}"),
+            })?;
+        eprintln!("applied {version}");
+    }
+
+    Ok(())
+}
+
+/// Restores the database with Time Travel, either to `bookmark` or to the bookmark recorded
+/// before migration `version` was applied
+///
+/// Only migrations [`migrate`] applied to a remote database have a recorded bookmark. Others,
+/// e.g. applied by a Worker through `D1Connection::run_pending_migrations` or by Wrangler,
+/// need a bookmark from `wrangler d1 time-travel info` passed directly.
+pub fn restore_to_bookmark(api: &mut HttpApi, bookmark: Option<String>, version: Option<String>) -> Result<()> {
+    let bookmark = match (bookmark, version) {
+        (Some(bookmark), _) => bookmark,
+        (None, Some(version)) => find_bookmark(api, &version)?,
+        (None, None) => return Err("pass either --bookmark or --version".into()),
+    };
+
+    let restored = api.restore(&bookmark)?;
+    eprintln!("restored to bookmark {}", restored.bookmark);
+    if let Some(previous) = restored.previous_bookmark {
+        eprintln!("to undo the restore, restore to bookmark {previous}");
+    }
+    Ok(())
+}
+
+fn find_bookmark(api: &mut HttpApi, version: &str) -> Result<String> {
+    let missing = || {
+        format!(
+            "no bookmark has been recorded for migration {version}, only migrations applied by \
+            `diesel-d1 migrate` to a remote database have one; find a bookmark with \
+            `wrangler d1 time-travel info` and pass it with --bookmark"
+        )
+        .into()
+    };
+
+    let exists = api.query(&format!(
+        "SELECT COUNT(*) FROM sqlite_master WHERE type = 'table' AND name = '{BOOKMARKS_TABLE}'"
+    ))?;
+    if exists.first().and_then(|row| row.first()) != Some(&Value::from(1)) {
+        return Err(missing());
+    }
+
+    let rows = api.query(&format!(
+        "SELECT bookmark FROM {BOOKMARKS_TABLE} WHERE version = '{}'",
+        version.replace('\'', "''")
+    ))?;
+    rows.first()
+        .and_then(|row| row.first())
+        .and_then(Value::as_str)
+        .map(str::to_owned)
+        .ok_or_else(missing)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::mock_api::{failure, raw_rows, success, MockApi};
+
+    #[test]
+    fn bookmarks_every_migration() {
+        let dir = std::env::temp_dir().join(format!("diesel-d1-migrate-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("0001_users.sql"), "CREATE TABLE users (id INTEGER PRIMARY KEY);").unwrap();
+        std::fs::write(dir.join("0002_posts.sql"), "CREATE TABLE posts (id INTEGER PRIMARY KEY);").unwrap();
+        std::fs::write(dir.join("0003_broken.sql"), "CREATE TABLE posts (id INTEGER PRIMARY KEY);").unwrap();
+
+        let mock = MockApi::start(vec![
+            success(json!([])),
+            raw_rows(json!([["0001_users.sql"]])),
+            success(json!({ "bookmark": "before-posts" })),
+            success(json!([])),
+            success(json!({ "bookmark": "before-broken" })),
+            failure("table posts already exists: SQLITE_ERROR"),
+        ]);
+        let mut api = HttpApi::new(&mock.url, "account", "database", "token");
+
+        let err = migrate(&mut api, &dir).unwrap_err().to_string();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert!(err.starts_with("migration 0003_broken.sql failed"), "{err}");
+        assert!(err.ends_with("restore to bookmark before-broken to undo any part of it that was applied"), "{err}");
+
+        let requests = mock.requests();
+        let paths = requests
+            .iter()
+            .map(|request| request.path.rsplit("/database/").next().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            paths,
+            ["query", "raw", "time_travel/bookmark", "query", "time_travel/bookmark", "query"]
+        );
+        for (request, version, bookmark) in [
+            (&requests[3], "0002_posts.sql", "before-posts"),
+            (&requests[5], "0003_broken.sql", "before-broken"),
+        ] {
+            let sql = request.body["sql"].as_str().unwrap();
+            assert!(
+                sql.ends_with(&format!(
+                    "INSERT OR REPLACE INTO {BOOKMARKS_TABLE} (version, bookmark) VALUES ('{version}', '{bookmark}')"
+                )),
+                "{sql}"
+            );
+        }
+    }
+
+    #[test]
+    fn names_migrations_without_a_bookmark() {
+        let mock = MockApi::start(vec![
+            raw_rows(json!([[0]])),
+            raw_rows(json!([[1]])),
+            raw_rows(json!([])),
+            raw_rows(json!([[1]])),
+            raw_rows(json!([["before-posts"]])),
+        ]);
+        let mut api = HttpApi::new(&mock.url, "account", "database", "token");
+
+        // nothing recorded at all, e.g. every migration was applied by the Worker
+        let err = find_bookmark(&mut api, "0001_users.sql").unwrap_err().to_string();
+        assert!(
+            err.starts_with("no bookmark has been recorded for migration 0001_users.sql, only migrations applied by"),
+            "{err}"
+        );
+        let err = find_bookmark(&mut api, "0003_tags.sql").unwrap_err().to_string();
+        assert!(err.starts_with("no bookmark has been recorded for migration 0003_tags.sql"), "{err}");
+        assert!(err.ends_with("pass it with --bookmark"), "{err}");
+
+        assert_eq!(find_bookmark(&mut api, "0002_posts.sql").unwrap(), "before-posts");
+        assert!(mock.requests()[4].body["sql"].as_str().unwrap().ends_with("WHERE version = '0002_posts.sql'"));
+    }
+}
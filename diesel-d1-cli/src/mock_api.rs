@@ -0,0 +1,112 @@
+//! A stand-in for the Cloudflare REST API, answering requests with canned responses
+
+use std::{
+    io::{BufRead, BufReader, Read, Write},
+    net::TcpListener,
+    sync::mpsc,
+    thread,
+};
+
+use serde_json::{json, Value};
+
+/// A request the mock received
+#[derive(Debug)]
+pub struct Request {
+    pub method: String,
+    /// Path and query string
+    pub path: String,
+    pub authorization: Option<String>,
+    /// `Null` for requests without a body
+    pub body: Value,
+}
+
+pub struct MockApi {
+    /// What to pass to `HttpApi::new` as `api_url`
+    pub url: String,
+    requests: mpsc::Receiver<Request>,
+}
+
+impl MockApi {
+    /// Answers one request with each of `responses` in turn, then stops listening
+    pub fn start(responses: Vec<Value>) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/client/v4", listener.local_addr().unwrap());
+        let (sender, requests) = mpsc::channel();
+
+        thread::spawn(move || {
+            for response in responses {
+                let (stream, _) = listener.accept().unwrap();
+                let mut reader = BufReader::new(stream);
+
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                let mut parts = line.split_whitespace();
+                let method = parts.next().unwrap_or_default().to_owned();
+                let path = parts.next().unwrap_or_default().to_owned();
+
+                let mut authorization = None;
+                let mut length = 0;
+                loop {
+                    let mut header = String::new();
+                    reader.read_line(&mut header).unwrap();
+                    let Some((name, value)) = header.trim_end().split_once(':') else {
+                        break;
+                    };
+                    match name.to_ascii_lowercase().as_str() {
+                        "authorization" => authorization = Some(value.trim().to_owned()),
+                        "content-length" => length = value.trim().parse().unwrap(),
+                        _ => {},
+                    }
+                }
+                let mut body = vec![0; length];
+                reader.read_exact(&mut body).unwrap();
+                let body = match body.is_empty() {
+                    true => Value::Null,
+                    false => serde_json::from_slice(&body).unwrap(),
+                };
+
+                // recorded before answering, so the client never sees a response before its request
+                sender.send(Request { method, path, authorization, body }).unwrap();
+
+                let status = match response["success"] == Value::Bool(true) {
+                    true => "200 OK",
+                    false => "400 Bad Request",
+                };
+                let response = response.to_string();
+                write!(
+                    reader.get_mut(),
+                    "HTTP/1.1 {status}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{response}",
+                    response.len()
+                )
+                .unwrap();
+            }
+        });
+
+        MockApi { url, requests }
+    }
+
+    /// Requests received so far
+    pub fn requests(&self) -> Vec<Request> {
+        self.requests.try_iter().collect()
+    }
+}
+
+/// A successful response carrying `result`
+pub fn success(result: Value) -> Value {
+    json!({ "success": true, "errors": [], "messages": [], "result": result })
+}
+
+/// A failed response, as the API sends them for invalid SQL
+pub fn failure(message: &str) -> Value {
+    json!({
+        "success": false,
+        "errors": [{ "code": 7500, "message": message }],
+        "messages": [],
+        "result": null,
+    })
+}
+
+/// The `result` of `/raw` for a single statement
+pub fn raw_rows(rows: Value) -> Value {
+    success(json!([{ "results": { "columns": [], "rows": rows }, "success": true, "meta": {} }]))
+}
@@ -0,0 +1,367 @@
+//! Renders introspected tables as diesel's `table!`, `joinable!` and
+//! `allow_tables_to_appear_in_same_query!` macros
+
+use std::{collections::HashMap, fmt::Write};
+
+use diesel_d1::drift::D1Affinity;
+
+use crate::introspect::{Column, Table};
+
+pub fn print_schema(tables: &[Table]) -> String {
+    // tables without a primary key can't be described by `table!`
+    let tables = tables
+        .iter()
+        .filter(|table| {
+            let has_primary_key = !table.primary_key().is_empty();
+            if !has_primary_key {
+                eprintln!("warning: skipping table `{}`, it has no primary key", table.name);
+            }
+            has_primary_key
+        })
+        .collect::<Vec<_>>();
+
+    let mut out = String::from("// @generated automatically by diesel-d1 print-schema\n");
+
+    for table in &tables {
+        out.push('\n');
+        write_table(&mut out, table);
+    }
+
+    let joinables = joinables(&tables);
+    if !joinables.is_empty() {
+        out.push('\n');
+        for (child, parent, column) in joinables {
+            let _ = writeln!(
+                out,
+                "diesel::joinable!({} -> {} ({}));",
+                rust_identifier(child),
+                rust_identifier(parent),
+                rust_identifier(column)
+            );
+        }
+    }
+
+    if tables.len() > 1 {
+        out.push_str("\ndiesel::allow_tables_to_appear_in_same_query!(\n");
+        for table in &tables {
+            let _ = writeln!(out, "    {},", rust_identifier(&table.name));
+        }
+        out.push_str(");\n");
+    }
+
+    out
+}
+
+fn write_table(out: &mut String, table: &Table) {
+    let primary_key = table
+        .primary_key()
+        .iter()
+        .map(|column| rust_identifier(&column.name))
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    out.push_str("diesel::table! {\n");
+    let name = rust_identifier(&table.name);
+    if name != table.name {
+        let _ = writeln!(out, "    #[sql_name = {:?}]", table.name);
+    }
+    let _ = writeln!(out, "    {name} ({primary_key}) {{");
+
+    for column in &table.columns {
+        let name = rust_identifier(&column.name);
+        if name != column.name {
+            let _ = writeln!(out, "        #[sql_name = {:?}]", column.name);
+        }
+        let _ = writeln!(out, "        {name} -> {},", column_type(column));
+    }
+
+    out.push_str("    }\n}\n");
+}
+
+/// `(child, parent, column)` for every parent a table has exactly one single-column foreign
+/// key to, which is what `joinable!` can express
+fn joinables<'a>(tables: &[&'a Table]) -> Vec<(&'a str, &'a str, &'a str)> {
+    let mut joinables = Vec::new();
+
+    for table in tables {
+        let mut keys_per_parent = HashMap::<&str, Vec<_>>::new();
+        for key in &table.foreign_keys {
+            keys_per_parent.entry(&key.parent_table).or_default().push(key);
+        }
+
+        let mut parents = keys_per_parent.into_iter().collect::<Vec<_>>();
+        parents.sort_by_key(|(parent, _)| *parent);
+
+        for (parent_name, keys) in parents {
+            let [key] = keys.as_slice() else {
+                continue;
+            };
+            let Some(parent) = tables.iter().find(|table| table.name == parent_name) else {
+                continue;
+            };
+            let [parent_key] = parent.primary_key()[..] else {
+                continue;
+            };
+            let references_primary_key = key
+                .parent_column
+                .as_ref()
+                .map_or(true, |column| *column == parent_key.name);
+
+            if parent.name != table.name && references_primary_key {
+                joinables.push((table.name.as_str(), parent.name.as_str(), key.column.as_str()));
+            }
+        }
+    }
+
+    joinables
+}
+
+fn column_type(column: &Column) -> String {
+    let sql_type = sql_type(&column.declared_type);
+    // primary keys are never NULL in practice, even when SQLite would allow it
+    if column.not_null || column.primary_key > 0 {
+        sql_type.to_owned()
+    } else {
+        format!("Nullable<{sql_type}>")
+    }
+}
+
+/// Maps a declared column type to one of the SQL types `D1Backend` supports, following
+/// SQLite's affinity rules for the types it doesn't know by name
+pub fn sql_type(declared_type: &str) -> &'static str {
+    let affinity = D1Affinity::from_declared_type(declared_type);
+    let declared_type = declared_type.to_lowercase();
+    let contains = |needle: &str| declared_type.contains(needle);
+
+    match affinity {
+        _ if contains("bool") => "Bool",
+        D1Affinity::Integer if contains("tinyint") || contains("smallint") => "SmallInt",
+        D1Affinity::Integer if contains("bigint") || contains("big int") || contains("int8") => "BigInt",
+        D1Affinity::Integer => "Integer",
+        _ if contains("datetime") || contains("timestamp") => "Timestamp",
+        _ if contains("date") => "Date",
+        _ if contains("time") => "Time",
+        D1Affinity::Text => "Text",
+        D1Affinity::Blob => "Binary",
+        _ if contains("binary") => "Binary",
+        D1Affinity::Real if contains("float") => "Float",
+        D1Affinity::Real => "Double",
+        D1Affinity::Numeric => "Numeric",
+    }
+}
+
+/// Turns a table or column name into something `table!` accepts as an identifier
+fn rust_identifier(name: &str) -> String {
+    let mut identifier = name
+        .chars()
+        .map(|char| if char.is_ascii_alphanumeric() || char == '_' { char } else { '_' })
+        .collect::<String>();
+
+    if identifier.is_empty() || identifier.starts_with(|char: char| char.is_ascii_digit()) {
+        identifier.insert(0, '_');
+    }
+    if KEYWORDS.contains(&identifier.as_str()) {
+        identifier.push('_');
+    }
+
+    identifier
+}
+
+const KEYWORDS: &[&str] = &[
+    "as", "async", "await", "break", "const", "continue", "crate", "dyn", "else", "enum", "extern", "false", "fn",
+    "for", "if", "impl", "in", "let", "loop", "match", "mod", "move", "mut", "pub", "ref", "return", "self", "Self",
+    "static", "struct", "super", "trait", "true", "type", "unsafe", "use", "where", "while", "abstract", "become",
+    "box", "do", "final", "macro", "override", "priv", "typeof", "unsized", "virtual", "yield", "try",
+];
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use diesel_d1::migrations::{D1MigrationsTable, BOOKMARKS_TABLE, CHECKPOINTS_TABLE};
+
+    use super::*;
+    use crate::{
+        introspect::{load_tables, ForeignKey},
+        source::{SchemaSource, SqliteFile},
+    };
+
+    #[test]
+    fn maps_declared_types_by_affinity() {
+        let cases = [
+            ("INTEGER", "Integer"),
+            ("int", "Integer"),
+            ("MEDIUMINT", "Integer"),
+            ("BIGINT", "BigInt"),
+            ("UNSIGNED BIG INT", "BigInt"),
+            ("INT8", "BigInt"),
+            ("TINYINT", "SmallInt"),
+            ("SMALLINT", "SmallInt"),
+            ("BOOLEAN", "Bool"),
+            ("TEXT", "Text"),
+            ("VARCHAR(255)", "Text"),
+            ("NATIVE CHARACTER(70)", "Text"),
+            ("CLOB", "Text"),
+            ("BLOB", "Binary"),
+            ("", "Binary"),
+            ("REAL", "Double"),
+            ("DOUBLE PRECISION", "Double"),
+            ("FLOAT", "Float"),
+            ("DATETIME", "Timestamp"),
+            ("TIMESTAMP", "Timestamp"),
+            ("DATE", "Date"),
+            ("TIME", "Time"),
+            ("DECIMAL(10,5)", "Numeric"),
+            ("NUMERIC", "Numeric"),
+            ("VARBINARY(16)", "Binary"),
+            // INT decides the affinity, whatever else the name contains
+            ("FLOATING POINT", "Integer"),
+        ];
+        for (declared_type, expected) in cases {
+            assert_eq!(sql_type(declared_type), expected, "{declared_type}");
+        }
+    }
+
+    #[test]
+    fn nullable_unless_not_null_or_primary_key() {
+        let column = |not_null, primary_key| Column {
+            name: "a".to_owned(),
+            declared_type: "TEXT".to_owned(),
+            not_null,
+            primary_key,
+        };
+        assert_eq!(column_type(&column(false, 0)), "Nullable<Text>");
+        assert_eq!(column_type(&column(true, 0)), "Text");
+        assert_eq!(column_type(&column(false, 1)), "Text");
+    }
+
+    #[test]
+    fn escapes_identifiers() {
+        assert_eq!(rust_identifier("users"), "users");
+        assert_eq!(rust_identifier("type"), "type_");
+        assert_eq!(rust_identifier("self"), "self_");
+        assert_eq!(rust_identifier("Self"), "Self_");
+        assert_eq!(rust_identifier("created at"), "created_at");
+        assert_eq!(rust_identifier("user-id"), "user_id");
+        assert_eq!(rust_identifier("2fa"), "_2fa");
+        assert_eq!(rust_identifier(""), "_");
+    }
+
+    fn table(name: &str, columns: &[(&str, i64)], foreign_keys: &[(&str, &str, Option<&str>)]) -> Table {
+        Table {
+            name: name.to_owned(),
+            columns: columns
+                .iter()
+                .map(|(name, primary_key)| Column {
+                    name: (*name).to_owned(),
+                    declared_type: "INTEGER".to_owned(),
+                    not_null: true,
+                    primary_key: *primary_key,
+                })
+                .collect(),
+            foreign_keys: foreign_keys
+                .iter()
+                .map(|(column, parent_table, parent_column)| ForeignKey {
+                    column: (*column).to_owned(),
+                    parent_table: (*parent_table).to_owned(),
+                    parent_column: parent_column.map(str::to_owned),
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn joinables_need_a_single_key_to_a_single_column_primary_key() {
+        let users = table("users", &[("id", 1)], &[]);
+        let pairs = table("pairs", &[("a", 1), ("b", 2)], &[]);
+        let posts = table(
+            "posts",
+            &[("id", 1), ("author_id", 0), ("editor_id", 0), ("pair_a", 0), ("parent_id", 0)],
+            &[
+                // two keys to the same parent are ambiguous for `joinable!`
+                ("author_id", "users", Some("id")),
+                ("editor_id", "users", Some("id")),
+                ("pair_a", "pairs", Some("a")),
+                // joining a table to itself needs an alias
+                ("parent_id", "posts", None),
+            ],
+        );
+        let comments = table(
+            "comments",
+            &[("id", 1), ("post_id", 0), ("user_name", 0), ("missing_id", 0)],
+            &[
+                ("post_id", "posts", None),
+                ("user_name", "users", Some("name")),
+                ("missing_id", "missing", Some("id")),
+            ],
+        );
+
+        let tables = [&users, &pairs, &posts, &comments];
+        assert_eq!(joinables(&tables), vec![("comments", "posts", "post_id")]);
+    }
+
+    #[test]
+    fn prints_sqlite_schema() {
+        let mut source = SqliteFile::open(Path::new(":memory:"), true).unwrap();
+        source
+            .execute(&[
+                "CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT NOT NULL, \"type\" VARCHAR(20), score REAL)"
+                    .to_owned(),
+                "CREATE TABLE posts (id INTEGER PRIMARY KEY, author_id INTEGER NOT NULL REFERENCES users(id), \
+                    body TEXT, published_at DATETIME, flags BLOB)"
+                    .to_owned(),
+                "CREATE TABLE \"post tags\" (post_id INTEGER REFERENCES posts, tag TEXT, PRIMARY KEY (post_id, tag))"
+                    .to_owned(),
+                "CREATE TABLE logs (message TEXT)".to_owned(),
+                D1MigrationsTable::Diesel.create_sql(),
+                D1MigrationsTable::Wrangler.create_sql(),
+                format!("CREATE TABLE {CHECKPOINTS_TABLE} (version TEXT PRIMARY KEY)"),
+                format!("CREATE TABLE {BOOKMARKS_TABLE} (version TEXT PRIMARY KEY)"),
+            ])
+            .unwrap();
+
+        let schema = print_schema(&load_tables(&mut source).unwrap());
+        assert_eq!(
+            schema,
+            r#"// @generated automatically by diesel-d1 print-schema
+
+diesel::table! {
+    #[sql_name = "post tags"]
+    post_tags (post_id, tag) {
+        post_id -> Integer,
+        tag -> Text,
+    }
+}
+
+diesel::table! {
+    posts (id) {
+        id -> Integer,
+        author_id -> Integer,
+        body -> Nullable<Text>,
+        published_at -> Nullable<Timestamp>,
+        flags -> Nullable<Binary>,
+    }
+}
+
+diesel::table! {
+    users (id) {
+        id -> Integer,
+        name -> Text,
+        #[sql_name = "type"]
+        type_ -> Nullable<Text>,
+        score -> Nullable<Double>,
+    }
+}
+
+diesel::joinable!(post_tags -> posts (post_id));
+diesel::joinable!(posts -> users (author_id));
+
+diesel::allow_tables_to_appear_in_same_query!(
+    post_tags,
+    posts,
+    users,
+);
+"#
+        );
+    }
+}
@@ -0,0 +1,304 @@
+//! Places a D1 database can be reached from
+
+use std::{
+    error::Error,
+    path::{Path, PathBuf},
+};
+
+use serde_json::Value;
+
+pub type Result<T> = std::result::Result<T, Box<dyn Error + Send + Sync>>;
+
+/// Runs SQL against a D1 database
+pub trait SchemaSource {
+    /// Runs a single statement, returning the rows as JSON values in column order
+    fn query(&mut self, sql: &str) -> Result<Vec<Vec<Value>>>;
+
+    /// Runs `statements` as one script, atomically where the source allows it
+    fn execute(&mut self, statements: &[String]) -> Result<()>;
+
+    /// Time Travel bookmark of the database's current state, `None` where Time Travel isn't
+    /// available
+    fn bookmark(&mut self) -> Result<Option<String>> {
+        Ok(None)
+    }
+}
+
+/// A plain SQLite file, like the ones `wrangler dev` keeps in `.wrangler/state`
+pub struct SqliteFile {
+    connection: rusqlite::Connection,
+}
+
+impl SqliteFile {
+    pub fn open(path: &Path, writable: bool) -> Result<Self> {
+        let flags = match writable {
+            true => rusqlite::OpenFlags::SQLITE_OPEN_READ_WRITE,
+            false => rusqlite::OpenFlags::SQLITE_OPEN_READ_ONLY,
+        };
+        let connection = rusqlite::Connection::open_with_flags(path, flags)
+            .map_err(|err| format!("unable to open {}: {err}", path.display()))?;
+        Ok(SqliteFile { connection })
+    }
+
+    /// Finds the database Miniflare stores under a Wrangler state directory
+    ///
+    /// Miniflare names the files after a hash of the database id, so this only works when the
+    /// state holds a single database. Pass the file itself otherwise.
+    pub fn find_in_wrangler_state(state_dir: &Path) -> Result<PathBuf> {
+        let dir = state_dir.join("v3").join("d1").join("miniflare-D1DatabaseObject");
+        let mut databases = std::fs::read_dir(&dir)
+            .map_err(|err| format!("unable to read {}: {err}", dir.display()))?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.extension().is_some_and(|extension| extension == "sqlite"))
+            .collect::<Vec<_>>();
+
+        match databases.len() {
+            0 => Err(format!("no local D1 database found in {}", dir.display()).into()),
+            1 => Ok(databases.remove(0)),
+            _ => {
+                databases.sort();
+                let names = databases
+                    .iter()
+                    .map(|path| format!("  {}", path.display()))
+                    .collect::<Vec<_>>()
+                    .join("\n");
+                Err(format!("more than one local D1 database, pick one with --database:\n{names}").into())
+            },
+        }
+    }
+}
+
+impl SchemaSource for SqliteFile {
+    fn query(&mut self, sql: &str) -> Result<Vec<Vec<Value>>> {
+        let mut statement = self.connection.prepare(sql)?;
+        let column_count = statement.column_count();
+        let rows = statement.query_map([], |row| {
+            (0..column_count)
+                .map(|index| {
+                    Ok(match row.get_ref(index)? {
+                        rusqlite::types::ValueRef::Null => Value::Null,
+                        rusqlite::types::ValueRef::Integer(value) => Value::from(value),
+                        rusqlite::types::ValueRef::Real(value) => Value::from(value),
+                        rusqlite::types::ValueRef::Text(value) => Value::from(String::from_utf8_lossy(value)),
+                        rusqlite::types::ValueRef::Blob(value) => Value::from(value.to_vec()),
+                    })
+                })
+                .collect()
+        })?;
+
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+
+    fn execute(&mut self, statements: &[String]) -> Result<()> {
+        // atomic like the batches D1 runs migrations in
+        let transaction = self.connection.transaction()?;
+        for statement in statements {
+            transaction.execute_batch(statement)?;
+        }
+        Ok(transaction.commit()?)
+    }
+}
+
+/// Base URL of the Cloudflare REST API
+pub const DEFAULT_API_URL: &str = "https://api.cloudflare.com/client/v4";
+
+/// A remote database, queried through the Cloudflare REST API
+pub struct HttpApi {
+    /// `.../accounts/{account_id}/d1/database/{database_id}`
+    url: String,
+    api_token: String,
+}
+
+/// Answer of a Time Travel restore
+pub struct Restored {
+    pub bookmark: String,
+    /// Bookmark of the state before the restore, to undo it
+    pub previous_bookmark: Option<String>,
+}
+
+impl HttpApi {
+    /// `api_url` is [`DEFAULT_API_URL`] unless the requests should go to e.g. a mock server
+    pub fn new(api_url: &str, account_id: &str, database_id: &str, api_token: &str) -> Self {
+        HttpApi {
+            url: format!(
+                "{}/accounts/{account_id}/d1/database/{database_id}",
+                api_url.trim_end_matches('/')
+            ),
+            api_token: api_token.to_owned(),
+        }
+    }
+
+    /// Restores the database to `bookmark` with Time Travel
+    pub fn restore(&self, bookmark: &str) -> Result<Restored> {
+        let request = ureq::post(&format!("{}/time_travel/restore", self.url)).query("bookmark", bookmark);
+        let result = self.send(request, None)?;
+
+        Ok(Restored {
+            bookmark: result["bookmark"]
+                .as_str()
+                .ok_or("unexpected D1 API response")?
+                .to_owned(),
+            previous_bookmark: result["previous_bookmark"].as_str().map(str::to_owned),
+        })
+    }
+
+    /// Sends `request` and returns the `result` of the response
+    fn send(&self, request: ureq::Request, body: Option<Value>) -> Result<Value> {
+        let request = request.set("Authorization", &format!("Bearer {}", self.api_token));
+        let response = match body {
+            Some(body) => request.send_json(body),
+            None => request.call(),
+        };
+
+        // API errors still come with a JSON body explaining them
+        let mut body: Value = match response {
+            Ok(response) => response.into_json()?,
+            Err(ureq::Error::Status(_, response)) => response.into_json()?,
+            Err(err) => return Err(err.into()),
+        };
+
+        if body["success"] != Value::Bool(true) {
+            let errors = body["errors"]
+                .as_array()
+                .map(|errors| {
+                    errors
+                        .iter()
+                        .map(|error| error["message"].as_str().unwrap_or_default().to_owned())
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                })
+                .unwrap_or_default();
+            return Err(format!("D1 API request failed: {errors}").into());
+        }
+        Ok(body["result"].take())
+    }
+}
+
+impl SchemaSource for HttpApi {
+    fn query(&mut self, sql: &str) -> Result<Vec<Vec<Value>>> {
+        let result = self.send(
+            ureq::post(&format!("{}/raw", self.url)),
+            Some(serde_json::json!({ "sql": sql })),
+        )?;
+
+        // `/raw` returns rows as arrays, which keeps the column order intact
+        let rows = result[0]["results"]["rows"]
+            .as_array()
+            .ok_or("unexpected D1 API response")?
+            .iter()
+            .map(|row| row.as_array().cloned().unwrap_or_default())
+            .collect();
+        Ok(rows)
+    }
+
+    fn execute(&mut self, statements: &[String]) -> Result<()> {
+        // D1 stops at the first failing statement, but doesn't document rolling back the ones
+        // before it
+        let sql = statements.join(";\n");
+        self.send(
+            ureq::post(&format!("{}/query", self.url)),
+            Some(serde_json::json!({ "sql": sql })),
+        )?;
+        Ok(())
+    }
+
+    fn bookmark(&mut self) -> Result<Option<String>> {
+        let result = self.send(ureq::get(&format!("{}/time_travel/bookmark", self.url)), None)?;
+        let bookmark = result["bookmark"].as_str().ok_or("unexpected D1 API response")?;
+        Ok(Some(bookmark.to_owned()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::mock_api::{failure, raw_rows, success, MockApi};
+
+    const DATABASE_PATH: &str = "/client/v4/accounts/account/d1/database/database";
+
+    fn api(mock: &MockApi) -> HttpApi {
+        HttpApi::new(&format!("{}/", mock.url), "account", "database", "token")
+    }
+
+    #[test]
+    fn queries_through_raw() {
+        let mock = MockApi::start(vec![raw_rows(json!([[1, "a", null], [2, "b", 1.5]]))]);
+
+        let rows = api(&mock).query("SELECT id, name, score FROM users").unwrap();
+        assert_eq!(rows, [vec![json!(1), json!("a"), Value::Null], vec![json!(2), json!("b"), json!(1.5)]]);
+
+        let requests = mock.requests();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].method, "POST");
+        assert_eq!(requests[0].path, format!("{DATABASE_PATH}/raw"));
+        assert_eq!(requests[0].authorization.as_deref(), Some("Bearer token"));
+        assert_eq!(requests[0].body, json!({ "sql": "SELECT id, name, score FROM users" }));
+    }
+
+    #[test]
+    fn executes_statements_in_one_query() {
+        let mock = MockApi::start(vec![success(json!([]))]);
+
+        api(&mock)
+            .execute(&["CREATE TABLE a (id INTEGER)".to_owned(), "INSERT INTO a VALUES (1)".to_owned()])
+            .unwrap();
+
+        let requests = mock.requests();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].method, "POST");
+        assert_eq!(requests[0].path, format!("{DATABASE_PATH}/query"));
+        assert_eq!(
+            requests[0].body,
+            json!({ "sql": "CREATE TABLE a (id INTEGER);\nINSERT INTO a VALUES (1)" })
+        );
+    }
+
+    #[test]
+    fn reports_api_errors() {
+        let mock = MockApi::start(vec![failure("no such table: a: SQLITE_ERROR")]);
+
+        let err = api(&mock).execute(&["INSERT INTO a VALUES (1)".to_owned()]).unwrap_err();
+        assert_eq!(err.to_string(), "D1 API request failed: no such table: a: SQLITE_ERROR");
+    }
+
+    #[test]
+    fn takes_bookmarks() {
+        let mock = MockApi::start(vec![
+            success(json!({ "bookmark": "00000085-0000024c-00004c6d-8e61117bf38d7adb71b934ebbf891683" })),
+            success(json!({})),
+        ]);
+        let mut api = api(&mock);
+
+        assert_eq!(
+            api.bookmark().unwrap().as_deref(),
+            Some("00000085-0000024c-00004c6d-8e61117bf38d7adb71b934ebbf891683")
+        );
+        assert_eq!(api.bookmark().unwrap_err().to_string(), "unexpected D1 API response");
+
+        let requests = mock.requests();
+        assert_eq!(requests[0].method, "GET");
+        assert_eq!(requests[0].path, format!("{DATABASE_PATH}/time_travel/bookmark"));
+        assert_eq!(requests[0].body, Value::Null);
+    }
+
+    #[test]
+    fn restores_bookmarks() {
+        let mock = MockApi::start(vec![
+            success(json!({ "bookmark": "a", "previous_bookmark": "b", "message": "Restored" })),
+            success(json!({ "bookmark": "a" })),
+        ]);
+        let api = api(&mock);
+
+        let restored = api.restore("a").unwrap();
+        assert_eq!(restored.bookmark, "a");
+        assert_eq!(restored.previous_bookmark.as_deref(), Some("b"));
+        assert_eq!(api.restore("a").unwrap().previous_bookmark, None);
+
+        let requests = mock.requests();
+        assert_eq!(requests[0].method, "POST");
+        assert_eq!(requests[0].path, format!("{DATABASE_PATH}/time_travel/restore?bookmark=a"));
+        assert_eq!(requests[0].authorization.as_deref(), Some("Bearer token"));
+    }
+}
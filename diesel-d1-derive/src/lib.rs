@@ -0,0 +1,367 @@
+//! Derive macros for `diesel-d1`, re-exported from there behind the `derive` feature
+
+use proc_macro::TokenStream;
+use proc_macro2::{Literal, TokenStream as TokenStream2};
+use quote::quote;
+use syn::{parse_macro_input, spanned::Spanned, Data, DeriveInput, Error, Expr, ExprLit, Fields, Lit, LitInt, LitStr};
+
+/// Maps a fieldless enum to a `Text` or `Integer` column of `D1Backend`
+///
+/// The container attribute picks the storage: `#[d1(text)]` stores the variant name, or
+/// `#[d1(rename = "...")]` when set on the variant; `#[d1(integer)]` stores the variant's
+/// discriminant, the value `as i32` gives, or `#[d1(value = ...)]` when set on the variant.
+/// `value` only changes what that variant stores, the variants after it keep Rust's
+/// numbering. Reading a value that doesn't match any variant fails with a deserialization
+/// error naming the column.
+///
+/// Only `ToSql`/`FromSql` are generated, combine it with diesel's `AsExpression` and
+/// `FromSqlRow` derives to use the enum in queries:
+///
+/// ```ignore
+/// #[derive(Debug, AsExpression, FromSqlRow, D1Enum)]
+/// #[diesel(sql_type = diesel::sql_types::Text)]
+/// #[d1(text)]
+/// enum Status {
+///     Active,
+///     #[d1(rename = "off")]
+///     Inactive,
+/// }
+/// ```
+#[proc_macro_derive(D1Enum, attributes(d1))]
+pub fn derive_d1_enum(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    d1_enum(input)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+enum Storage {
+    Text,
+    Integer,
+}
+
+fn d1_enum(input: DeriveInput) -> syn::Result<TokenStream2> {
+    let Data::Enum(data) = &input.data else {
+        return Err(Error::new(input.span(), "D1Enum can only be derived for enums"));
+    };
+
+    let mut storage = None;
+    for attr in input.attrs.iter().filter(|attr| attr.path().is_ident("d1")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("text") {
+                storage = Some(Storage::Text);
+                Ok(())
+            } else if meta.path.is_ident("integer") {
+                storage = Some(Storage::Integer);
+                Ok(())
+            } else {
+                Err(meta.error("expected `text` or `integer`"))
+            }
+        })?;
+    }
+    let storage = storage.ok_or_else(|| {
+        Error::new(input.ident.span(), "missing `#[d1(text)]` or `#[d1(integer)]`")
+    })?;
+
+    let mut idents = Vec::new();
+    let mut values: Vec<Literal> = Vec::new();
+    // `None` after a discriminant that isn't a literal, which can't be counted on from
+    let mut next_discriminant = Some(0i32);
+
+    for variant in &data.variants {
+        if !matches!(variant.fields, Fields::Unit) {
+            return Err(Error::new(variant.span(), "D1Enum variants can't have fields"));
+        }
+
+        let mut rename = None;
+        let mut value = None;
+        for attr in variant.attrs.iter().filter(|attr| attr.path().is_ident("d1")) {
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("rename") {
+                    rename = Some(meta.value()?.parse::<LitStr>()?.value());
+                    Ok(())
+                } else if meta.path.is_ident("value") {
+                    value = Some(meta.value()?.parse::<LitInt>()?.base10_parse::<i32>()?);
+                    Ok(())
+                } else {
+                    Err(meta.error("expected `rename` or `value`"))
+                }
+            })?;
+        }
+
+        let stored = match storage {
+            Storage::Text => {
+                if value.is_some() {
+                    return Err(Error::new(variant.span(), "`value` is only used with `#[d1(integer)]`"));
+                }
+                Literal::string(&rename.unwrap_or_else(|| variant.ident.to_string()))
+            },
+            Storage::Integer => {
+                if rename.is_some() {
+                    return Err(Error::new(variant.span(), "`rename` is only used with `#[d1(text)]`"));
+                }
+                // counted from the real discriminants only, so the values match `as` casts
+                let discriminant = match &variant.discriminant {
+                    Some((_, expr)) => match integer_literal(expr) {
+                        Ok(discriminant) => Some(discriminant),
+                        Err(_) if value.is_some() => None,
+                        Err(err) => return Err(err),
+                    },
+                    None => next_discriminant,
+                };
+                next_discriminant = discriminant.map(|discriminant| discriminant.wrapping_add(1));
+                let stored = value.or(discriminant).ok_or_else(|| {
+                    Error::new(
+                        variant.span(),
+                        format!(
+                            "the discriminant of `{}` follows one D1Enum can't read, use `#[d1(value = ...)]`",
+                            variant.ident
+                        ),
+                    )
+                })?;
+                Literal::i32_suffixed(stored)
+            },
+        };
+
+        // a repeated value would only warn about an unreachable pattern and read back as the
+        // first of the variants
+        if let Some(index) = values.iter().position(|other| other.to_string() == stored.to_string()) {
+            return Err(Error::new(
+                variant.span(),
+                format!("`{}` stores the same value as `{}`", variant.ident, idents[index]),
+            ));
+        }
+
+        idents.push(&variant.ident);
+        values.push(stored);
+    }
+
+    let name = &input.ident;
+    let name_str = name.to_string();
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    let bound_values = values.iter().map(|value| match storage {
+        Storage::Text => quote!(#value),
+        Storage::Integer => quote!(&#value),
+    });
+    let (sql_type, read, expected) = match storage {
+        Storage::Text => (
+            quote!(::diesel::sql_types::Text),
+            quote!(<::std::string::String as ::diesel::deserialize::FromSql<::diesel::sql_types::Text, ::diesel_d1::backend::D1Backend>>::from_sql(value)?),
+            quote!(stored.as_str()),
+        ),
+        Storage::Integer => (
+            quote!(::diesel::sql_types::Integer),
+            quote!(<i32 as ::diesel::deserialize::FromSql<::diesel::sql_types::Integer, ::diesel_d1::backend::D1Backend>>::from_sql(value)?),
+            quote!(stored),
+        ),
+    };
+
+    Ok(quote! {
+        impl #impl_generics ::diesel::serialize::ToSql<#sql_type, ::diesel_d1::backend::D1Backend> for #name #ty_generics #where_clause {
+            fn to_sql<'b>(&'b self, out: &mut ::diesel::serialize::Output<'b, '_, ::diesel_d1::backend::D1Backend>) -> ::diesel::serialize::Result {
+                match self {
+                    #(Self::#idents => ::diesel::serialize::ToSql::<#sql_type, ::diesel_d1::backend::D1Backend>::to_sql(#bound_values, out),)*
+                }
+            }
+        }
+
+        impl #impl_generics ::diesel::deserialize::FromSql<#sql_type, ::diesel_d1::backend::D1Backend> for #name #ty_generics #where_clause {
+            fn from_sql(value: ::diesel_d1::D1Value) -> ::diesel::deserialize::Result<Self> {
+                let column = value.column().to_owned();
+                let stored = #read;
+                match #expected {
+                    #(#values => ::std::result::Result::Ok(Self::#idents),)*
+                    _ => ::std::result::Result::Err(::std::format!(
+                        "unknown value `{}` for enum `{}` in column `{}`",
+                        stored,
+                        #name_str,
+                        column,
+                    ).into()),
+                }
+            }
+        }
+    })
+}
+
+fn integer_literal(expr: &Expr) -> syn::Result<i32> {
+    match expr {
+        Expr::Lit(ExprLit { lit: Lit::Int(int), .. }) => int.base10_parse(),
+        Expr::Unary(syn::ExprUnary { op: syn::UnOp::Neg(_), expr, .. }) => Ok(-integer_literal(expr)?),
+        _ => Err(Error::new(
+            expr.span(),
+            "D1Enum only understands integer literal discriminants, use `#[d1(value = ...)]` instead",
+        )),
+    }
+}
+
+/// Embeds a Wrangler migrations directory into the binary as a `WranglerMigrations` source
+///
+/// The path is relative to the crate's `Cargo.toml` and defaults to `migrations`, the
+/// directory `wrangler d1 migrations create` writes to:
+///
+/// ```ignore
+/// let migrations = diesel_d1::embed_wrangler_migrations!("migrations");
+/// conn.set_migrations_table(D1MigrationsTable::Wrangler);
+/// conn.run_pending_migrations(migrations).await?;
+/// ```
+#[proc_macro]
+pub fn embed_wrangler_migrations(input: TokenStream) -> TokenStream {
+    let dir = if input.is_empty() {
+        LitStr::new("migrations", proc_macro2::Span::call_site())
+    } else {
+        parse_macro_input!(input as LitStr)
+    };
+    wrangler_migrations(&dir)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+fn wrangler_migrations(dir: &LitStr) -> syn::Result<TokenStream2> {
+    let manifest_dir = std::env::var("CARGO_MANIFEST_DIR")
+        .map_err(|_| Error::new(dir.span(), "CARGO_MANIFEST_DIR is not set"))?;
+    let path = std::path::Path::new(&manifest_dir).join(dir.value());
+
+    let entries = std::fs::read_dir(&path)
+        .map_err(|err| Error::new(dir.span(), format!("unable to read {}: {err}", path.display())))?;
+    let mut files = Vec::new();
+    for entry in entries {
+        let file = entry
+            .map_err(|err| Error::new(dir.span(), format!("unable to read {}: {err}", path.display())))?
+            .path();
+        if file.extension().is_some_and(|extension| extension == "sql") && file.is_file() {
+            files.push(file);
+        }
+    }
+    files.sort();
+
+    let migrations = files.iter().map(|file| {
+        let name = file.file_name().unwrap_or_default().to_string_lossy().into_owned();
+        let file = file.to_string_lossy().into_owned();
+        // include_str! makes cargo rebuild when a migration changes
+        quote!((#name, ::std::include_str!(#file)))
+    });
+
+    Ok(quote! {
+        ::diesel_d1::migrations::WranglerMigrations::from_static(&[#(#migrations),*])
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use syn::parse_quote;
+
+    use super::*;
+
+    fn error(input: DeriveInput) -> String {
+        d1_enum(input).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn text_values() {
+        let expanded = d1_enum(parse_quote! {
+            #[d1(text)]
+            enum Status {
+                Active,
+                #[d1(rename = "off")]
+                Inactive,
+            }
+        })
+        .unwrap()
+        .to_string();
+
+        assert!(expanded.contains(r#"Self :: Active => :: diesel :: serialize :: ToSql"#));
+        assert!(expanded.contains(r#""Active" => :: std :: result :: Result :: Ok (Self :: Active)"#));
+        assert!(expanded.contains(r#""off" => :: std :: result :: Result :: Ok (Self :: Inactive)"#));
+    }
+
+    #[test]
+    fn integer_values_follow_discriminants() {
+        let expanded = d1_enum(parse_quote! {
+            #[d1(integer)]
+            enum Priority {
+                Low = -1,
+                Normal,
+                #[d1(value = 10)]
+                High,
+                Urgent,
+            }
+        })
+        .unwrap()
+        .to_string();
+
+        // `value` doesn't move the numbering, `Urgent as i32` is still 2
+        for (value, variant) in [("- 1i32", "Low"), ("0i32", "Normal"), ("10i32", "High"), ("2i32", "Urgent")] {
+            let arm = format!("{value} => :: std :: result :: Result :: Ok (Self :: {variant})");
+            assert!(expanded.contains(&arm), "missing `{arm}` in {expanded}");
+        }
+    }
+
+    #[test]
+    fn values_override_non_literal_discriminants() {
+        let expanded = d1_enum(parse_quote! {
+            #[d1(integer)]
+            enum Flags {
+                #[d1(value = 4)]
+                Read = 1 << 2,
+                Write = 8,
+                Execute,
+            }
+        })
+        .unwrap()
+        .to_string();
+
+        for (value, variant) in [("4i32", "Read"), ("8i32", "Write"), ("9i32", "Execute")] {
+            let arm = format!("{value} => :: std :: result :: Result :: Ok (Self :: {variant})");
+            assert!(expanded.contains(&arm), "missing `{arm}` in {expanded}");
+        }
+    }
+
+    #[test]
+    fn rejects_duplicate_values() {
+        let renamed = error(parse_quote! {
+            #[d1(text)]
+            enum Status {
+                Active,
+                #[d1(rename = "Active")]
+                Enabled,
+            }
+        });
+        assert_eq!(renamed, "`Enabled` stores the same value as `Active`");
+
+        let numbered = error(parse_quote! {
+            #[d1(integer)]
+            enum Priority {
+                Low,
+                Normal,
+                #[d1(value = 1)]
+                High,
+            }
+        });
+        assert_eq!(numbered, "`High` stores the same value as `Normal`");
+    }
+
+    #[test]
+    fn rejects_invalid_input() {
+        let cases: [(DeriveInput, &str); 7] = [
+            (parse_quote!(#[d1(text)] struct Status;), "D1Enum can only be derived for enums"),
+            (parse_quote!(enum Status { Active }), "missing `#[d1(text)]` or `#[d1(integer)]`"),
+            (parse_quote!(#[d1(json)] enum Status { Active }), "expected `text` or `integer`"),
+            (parse_quote!(#[d1(text)] enum Status { Active(bool) }), "D1Enum variants can't have fields"),
+            (
+                parse_quote!(#[d1(text)] enum Status { #[d1(value = 1)] Active }),
+                "`value` is only used with `#[d1(integer)]`",
+            ),
+            (
+                parse_quote!(#[d1(integer)] enum Status { Active = 1 << 2 }),
+                "D1Enum only understands integer literal discriminants, use `#[d1(value = ...)]` instead",
+            ),
+            (
+                parse_quote!(#[d1(integer)] enum Status { #[d1(value = 4)] Active = 1 << 2, Inactive }),
+                "the discriminant of `Inactive` follows one D1Enum can't read, use `#[d1(value = ...)]`",
+            ),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(error(input), expected);
+        }
+    }
+}
@@ -0,0 +1,6 @@
+// the error cases are unit tests of the derive, trybuild would rebuild every dependency
+// when switching between passing and failing cases
+#[test]
+fn d1_enum() {
+    trybuild::TestCases::new().pass("tests/d1_enum/*.rs");
+}
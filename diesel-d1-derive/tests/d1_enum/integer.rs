@@ -0,0 +1,34 @@
+use diesel::{debug_query, prelude::*, sql_types::Integer, AsExpression, FromSqlRow};
+use diesel_d1::{backend::D1Backend, D1Enum};
+
+table! {
+    tasks (id) {
+        id -> Integer,
+        priority -> Integer,
+    }
+}
+
+#[derive(Debug, Clone, Copy, AsExpression, FromSqlRow, D1Enum)]
+#[diesel(sql_type = Integer)]
+#[d1(integer)]
+enum Priority {
+    Low = -1,
+    Normal,
+    #[d1(value = 10)]
+    High,
+    Urgent,
+}
+
+fn main() {
+    let query = tasks::table.filter(tasks::priority.eq(Priority::High));
+    assert_eq!(
+        debug_query::<D1Backend, _>(&query).to_string(),
+        "SELECT `tasks`.`id`, `tasks`.`priority` FROM `tasks` \
+        WHERE (`tasks`.`priority` = ?) -- binds: [High]",
+    );
+    assert_eq!(Priority::Normal as i32, 0);
+    assert_eq!(Priority::Low as i32, -1);
+    // `value` only changes what `High` stores, the numbering goes on from its discriminant
+    assert_eq!(Priority::High as i32, 1);
+    assert_eq!(Priority::Urgent as i32, 2);
+}
@@ -0,0 +1,27 @@
+use diesel::{debug_query, prelude::*, sql_types::Text, AsExpression, FromSqlRow};
+use diesel_d1::{backend::D1Backend, D1Enum};
+
+table! {
+    accounts (id) {
+        id -> Integer,
+        status -> Text,
+    }
+}
+
+#[derive(Debug, Clone, Copy, AsExpression, FromSqlRow, D1Enum)]
+#[diesel(sql_type = Text)]
+#[d1(text)]
+enum Status {
+    Active,
+    #[d1(rename = "off")]
+    Inactive,
+}
+
+fn main() {
+    let query = accounts::table.filter(accounts::status.eq_any([Status::Active, Status::Inactive]));
+    assert_eq!(
+        debug_query::<D1Backend, _>(&query).to_string(),
+        "SELECT `accounts`.`id`, `accounts`.`status` FROM `accounts` \
+        WHERE (`accounts`.`status` IN (?, ?)) -- binds: [Active, Inactive]",
+    );
+}
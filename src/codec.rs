@@ -0,0 +1,405 @@
+//! Column types that transparently encode their contents before they're stored as BLOBs
+//!
+//! The wrappers map to `sql_types::Binary` and can be nested, e.g.
+//! `Encrypted<Compressed<Vec<u8>>, MyKeys>` compresses before encrypting. Everything runs in
+//! plain Rust, so no WebCrypto or native libraries are needed.
+
+use std::{borrow::Cow, marker::PhantomData};
+
+use diesel::{
+    deserialize::{self, FromSql, FromSqlRow},
+    expression::AsExpression,
+    serialize::{self, IsNull, Output, ToSql},
+    sql_types,
+};
+
+use crate::{backend::D1Backend, types::blob_value, value::D1Value};
+
+/// Like `deserialize::Result`, diesel's `serialize::Result` only covers `IsNull`
+type SerializeResult<T> = Result<T, Box<dyn std::error::Error + Send + Sync>>;
+
+/// Rust types that can be turned into the raw bytes a codec works on
+pub trait BinaryData: Sized {
+    fn to_bytes(&self) -> SerializeResult<Cow<'_, [u8]>>;
+
+    fn from_bytes(bytes: Vec<u8>) -> deserialize::Result<Self>;
+}
+
+impl BinaryData for Vec<u8> {
+    fn to_bytes(&self) -> SerializeResult<Cow<'_, [u8]>> {
+        Ok(Cow::Borrowed(self))
+    }
+
+    fn from_bytes(bytes: Vec<u8>) -> deserialize::Result<Self> {
+        Ok(bytes)
+    }
+}
+
+impl BinaryData for String {
+    fn to_bytes(&self) -> SerializeResult<Cow<'_, [u8]>> {
+        Ok(Cow::Borrowed(self.as_bytes()))
+    }
+
+    fn from_bytes(bytes: Vec<u8>) -> deserialize::Result<Self> {
+        Ok(String::from_utf8(bytes)?)
+    }
+}
+
+// ------
+
+// Compression
+
+#[cfg(feature = "compression")]
+pub use compression::{Compressed, CompressionCodec, Deflate, Zstd, MAX_DECOMPRESSED_LEN};
+
+#[cfg(feature = "compression")]
+mod compression {
+    use super::*;
+
+    /// The most bytes a compressed value is allowed to expand to when it's read back
+    ///
+    /// D1 rows are capped at 2 MB, this leaves room for highly compressible values while
+    /// keeping a crafted one from exhausting the 128 MB a Worker gets.
+    pub const MAX_DECOMPRESSED_LEN: usize = 32 * 1024 * 1024;
+
+    /// A compression algorithm usable with [`Compressed`]
+    pub trait CompressionCodec {
+        fn compress(bytes: &[u8]) -> SerializeResult<Vec<u8>>;
+
+        /// Fails rather than returning more than [`MAX_DECOMPRESSED_LEN`] bytes
+        fn decompress(bytes: &[u8]) -> deserialize::Result<Vec<u8>>;
+    }
+
+    /// Raw DEFLATE (RFC 1951)
+    #[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+    pub struct Deflate;
+
+    impl CompressionCodec for Deflate {
+        fn compress(bytes: &[u8]) -> SerializeResult<Vec<u8>> {
+            Ok(miniz_oxide::deflate::compress_to_vec(bytes, 6))
+        }
+
+        fn decompress(bytes: &[u8]) -> deserialize::Result<Vec<u8>> {
+            miniz_oxide::inflate::decompress_to_vec_with_limit(bytes, MAX_DECOMPRESSED_LEN).map_err(|err| {
+                match err.status {
+                    miniz_oxide::inflate::TINFLStatus::HasMoreOutput => too_large(),
+                    status => format!("invalid deflate data: {status:?}").into(),
+                }
+            })
+        }
+    }
+
+    /// Zstandard frames, compressed at the fastest level
+    #[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+    pub struct Zstd;
+
+    impl CompressionCodec for Zstd {
+        fn compress(bytes: &[u8]) -> SerializeResult<Vec<u8>> {
+            Ok(ruzstd::encoding::compress_to_vec(bytes, ruzstd::encoding::CompressionLevel::Fastest))
+        }
+
+        fn decompress(bytes: &[u8]) -> deserialize::Result<Vec<u8>> {
+            use std::io::Read;
+
+            let decoder = ruzstd::decoding::StreamingDecoder::new(bytes)
+                .map_err(|err| format!("invalid zstd data: {err}"))?;
+            let mut decompressed = Vec::new();
+            decoder
+                .take(MAX_DECOMPRESSED_LEN as u64 + 1)
+                .read_to_end(&mut decompressed)?;
+            if decompressed.len() > MAX_DECOMPRESSED_LEN {
+                return Err(too_large());
+            }
+            Ok(decompressed)
+        }
+    }
+
+    fn too_large() -> Box<dyn std::error::Error + Send + Sync> {
+        format!("compressed value expands to more than {MAX_DECOMPRESSED_LEN} bytes").into()
+    }
+
+    /// Stores `T` compressed with `C` in a `Binary` column
+    #[derive(Debug, Clone, PartialEq, Eq, AsExpression, FromSqlRow)]
+    #[diesel(sql_type = sql_types::Binary)]
+    pub struct Compressed<T, C = Deflate>(pub T, PhantomData<C>);
+
+    impl<T, C> Compressed<T, C> {
+        pub fn new(value: T) -> Self {
+            Compressed(value, PhantomData)
+        }
+
+        pub fn into_inner(self) -> T {
+            self.0
+        }
+    }
+
+    impl<T: BinaryData, C: CompressionCodec> BinaryData for Compressed<T, C> {
+        fn to_bytes(&self) -> SerializeResult<Cow<'_, [u8]>> {
+            let bytes = self.0.to_bytes()?;
+            C::compress(&bytes).map(Cow::Owned)
+        }
+
+        fn from_bytes(bytes: Vec<u8>) -> deserialize::Result<Self> {
+            let decompressed = C::decompress(&bytes)?;
+            T::from_bytes(decompressed).map(Compressed::new)
+        }
+    }
+
+    impl<T: BinaryData, C: CompressionCodec> FromSql<sql_types::Binary, D1Backend> for Compressed<T, C> {
+        fn from_sql(value: D1Value) -> deserialize::Result<Self> {
+            BinaryData::from_bytes(value.read_blob()?)
+        }
+    }
+
+    impl<T, C> ToSql<sql_types::Binary, D1Backend> for Compressed<T, C>
+    where
+        T: BinaryData + std::fmt::Debug,
+        C: CompressionCodec + std::fmt::Debug,
+    {
+        fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, D1Backend>) -> serialize::Result {
+            out.set_value(blob_value(&self.to_bytes()?));
+            Ok(IsNull::No)
+        }
+    }
+}
+
+// ------
+
+// Encryption
+
+#[cfg(feature = "encryption")]
+pub use encryption::{Encrypted, KeyProvider};
+
+#[cfg(feature = "encryption")]
+mod encryption {
+    use aes_gcm::{
+        aead::{Aead, KeyInit},
+        Aes256Gcm, Key, Nonce,
+    };
+
+    use super::*;
+
+    const NONCE_LEN: usize = 12;
+
+    /// Supplies the key used by [`Encrypted`] columns
+    ///
+    /// Implement it on a marker type, so every column type names its key once:
+    ///
+    /// ```ignore
+    /// struct UserDataKey;
+    ///
+    /// impl KeyProvider for UserDataKey {
+    ///     fn key() -> [u8; 32] {
+    ///         *USER_DATA_KEY
+    ///     }
+    /// }
+    /// ```
+    pub trait KeyProvider {
+        /// AES-256 key
+        fn key() -> [u8; 32];
+
+        /// A fresh nonce for every value that gets encrypted, defaults to the platform RNG
+        /// (`crypto.getRandomValues` on Workers)
+        fn nonce() -> SerializeResult<[u8; NONCE_LEN]> {
+            let mut nonce = [0; NONCE_LEN];
+            getrandom::getrandom(&mut nonce).map_err(|err| format!("unable to generate nonce: {err}"))?;
+            Ok(nonce)
+        }
+    }
+
+    /// Stores `T` encrypted with AES-256-GCM in a `Binary` column, as the nonce followed by the
+    /// ciphertext and tag
+    ///
+    /// Its `Debug` output leaves the value out, so it doesn't end up in logs.
+    #[derive(Clone, PartialEq, Eq, AsExpression, FromSqlRow)]
+    #[diesel(sql_type = sql_types::Binary)]
+    pub struct Encrypted<T, K>(pub T, PhantomData<K>);
+
+    impl<T, K> Encrypted<T, K> {
+        pub fn new(value: T) -> Self {
+            Encrypted(value, PhantomData)
+        }
+
+        pub fn into_inner(self) -> T {
+            self.0
+        }
+    }
+
+    impl<T, K> std::fmt::Debug for Encrypted<T, K> {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            f.write_str("Encrypted(<redacted>)")
+        }
+    }
+
+    fn cipher<K: KeyProvider>() -> Aes256Gcm {
+        Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&K::key()))
+    }
+
+    impl<T: BinaryData, K: KeyProvider> BinaryData for Encrypted<T, K> {
+        fn to_bytes(&self) -> SerializeResult<Cow<'_, [u8]>> {
+            let plaintext = self.0.to_bytes()?;
+            let nonce = K::nonce()?;
+            let ciphertext = cipher::<K>()
+                .encrypt(Nonce::from_slice(&nonce), plaintext.as_ref())
+                .map_err(|err| format!("unable to encrypt value: {err}"))?;
+
+            let mut bytes = Vec::with_capacity(NONCE_LEN + ciphertext.len());
+            bytes.extend_from_slice(&nonce);
+            bytes.extend_from_slice(&ciphertext);
+            Ok(Cow::Owned(bytes))
+        }
+
+        fn from_bytes(bytes: Vec<u8>) -> deserialize::Result<Self> {
+            if bytes.len() < NONCE_LEN {
+                return Err("encrypted value is shorter than its nonce".into());
+            }
+
+            let (nonce, ciphertext) = bytes.split_at(NONCE_LEN);
+            let plaintext = cipher::<K>()
+                .decrypt(Nonce::from_slice(nonce), ciphertext)
+                .map_err(|err| format!("unable to decrypt value: {err}"))?;
+            T::from_bytes(plaintext).map(Encrypted::new)
+        }
+    }
+
+    impl<T: BinaryData, K: KeyProvider> FromSql<sql_types::Binary, D1Backend> for Encrypted<T, K> {
+        fn from_sql(value: D1Value) -> deserialize::Result<Self> {
+            BinaryData::from_bytes(value.read_blob()?)
+        }
+    }
+
+    impl<T: BinaryData, K: KeyProvider> ToSql<sql_types::Binary, D1Backend> for Encrypted<T, K> {
+        fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, D1Backend>) -> serialize::Result {
+            out.set_value(blob_value(&self.to_bytes()?));
+            Ok(IsNull::No)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(feature = "compression")]
+    mod compression {
+        use super::*;
+        use crate::codec::{Compressed, CompressionCodec, Deflate, Zstd, MAX_DECOMPRESSED_LEN};
+
+        fn round_trip<C: CompressionCodec>() {
+            let value = Compressed::<String, C>::new("diesel-d1 ".repeat(1000));
+            let bytes = value.to_bytes().unwrap().into_owned();
+            assert!(bytes.len() < value.0.len());
+
+            let read = Compressed::<String, C>::from_bytes(bytes).unwrap();
+            assert_eq!(read.0, value.0);
+        }
+
+        #[test]
+        fn deflate_round_trip() {
+            round_trip::<Deflate>();
+        }
+
+        #[test]
+        fn zstd_round_trip() {
+            round_trip::<Zstd>();
+        }
+
+        #[test]
+        fn empty_round_trip() {
+            let bytes = Compressed::<Vec<u8>, Zstd>::new(Vec::new()).to_bytes().unwrap().into_owned();
+            assert!(Compressed::<Vec<u8>, Zstd>::from_bytes(bytes).unwrap().0.is_empty());
+        }
+
+        #[test]
+        fn invalid_data_is_an_error() {
+            assert!(Deflate::decompress(&[0xff; 16]).is_err());
+            assert!(Zstd::decompress(b"not a zstd frame").is_err());
+        }
+
+        fn rejects_bomb<C: CompressionCodec>() {
+            let bomb = C::compress(&vec![0; MAX_DECOMPRESSED_LEN + 1]).unwrap();
+            let err = C::decompress(&bomb).unwrap_err();
+            assert!(err.to_string().contains("expands to more than"), "{err}");
+
+            let limit = C::compress(&vec![0; MAX_DECOMPRESSED_LEN]).unwrap();
+            assert_eq!(C::decompress(&limit).unwrap().len(), MAX_DECOMPRESSED_LEN);
+        }
+
+        #[test]
+        fn deflate_rejects_bomb() {
+            rejects_bomb::<Deflate>();
+        }
+
+        #[test]
+        fn zstd_rejects_bomb() {
+            rejects_bomb::<Zstd>();
+        }
+    }
+
+    #[cfg(feature = "encryption")]
+    mod encryption {
+        use super::*;
+        use crate::codec::{Encrypted, KeyProvider};
+
+        struct TestKey;
+
+        impl KeyProvider for TestKey {
+            fn key() -> [u8; 32] {
+                [7; 32]
+            }
+        }
+
+        struct OtherKey;
+
+        impl KeyProvider for OtherKey {
+            fn key() -> [u8; 32] {
+                [8; 32]
+            }
+        }
+
+        #[test]
+        fn round_trip() {
+            let value = Encrypted::<String, TestKey>::new("secret".to_owned());
+            let bytes = value.to_bytes().unwrap().into_owned();
+            assert!(!bytes.windows(6).any(|window| window == b"secret"));
+
+            let read = Encrypted::<String, TestKey>::from_bytes(bytes).unwrap();
+            assert_eq!(read.0, "secret");
+        }
+
+        #[test]
+        fn uses_a_fresh_nonce() {
+            let value = Encrypted::<Vec<u8>, TestKey>::new(vec![1, 2, 3]);
+            assert_ne!(value.to_bytes().unwrap(), value.to_bytes().unwrap());
+        }
+
+        #[test]
+        fn rejects_wrong_key_and_tampering() {
+            let mut bytes = Encrypted::<Vec<u8>, TestKey>::new(vec![1, 2, 3]).to_bytes().unwrap().into_owned();
+            assert!(Encrypted::<Vec<u8>, OtherKey>::from_bytes(bytes.clone()).is_err());
+
+            *bytes.last_mut().unwrap() ^= 1;
+            assert!(Encrypted::<Vec<u8>, TestKey>::from_bytes(bytes).is_err());
+            assert!(Encrypted::<Vec<u8>, TestKey>::from_bytes(vec![0; 4]).is_err());
+        }
+
+        #[test]
+        fn debug_is_redacted() {
+            let value = Encrypted::<String, TestKey>::new("secret".to_owned());
+            assert_eq!(format!("{value:?}"), "Encrypted(<redacted>)");
+        }
+
+        #[cfg(feature = "compression")]
+        #[test]
+        fn nested_round_trip() {
+            use crate::codec::{Compressed, Zstd};
+
+            let value = Encrypted::<Compressed<String, Zstd>, TestKey>::new(Compressed::new("a".repeat(500)));
+            let bytes = value.to_bytes().unwrap().into_owned();
+            assert!(bytes.len() < 100);
+
+            let read = Encrypted::<Compressed<String, Zstd>, TestKey>::from_bytes(bytes).unwrap();
+            assert_eq!(read.0 .0, "a".repeat(500));
+        }
+    }
+}
@@ -0,0 +1,709 @@
+//! Detecting drift between `table!` definitions and the schema of the live database
+//!
+//! List the tables the Worker was compiled with, then compare them with the database:
+//!
+//! ```ignore
+//! let expected = D1ExpectedSchema::default()
+//!     .table::<users::table>()
+//!     .table::<posts::table>();
+//!
+//! let drift = conn.schema_drift(&expected).await?;
+//! if !drift.is_empty() {
+//!     console_error!("{drift}");
+//! }
+//! ```
+
+use std::fmt;
+
+use diesel::{
+    query_builder::{QueryBuilder, QueryFragment},
+    sql_types, Column, QueryResult,
+};
+
+use crate::{
+    backend::D1Backend, query_builder::D1QueryBuilder, query_dsl::D1TableName, utils::quote_identifier, D1Column,
+    D1Connection,
+};
+
+/// SQLite's column affinities, see <https://www.sqlite.org/datatype3.html#type_affinity>
+#[derive(Debug, Copy, Clone, Hash, PartialEq, Eq)]
+pub enum D1Affinity {
+    Integer,
+    Real,
+    Numeric,
+    Text,
+    Blob,
+}
+
+impl D1Affinity {
+    /// Affinity SQLite gives to a column declared with `declared_type`
+    pub fn from_declared_type(declared_type: &str) -> Self {
+        let declared_type = declared_type.to_ascii_uppercase();
+        let contains = |needle: &str| declared_type.contains(needle);
+
+        if contains("INT") {
+            D1Affinity::Integer
+        } else if contains("CHAR") || contains("CLOB") || contains("TEXT") {
+            D1Affinity::Text
+        } else if contains("BLOB") || declared_type.is_empty() {
+            D1Affinity::Blob
+        } else if contains("REAL") || contains("FLOA") || contains("DOUB") {
+            D1Affinity::Real
+        } else {
+            D1Affinity::Numeric
+        }
+    }
+}
+
+impl fmt::Display for D1Affinity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            D1Affinity::Integer => "INTEGER",
+            D1Affinity::Real => "REAL",
+            D1Affinity::Numeric => "NUMERIC",
+            D1Affinity::Text => "TEXT",
+            D1Affinity::Blob => "BLOB",
+        })
+    }
+}
+
+/// How a diesel SQL type is expected to be stored by `D1Backend`
+///
+/// Implement it for custom SQL types to use them in checked tables.
+pub trait D1SqlTypeInfo {
+    /// Type used when generating `CREATE TABLE` and `ADD COLUMN` statements
+    const DECLARED_TYPE: &'static str;
+    /// Affinities a column can have without values failing to decode
+    const AFFINITIES: &'static [D1Affinity];
+    const NULLABLE: bool = false;
+}
+
+macro_rules! sql_type_info {
+    ($($sql_type:ident => $declared_type:literal, [$($affinity:ident),+];)*) => {
+        $(
+            impl D1SqlTypeInfo for sql_types::$sql_type {
+                const DECLARED_TYPE: &'static str = $declared_type;
+                const AFFINITIES: &'static [D1Affinity] = &[$(D1Affinity::$affinity),+];
+            }
+        )*
+    };
+}
+
+sql_type_info! {
+    Bool => "BOOLEAN", [Numeric, Integer];
+    SmallInt => "SMALLINT", [Integer, Numeric];
+    Integer => "INTEGER", [Integer, Numeric];
+    BigInt => "BIGINT", [Integer, Numeric];
+    Float => "FLOAT", [Real, Numeric];
+    Double => "DOUBLE", [Real, Numeric];
+    // stored as text, a NUMERIC column would round values through REAL
+    Numeric => "TEXT", [Text, Numeric];
+    Text => "TEXT", [Text];
+    Binary => "BLOB", [Blob];
+    Date => "DATE", [Numeric, Text];
+    Time => "TIME", [Numeric, Text];
+    Timestamp => "TIMESTAMP", [Numeric, Text];
+}
+
+impl<T: D1SqlTypeInfo + sql_types::SqlType> D1SqlTypeInfo for sql_types::Nullable<T> {
+    const DECLARED_TYPE: &'static str = T::DECLARED_TYPE;
+    const AFFINITIES: &'static [D1Affinity] = T::AFFINITIES;
+    const NULLABLE: bool = true;
+}
+
+/// A column as declared in `table!`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct D1ExpectedColumn {
+    pub name: &'static str,
+    pub declared_type: &'static str,
+    pub affinities: &'static [D1Affinity],
+    pub nullable: bool,
+    /// Position in the primary key starting at 1, 0 if the column isn't part of it
+    pub primary_key: usize,
+}
+
+/// A table as declared in `table!`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct D1ExpectedTable {
+    pub name: &'static str,
+    pub columns: Vec<D1ExpectedColumn>,
+}
+
+/// Tables generated by `table!`, implemented for all of them
+pub trait D1SchemaTable {
+    fn expected_table() -> D1ExpectedTable;
+}
+
+impl<T> D1SchemaTable for T
+where
+    T: D1TableName + Default,
+    T::AllColumns: D1ColumnList,
+    T::PrimaryKey: QueryFragment<D1Backend>,
+{
+    fn expected_table() -> D1ExpectedTable {
+        let mut columns = Vec::new();
+        T::AllColumns::push_columns(&mut columns);
+
+        // diesel doesn't expose primary key columns by name, find them in the rendered SQL.
+        // Every column is rendered as "`table`.`column`", so one can't match another.
+        let primary_key = render(&T::default().primary_key());
+        let mut positions = columns
+            .iter()
+            .enumerate()
+            .filter_map(|(index, (_, sql))| primary_key.find(sql.as_str()).map(|position| (position, index)))
+            .collect::<Vec<_>>();
+        positions.sort();
+
+        let mut columns = columns.into_iter().map(|(column, _)| column).collect::<Vec<_>>();
+        for (key_position, (_, index)) in positions.into_iter().enumerate() {
+            columns[index].primary_key = key_position + 1;
+        }
+
+        D1ExpectedTable {
+            name: T::TABLE_NAME,
+            columns,
+        }
+    }
+}
+
+/// The `AllColumns` tuple of a table
+#[doc(hidden)]
+pub trait D1ColumnList {
+    fn push_columns(columns: &mut Vec<(D1ExpectedColumn, String)>);
+}
+
+macro_rules! column_list {
+    ($($column:ident)+) => {
+        impl<$($column),+> D1ColumnList for ($($column,)+)
+        where
+            $($column: Column + QueryFragment<D1Backend> + Default, $column::SqlType: D1SqlTypeInfo,)+
+        {
+            fn push_columns(columns: &mut Vec<(D1ExpectedColumn, String)>) {
+                $(
+                    columns.push((
+                        D1ExpectedColumn {
+                            name: $column::NAME,
+                            declared_type: <$column::SqlType as D1SqlTypeInfo>::DECLARED_TYPE,
+                            affinities: <$column::SqlType as D1SqlTypeInfo>::AFFINITIES,
+                            nullable: <$column::SqlType as D1SqlTypeInfo>::NULLABLE,
+                            primary_key: 0,
+                        },
+                        render(&$column::default()),
+                    ));
+                )+
+            }
+        }
+    };
+}
+
+macro_rules! column_lists {
+    ($first:ident) => {
+        column_list!($first);
+    };
+    ($first:ident $($rest:ident)+) => {
+        column_list!($first $($rest)+);
+        column_lists!($($rest)+);
+    };
+}
+
+// diesel's default limit is 32 columns per table
+column_lists!(
+    C1 C2 C3 C4 C5 C6 C7 C8 C9 C10 C11 C12 C13 C14 C15 C16
+    C17 C18 C19 C20 C21 C22 C23 C24 C25 C26 C27 C28 C29 C30 C31 C32
+);
+
+fn render(fragment: &impl QueryFragment<D1Backend>) -> String {
+    let mut query_builder = D1QueryBuilder::default();
+    // rendering columns can't fail, they only push identifiers
+    let _ = fragment.to_sql(&mut query_builder, &D1Backend);
+    query_builder.finish()
+}
+
+/// The tables a Worker expects the database to have
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct D1ExpectedSchema {
+    pub tables: Vec<D1ExpectedTable>,
+}
+
+impl D1ExpectedSchema {
+    pub fn table<T: D1SchemaTable>(mut self) -> Self {
+        self.tables.push(T::expected_table());
+        self
+    }
+}
+
+/// A single way the live schema differs from the expected one
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum D1SchemaDifference {
+    MissingTable {
+        table: D1ExpectedTable,
+    },
+    /// A table in the database that isn't in [`D1ExpectedSchema`]
+    ExtraTable {
+        table: String,
+    },
+    MissingColumn {
+        table: &'static str,
+        column: D1ExpectedColumn,
+    },
+    /// A column in the database that isn't in `table!`
+    ExtraColumn {
+        table: &'static str,
+        column: D1Column,
+    },
+    /// The column's affinity doesn't hold values of the type used in `table!`
+    AffinityMismatch {
+        table: &'static str,
+        column: D1ExpectedColumn,
+        declared_type: String,
+        affinity: D1Affinity,
+    },
+    /// `nullable` is what `table!` declares, the database says the opposite
+    NullabilityMismatch {
+        table: &'static str,
+        column: D1ExpectedColumn,
+    },
+}
+
+impl fmt::Display for D1SchemaDifference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            D1SchemaDifference::MissingTable { table } => write!(f, "table `{}` is missing", table.name),
+            D1SchemaDifference::ExtraTable { table } => write!(f, "table `{table}` isn't declared in table!"),
+            D1SchemaDifference::MissingColumn { table, column } => {
+                write!(f, "column `{table}.{}` is missing", column.name)
+            },
+            D1SchemaDifference::ExtraColumn { table, column } => {
+                write!(f, "column `{table}.{}` isn't declared in table!", column.name)
+            },
+            D1SchemaDifference::AffinityMismatch {
+                table,
+                column,
+                declared_type,
+                affinity,
+            } => write!(
+                f,
+                "column `{table}.{}` is declared as `{declared_type}` ({affinity} affinity), expected {}",
+                column.name, column.declared_type
+            ),
+            D1SchemaDifference::NullabilityMismatch { table, column } => {
+                if column.nullable {
+                    write!(f, "column `{table}.{}` is NOT NULL, table! declares it nullable", column.name)
+                } else {
+                    write!(f, "column `{table}.{}` is nullable, table! declares it NOT NULL", column.name)
+                }
+            },
+        }
+    }
+}
+
+/// Result of [`D1Connection::schema_drift`]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct D1SchemaDrift {
+    pub differences: Vec<D1SchemaDifference>,
+}
+
+impl D1SchemaDrift {
+    pub fn is_empty(&self) -> bool {
+        self.differences.is_empty()
+    }
+
+    /// SQL that brings the database in line with `table!`
+    ///
+    /// Only additive changes are generated as statements. Dropping data or changing existing
+    /// columns needs a table rebuild, those differences are left as comments to handle by hand.
+    pub fn reconcile_sql(&self) -> String {
+        let mut sql = String::new();
+
+        for difference in &self.differences {
+            match difference {
+                D1SchemaDifference::MissingTable { table } => {
+                    let mut definitions = table
+                        .columns
+                        .iter()
+                        .map(|column| {
+                            let not_null = if column.nullable { "" } else { " NOT NULL" };
+                            format!("{} {}{not_null}", quote_identifier(column.name), column.declared_type)
+                        })
+                        .collect::<Vec<_>>();
+
+                    let mut primary_key = table.columns.iter().filter(|column| column.primary_key > 0).collect::<Vec<_>>();
+                    primary_key.sort_by_key(|column| column.primary_key);
+                    if !primary_key.is_empty() {
+                        let columns = primary_key
+                            .iter()
+                            .map(|column| quote_identifier(column.name))
+                            .collect::<Vec<_>>()
+                            .join(", ");
+                        definitions.push(format!("PRIMARY KEY ({columns})"));
+                    }
+
+                    sql.push_str(&format!(
+                        "CREATE TABLE {} (\n    {}\n);\n",
+                        quote_identifier(table.name),
+                        definitions.join(",\n    ")
+                    ));
+                },
+                D1SchemaDifference::MissingColumn { table, column } if column.nullable => {
+                    sql.push_str(&format!(
+                        "ALTER TABLE {} ADD COLUMN {} {};\n",
+                        quote_identifier(table),
+                        quote_identifier(column.name),
+                        column.declared_type
+                    ));
+                },
+                D1SchemaDifference::MissingColumn { table, column } => {
+                    // SQLite can only add NOT NULL columns with a default, which table! doesn't know
+                    sql.push_str(&format!(
+                        "-- {difference}, it needs a default: ALTER TABLE {} ADD COLUMN {} {} NOT NULL DEFAULT ...;\n",
+                        quote_identifier(table),
+                        quote_identifier(column.name),
+                        column.declared_type
+                    ));
+                },
+                _ => sql.push_str(&format!("-- {difference}\n")),
+            }
+        }
+
+        sql
+    }
+}
+
+impl fmt::Display for D1SchemaDrift {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for difference in &self.differences {
+            writeln!(f, "{difference}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Tables that are never declared in `table!`
+fn is_internal_table(name: &str) -> bool {
+    [
+        crate::migrations::MIGRATIONS_TABLE,
+        crate::migrations::WRANGLER_MIGRATIONS_TABLE,
+        crate::migrations::CHECKPOINTS_TABLE,
+        crate::migrations::BOOKMARKS_TABLE,
+    ]
+    .contains(&name)
+}
+
+impl D1Connection {
+    /// Compares `expected` with the tables and columns of the database
+    ///
+    /// Tables of the database that aren't in `expected` are reported as well, apart from the
+    /// ones used to track migrations.
+    pub async fn schema_drift(&mut self, expected: &D1ExpectedSchema) -> QueryResult<D1SchemaDrift> {
+        let mut tables = Vec::new();
+        for table in self.tables().await? {
+            // columns of tables that aren't expected are never compared
+            let columns = match expected.tables.iter().any(|expected| expected.name == table.name) {
+                true => self.columns(&table.name).await?,
+                false => Vec::new(),
+            };
+            tables.push((table.name, columns));
+        }
+
+        Ok(compare_schema(expected, tables))
+    }
+}
+
+/// Compares `expected` with the `(name, columns)` of every table of the database
+fn compare_schema(expected: &D1ExpectedSchema, tables: Vec<(String, Vec<D1Column>)>) -> D1SchemaDrift {
+    let mut differences = Vec::new();
+
+    for expected_table in &expected.tables {
+        let Some((_, columns)) = tables.iter().find(|(name, _)| name == expected_table.name) else {
+            differences.push(D1SchemaDifference::MissingTable {
+                table: expected_table.clone(),
+            });
+            continue;
+        };
+        let table = expected_table.name;
+
+        for expected_column in &expected_table.columns {
+            let Some(column) = columns.iter().find(|column| column.name == expected_column.name) else {
+                differences.push(D1SchemaDifference::MissingColumn {
+                    table,
+                    column: expected_column.clone(),
+                });
+                continue;
+            };
+
+            let affinity = D1Affinity::from_declared_type(&column.declared_type);
+            if !expected_column.affinities.contains(&affinity) {
+                differences.push(D1SchemaDifference::AffinityMismatch {
+                    table,
+                    column: expected_column.clone(),
+                    declared_type: column.declared_type.clone(),
+                    affinity,
+                });
+            }
+
+            // primary keys are never NULL in practice, even when SQLite would allow it
+            let nullable = !column.not_null && column.primary_key == 0;
+            if nullable != expected_column.nullable {
+                differences.push(D1SchemaDifference::NullabilityMismatch {
+                    table,
+                    column: expected_column.clone(),
+                });
+            }
+        }
+
+        for column in columns {
+            if !expected_table.columns.iter().any(|expected| expected.name == column.name) {
+                differences.push(D1SchemaDifference::ExtraColumn {
+                    table,
+                    column: column.clone(),
+                });
+            }
+        }
+    }
+
+    for (table, _) in tables {
+        let expected = expected.tables.iter().any(|expected| expected.name == table);
+        if !expected && !is_internal_table(&table) {
+            differences.push(D1SchemaDifference::ExtraTable { table });
+        }
+    }
+
+    D1SchemaDrift { differences }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    diesel::table! {
+        users (id) {
+            id -> Integer,
+            name -> Text,
+            bio -> Nullable<Text>,
+        }
+    }
+
+    // the key isn't in declaration order, and `id` is a suffix of `user_id`
+    diesel::table! {
+        memberships (user_id, group_id) {
+            id -> BigInt,
+            group_id -> Integer,
+            user_id -> Integer,
+            joined_at -> Nullable<Timestamp>,
+        }
+    }
+
+    fn column(position: i32, name: &str, declared_type: &str, not_null: bool, primary_key: i32) -> D1Column {
+        D1Column {
+            position,
+            name: name.to_owned(),
+            declared_type: declared_type.to_owned(),
+            not_null,
+            default_value: None,
+            primary_key,
+        }
+    }
+
+    fn users_columns() -> Vec<D1Column> {
+        vec![
+            column(0, "id", "INTEGER", false, 1),
+            column(1, "name", "TEXT", true, 0),
+            column(2, "bio", "TEXT", false, 0),
+        ]
+    }
+
+    fn expected_column(table: &D1ExpectedTable, name: &str) -> D1ExpectedColumn {
+        table.columns.iter().find(|column| column.name == name).unwrap().clone()
+    }
+
+    #[test]
+    fn maps_declared_types_to_affinities() {
+        // the examples of https://www.sqlite.org/datatype3.html#affinity_name_examples
+        let cases = [
+            ("INT", D1Affinity::Integer),
+            ("integer", D1Affinity::Integer),
+            ("UNSIGNED BIG INT", D1Affinity::Integer),
+            ("INT8", D1Affinity::Integer),
+            ("CHARACTER(20)", D1Affinity::Text),
+            ("varchar(255)", D1Affinity::Text),
+            ("NATIVE CHARACTER(70)", D1Affinity::Text),
+            ("CLOB", D1Affinity::Text),
+            ("BLOB", D1Affinity::Blob),
+            ("", D1Affinity::Blob),
+            ("REAL", D1Affinity::Real),
+            ("DOUBLE PRECISION", D1Affinity::Real),
+            ("FLOAT", D1Affinity::Real),
+            ("NUMERIC", D1Affinity::Numeric),
+            ("DECIMAL(10,5)", D1Affinity::Numeric),
+            ("BOOLEAN", D1Affinity::Numeric),
+            ("DATETIME", D1Affinity::Numeric),
+            // INT wins over every other rule
+            ("FLOATING POINT", D1Affinity::Integer),
+            ("CHARINT", D1Affinity::Integer),
+        ];
+        for (declared_type, affinity) in cases {
+            assert_eq!(D1Affinity::from_declared_type(declared_type), affinity, "{declared_type:?}");
+        }
+    }
+
+    #[test]
+    fn describes_table_columns() {
+        let table = users::table::expected_table();
+        assert_eq!(table.name, "users");
+        assert_eq!(
+            table.columns,
+            [
+                D1ExpectedColumn {
+                    name: "id",
+                    declared_type: "INTEGER",
+                    affinities: &[D1Affinity::Integer, D1Affinity::Numeric],
+                    nullable: false,
+                    primary_key: 1,
+                },
+                D1ExpectedColumn {
+                    name: "name",
+                    declared_type: "TEXT",
+                    affinities: &[D1Affinity::Text],
+                    nullable: false,
+                    primary_key: 0,
+                },
+                D1ExpectedColumn {
+                    name: "bio",
+                    declared_type: "TEXT",
+                    affinities: &[D1Affinity::Text],
+                    nullable: true,
+                    primary_key: 0,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn finds_primary_key_columns_in_key_order() {
+        let table = memberships::table::expected_table();
+        let primary_key = table
+            .columns
+            .iter()
+            .map(|column| (column.name, column.primary_key))
+            .collect::<Vec<_>>();
+        assert_eq!(primary_key, [("id", 0), ("group_id", 2), ("user_id", 1), ("joined_at", 0)]);
+    }
+
+    #[test]
+    fn matching_schema_has_no_drift() {
+        let expected = D1ExpectedSchema::default().table::<users::table>();
+        let tables = vec![
+            ("users".to_owned(), users_columns()),
+            (crate::migrations::MIGRATIONS_TABLE.to_owned(), Vec::new()),
+        ];
+
+        let drift = compare_schema(&expected, tables);
+        assert!(drift.is_empty(), "{drift}");
+        assert_eq!(drift.reconcile_sql(), "");
+    }
+
+    #[test]
+    fn reports_missing_and_extra_tables() {
+        let expected = D1ExpectedSchema::default().table::<users::table>().table::<memberships::table>();
+        let tables = vec![("users".to_owned(), users_columns()), ("sessions".to_owned(), Vec::new())];
+
+        let drift = compare_schema(&expected, tables);
+        assert_eq!(
+            drift.differences,
+            [
+                D1SchemaDifference::MissingTable {
+                    table: memberships::table::expected_table(),
+                },
+                D1SchemaDifference::ExtraTable {
+                    table: "sessions".to_owned(),
+                },
+            ]
+        );
+        assert_eq!(
+            drift.to_string(),
+            "table `memberships` is missing\ntable `sessions` isn't declared in table!\n"
+        );
+    }
+
+    #[test]
+    fn reports_column_differences() {
+        let users = users::table::expected_table();
+        let expected = D1ExpectedSchema::default().table::<users::table>();
+        let columns = vec![
+            column(0, "id", "INTEGER", false, 1),
+            column(1, "name", "BLOB", false, 0),
+            column(2, "avatar", "BLOB", false, 0),
+        ];
+
+        let drift = compare_schema(&expected, vec![("users".to_owned(), columns.clone())]);
+        assert_eq!(
+            drift.differences,
+            [
+                D1SchemaDifference::AffinityMismatch {
+                    table: "users",
+                    column: expected_column(&users, "name"),
+                    declared_type: "BLOB".to_owned(),
+                    affinity: D1Affinity::Blob,
+                },
+                D1SchemaDifference::NullabilityMismatch {
+                    table: "users",
+                    column: expected_column(&users, "name"),
+                },
+                D1SchemaDifference::MissingColumn {
+                    table: "users",
+                    column: expected_column(&users, "bio"),
+                },
+                D1SchemaDifference::ExtraColumn {
+                    table: "users",
+                    column: columns[2].clone(),
+                },
+            ]
+        );
+        assert_eq!(
+            drift.to_string(),
+            "column `users.name` is declared as `BLOB` (BLOB affinity), expected TEXT\n\
+             column `users.name` is nullable, table! declares it NOT NULL\n\
+             column `users.bio` is missing\n\
+             column `users.avatar` isn't declared in table!\n"
+        );
+    }
+
+    #[test]
+    fn reconciles_additive_differences() {
+        let memberships = memberships::table::expected_table();
+        let users = users::table::expected_table();
+        let drift = D1SchemaDrift {
+            differences: vec![
+                D1SchemaDifference::MissingTable {
+                    table: memberships.clone(),
+                },
+                D1SchemaDifference::MissingColumn {
+                    table: "users",
+                    column: expected_column(&users, "bio"),
+                },
+                D1SchemaDifference::MissingColumn {
+                    table: "users",
+                    column: expected_column(&users, "name"),
+                },
+                D1SchemaDifference::ExtraTable {
+                    table: "sessions".to_owned(),
+                },
+            ],
+        };
+
+        assert_eq!(
+            drift.reconcile_sql(),
+            "CREATE TABLE \"memberships\" (\n    \
+                \"id\" BIGINT NOT NULL,\n    \
+                \"group_id\" INTEGER NOT NULL,\n    \
+                \"user_id\" INTEGER NOT NULL,\n    \
+                \"joined_at\" TIMESTAMP,\n    \
+                PRIMARY KEY (\"user_id\", \"group_id\")\n\
+             );\n\
+             ALTER TABLE \"users\" ADD COLUMN \"bio\" TEXT;\n\
+             -- column `users.name` is missing, it needs a default: \
+                ALTER TABLE \"users\" ADD COLUMN \"name\" TEXT NOT NULL DEFAULT ...;\n\
+             -- table `sessions` isn't declared in table!\n"
+        );
+    }
+}
@@ -0,0 +1,101 @@
+use diesel::{
+    deserialize::{self, FromSql},
+    query_builder::{QueryFragment, QueryId},
+    QueryResult,
+};
+use wasm_bindgen::JsValue;
+
+use crate::{
+    backend::D1Backend,
+    fetch_all, prepare_statement_sql,
+    utils::SendableFuture,
+    value::{D1Value, D1ValueType},
+    D1Connection,
+};
+
+/// A column value whose Rust type is picked at runtime from what D1 returned
+///
+/// Implements `FromSql` for every SQL type, so it can be used with `diesel-dynamic-schema`
+/// or wherever the column types aren't known at compile time.
+#[derive(Debug, Clone, PartialEq)]
+pub enum D1DynamicValue {
+    Null,
+    Integer(i64),
+    Real(f64),
+    Text(String),
+    Blob(Vec<u8>),
+}
+
+impl D1DynamicValue {
+    fn from_value(value: &D1Value) -> deserialize::Result<Self> {
+        match value.value_type() {
+            D1ValueType::Null => Ok(D1DynamicValue::Null),
+            D1ValueType::Integer => value
+                .read_integer()
+                // JS booleans are reported as integers as well
+                .or_else(|err| value.read_bool().map(i64::from).map_err(|_| err))
+                .map(D1DynamicValue::Integer),
+            D1ValueType::Real => value.read_number().map(D1DynamicValue::Real),
+            D1ValueType::Text => value.read_string().map(D1DynamicValue::Text),
+            D1ValueType::Blob => value.read_blob().map(D1DynamicValue::Blob),
+        }
+    }
+}
+
+impl<ST> FromSql<ST, D1Backend> for D1DynamicValue {
+    fn from_sql(value: D1Value) -> deserialize::Result<Self> {
+        D1DynamicValue::from_value(&value)
+    }
+
+    fn from_nullable_sql(value: Option<D1Value>) -> deserialize::Result<Self> {
+        match value {
+            Some(value) => D1DynamicValue::from_value(&value),
+            None => Ok(D1DynamicValue::Null),
+        }
+    }
+}
+
+/// Untyped result of [`D1Connection::load_dynamic`]
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct D1DynamicResult {
+    /// Column names in the order D1 returned them. Empty if the query returned no rows,
+    /// since D1 only reports columns through the row objects.
+    pub columns: Vec<String>,
+    /// One entry per row, with a value for each of `columns`
+    pub rows: Vec<Vec<D1DynamicValue>>,
+}
+
+impl D1Connection {
+    /// Runs `source` and returns its rows without mapping them to Rust types first
+    ///
+    /// ```ignore
+    /// let result = conn.load_dynamic(diesel::sql_query("SELECT * FROM users")).await?;
+    /// for row in result.rows {
+    ///     // ...
+    /// }
+    /// ```
+    pub async fn load_dynamic<T>(&mut self, source: T) -> QueryResult<D1DynamicResult>
+    where
+        T: QueryFragment<D1Backend> + QueryId,
+    {
+        let statement = prepare_statement_sql(source, &self.binding);
+        let (columns, array) = SendableFuture(fetch_all(statement)).await?;
+
+        let rows = array
+            .iter()
+            .map(|row| {
+                columns
+                    .iter()
+                    .map(|column| {
+                        let js_value = js_sys::Reflect::get(row, &column.into())
+                            .unwrap_or(JsValue::UNDEFINED);
+                        D1DynamicValue::from_value(&D1Value::new(js_value, column.clone(), self.decoding_mode))
+                            .map_err(diesel::result::Error::DeserializationError)
+                    })
+                    .collect::<QueryResult<Vec<_>>>()
+            })
+            .collect::<QueryResult<Vec<_>>>()?;
+
+        Ok(D1DynamicResult { columns, rows })
+    }
+}
@@ -0,0 +1,159 @@
+use diesel::{
+    sql_types::{Bool, Integer, Nullable, Text},
+    QueryResult, QueryableByName,
+};
+use diesel_async::RunQueryDsl;
+
+use crate::D1Connection;
+
+/// A table as listed in `sqlite_master`
+#[derive(Debug, Clone, PartialEq, Eq, QueryableByName)]
+pub struct D1Table {
+    #[diesel(sql_type = Text)]
+    pub name: String,
+    /// The `CREATE TABLE` statement the table was created with
+    #[diesel(sql_type = Text)]
+    pub sql: String,
+}
+
+/// A column of a table, from `pragma_table_info`
+#[derive(Debug, Clone, PartialEq, Eq, QueryableByName)]
+pub struct D1Column {
+    /// Position of the column in the table, starting at 0
+    #[diesel(sql_type = Integer)]
+    pub position: i32,
+    #[diesel(sql_type = Text)]
+    pub name: String,
+    /// Declared type, as written in `CREATE TABLE`. Empty if the column has none.
+    #[diesel(sql_type = Text)]
+    pub declared_type: String,
+    #[diesel(sql_type = Bool)]
+    pub not_null: bool,
+    /// Default value as an SQL expression
+    #[diesel(sql_type = Nullable<Text>)]
+    pub default_value: Option<String>,
+    /// Position in the primary key starting at 1, 0 if the column isn't part of it
+    #[diesel(sql_type = Integer)]
+    pub primary_key: i32,
+}
+
+/// An index of a table, from `pragma_index_list` and `pragma_index_info`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct D1Index {
+    pub name: String,
+    pub unique: bool,
+    /// `c` if created with `CREATE INDEX`, `u` for `UNIQUE` constraints and `pk` for primary keys
+    pub origin: String,
+    /// Whether the index has a `WHERE` clause
+    pub partial: bool,
+    /// Indexed columns in key order, `None` for expressions
+    pub columns: Vec<Option<String>>,
+}
+
+/// One column of a foreign key, from `pragma_foreign_key_list`
+#[derive(Debug, Clone, PartialEq, Eq, QueryableByName)]
+pub struct D1ForeignKey {
+    /// Keys spanning several columns are listed once per column, with the same id
+    #[diesel(sql_type = Integer)]
+    pub id: i32,
+    /// Position of this column in the key, starting at 0
+    #[diesel(sql_type = Integer)]
+    pub seq: i32,
+    #[diesel(sql_type = Text)]
+    pub column: String,
+    #[diesel(sql_type = Text)]
+    pub parent_table: String,
+    /// `None` when the key references the parent's primary key implicitly
+    #[diesel(sql_type = Nullable<Text>)]
+    pub parent_column: Option<String>,
+    #[diesel(sql_type = Text)]
+    pub on_update: String,
+    #[diesel(sql_type = Text)]
+    pub on_delete: String,
+}
+
+/// A single column of an index, before they're grouped into `D1Index`
+#[derive(QueryableByName)]
+struct IndexColumn {
+    #[diesel(sql_type = Text)]
+    name: String,
+    #[diesel(sql_type = Bool)]
+    unique: bool,
+    #[diesel(sql_type = Text)]
+    origin: String,
+    #[diesel(sql_type = Bool)]
+    partial: bool,
+    #[diesel(sql_type = Nullable<Text>)]
+    column: Option<String>,
+}
+
+impl D1Connection {
+    /// Every table of the database, leaving out the internal `sqlite_*` and `_cf_*` ones
+    pub async fn tables(&mut self) -> QueryResult<Vec<D1Table>> {
+        diesel::sql_query(
+            "SELECT name, sql FROM sqlite_master \
+            WHERE type = 'table' \
+            AND name NOT LIKE 'sqlite\\_%' ESCAPE '\\' \
+            AND name NOT LIKE '\\_cf\\_%' ESCAPE '\\' \
+            ORDER BY name",
+        )
+        .load(self)
+        .await
+    }
+
+    /// Columns of `table`, in table order
+    pub async fn columns(&mut self, table: &str) -> QueryResult<Vec<D1Column>> {
+        diesel::sql_query(
+            "SELECT cid AS position, name, type AS declared_type, \"notnull\" AS not_null, \
+            dflt_value AS default_value, pk AS primary_key \
+            FROM pragma_table_info(?) \
+            ORDER BY cid",
+        )
+        .bind::<Text, _>(table)
+        .load(self)
+        .await
+    }
+
+    /// Indexes of `table`, including the ones SQLite creates for `UNIQUE` and primary key
+    /// constraints
+    pub async fn indexes(&mut self, table: &str) -> QueryResult<Vec<D1Index>> {
+        let columns: Vec<IndexColumn> = diesel::sql_query(
+            "SELECT list.name, list.\"unique\", list.origin, list.partial, info.name AS \"column\" \
+            FROM pragma_index_list(?) AS list \
+            LEFT JOIN pragma_index_info(list.name) AS info \
+            ORDER BY list.seq, info.seqno",
+        )
+        .bind::<Text, _>(table)
+        .load(self)
+        .await?;
+
+        let mut indexes: Vec<D1Index> = Vec::new();
+        for column in columns {
+            match indexes.last_mut() {
+                Some(index) if index.name == column.name => index.columns.push(column.column),
+                _ => indexes.push(D1Index {
+                    name: column.name,
+                    unique: column.unique,
+                    origin: column.origin,
+                    partial: column.partial,
+                    columns: vec![column.column],
+                }),
+            }
+        }
+
+        Ok(indexes)
+    }
+
+    /// Foreign keys of `table`, one entry per referencing column
+    pub async fn foreign_keys(&mut self, table: &str) -> QueryResult<Vec<D1ForeignKey>> {
+        diesel::sql_query(
+            "SELECT id, seq, \"from\" AS \"column\", \"table\" AS parent_table, \"to\" AS parent_column, \
+            on_update, on_delete \
+            FROM pragma_foreign_key_list(?) \
+            ORDER BY id, seq",
+        )
+        .bind::<Text, _>(table)
+        .load(self)
+        .await
+    }
+}
@@ -1,3 +1,5 @@
+use std::future::Future;
+
 use async_trait::async_trait;
 use backend::D1Backend;
 use bind_collector::D1BindCollector;
@@ -14,39 +16,74 @@ use futures_util::{
     FutureExt, StreamExt,
 };
 use js_sys::{Array, Object, Reflect};
+use migrations::D1MigrationsTable;
 use query_builder::D1QueryBuilder;
 use row::D1Row;
 use transaction_manager::D1TransactionManager;
-use utils::{D1Error, SendableFuture};
+use utils::{js_error_message, split_statements, D1Error, SendableFuture};
 use wasm_bindgen::JsValue;
 use wasm_bindgen_futures::JsFuture;
-use worker::{console_error, console_log};
+use worker::console_error;
 
 pub mod backend;
 mod bind_collector;
 mod binding;
+#[cfg(any(feature = "compression", feature = "encryption"))]
+pub mod codec;
+pub mod drift;
+mod dynamic;
+mod introspection;
+pub mod migrations;
 mod query_builder;
+pub mod query_dsl;
+pub mod rebuild;
 mod row;
+#[cfg(feature = "seed")]
+pub mod seed;
+#[cfg(feature = "test-utils")]
+mod test_database;
 mod transaction_manager;
 mod types;
 mod utils;
 mod value;
 
+#[cfg(feature = "derive")]
+pub use diesel_d1_derive::{embed_wrangler_migrations, D1Enum};
+pub use dynamic::{D1DynamicResult, D1DynamicValue};
+pub use introspection::{D1Column, D1ForeignKey, D1Index, D1Table};
+#[cfg(feature = "test-utils")]
+pub use test_database::D1TestDatabase;
+pub use value::{D1DecodingMode, D1Value, D1ValueError, D1ValueType};
+
 pub struct D1Connection {
     transaction_queries: Vec<D1PreparedStatement>,
     transaction_manager: D1TransactionManager,
     binding: D1Database,
+    decoding_mode: D1DecodingMode,
+    migrations_table: D1MigrationsTable,
 }
 
 impl D1Connection {
     pub fn new(env: worker::Env, name: &str) -> Self {
         let binding: D1Database = Reflect::get(&env, &name.to_owned().into()).unwrap().into();
+        D1Connection::from_binding(binding)
+    }
+
+    pub(crate) fn from_binding(binding: D1Database) -> Self {
         D1Connection {
             transaction_queries: Vec::default(),
             transaction_manager: D1TransactionManager::default(),
             binding,
+            decoding_mode: D1DecodingMode::default(),
+            migrations_table: D1MigrationsTable::default(),
         }
     }
+
+    /// Sets how values read by this connection are matched against the requested Rust types,
+    /// see [`D1DecodingMode`]
+    pub fn set_decoding_mode(&mut self, mode: D1DecodingMode) {
+        self.decoding_mode = mode;
+    }
 }
 
 // SAFETY: this is safe under WASM and workers because there's no threads and therefore no race conditions (at least memory ones)
@@ -56,13 +93,12 @@ unsafe impl Sync for D1Connection {}
 #[async_trait]
 impl SimpleAsyncConnection for D1Connection {
     async fn batch_execute(&mut self, query: &str) -> diesel::QueryResult<()> {
-        let statements = [JsValue::from_str(query)].iter().collect::<Array>();
+        let statements = split_statements(query)
+            .iter()
+            .map(|statement| prepare(&self.binding, statement))
+            .collect::<QueryResult<Vec<_>>>()?;
 
-        match SendableFuture(JsFuture::from(self.binding.batch(statements).unwrap())).await {
-            Ok(_) => Ok(()),
-            // FIXME(lduarte): I don't send a proper error becase I don't have time at the moment
-            Err(_) => Err(diesel::result::Error::NotFound),
-        }
+        run_batch(&self.binding, &statements).await
     }
 }
 #[async_trait]
@@ -93,51 +129,15 @@ impl AsyncConnection for D1Connection {
     {
         let source = source.as_query();
         let result = prepare_statement_sql(source, &self.binding);
+        let decoding_mode = self.decoding_mode;
 
         SendableFuture(async move {
-            let promise = match result.all() {
-                Ok(res) => res,
-                Err(err) => {
-                    console_error!("{:?}", err);
-                    panic!("not supposed to happen .all call");
-                },
-            };
-
-            let result = match SendableFuture(JsFuture::from(promise)).await {
-                Ok(res) => res,
-                Err(err) => {
-                    console_error!("{:?}", err);
-                    panic!("not supposed to happen .all promise");
-                },
-            };
-
-            let result: D1Result = result.into();
-
-            let error = result.error().unwrap();
-
-            if let Some(error_str) = error {
-                return Err(diesel::result::Error::DatabaseError(
-                    diesel::result::DatabaseErrorKind::Unknown,
-                    Box::new(D1Error { message: error_str }),
-                ));
-            }
-
-            let array = result.results().unwrap().unwrap().to_vec();
-
-            if array.is_empty() {
-                return Ok(stream::iter(vec![]).boxed());
-            }
-
-            let field_keys: Vec<String> = js_sys::Object::keys(&Object::from(array[0].clone()))
-                .to_vec()
-                .iter()
-                .map(|val| val.as_string().unwrap())
-                .collect();
+            let (field_keys, array) = fetch_all(result).await?;
 
             // FIXME: not performant at all, should work well enough
             let rows: Vec<QueryResult<D1Row>> = array
-                .iter()
-                .map(|val| Ok(D1Row::new(val.clone(), field_keys.clone())))
+                .into_iter()
+                .map(|val| Ok(D1Row::new(val, field_keys.clone(), decoding_mode)))
                 .collect();
             let iter = stream::iter(rows).boxed();
             Ok(iter)
@@ -211,6 +211,79 @@ impl AsyncConnection for D1Connection {
 
 impl ConnectionSealed for D1Connection {}
 
+/// Runs `statement` with `.all()` and returns the column names along with the raw JS row objects
+///
+/// Column names are taken from the first row, so an empty result has no columns
+async fn fetch_all(statement: D1PreparedStatement) -> QueryResult<(Vec<String>, Vec<JsValue>)> {
+    let promise = match statement.all() {
+        Ok(res) => res,
+        Err(err) => {
+            console_error!("{:?}", err);
+            panic!("not supposed to happen .all call");
+        },
+    };
+
+    let result = match SendableFuture(JsFuture::from(promise)).await {
+        Ok(res) => res,
+        Err(err) => {
+            console_error!("{:?}", err);
+            panic!("not supposed to happen .all promise");
+        },
+    };
+
+    let result: D1Result = result.into();
+
+    let error = result.error().unwrap();
+
+    if let Some(error_str) = error {
+        return Err(diesel::result::Error::DatabaseError(
+            diesel::result::DatabaseErrorKind::Unknown,
+            Box::new(D1Error { message: error_str }),
+        ));
+    }
+
+    let array = result.results().unwrap().unwrap().to_vec();
+
+    if array.is_empty() {
+        return Ok((vec![], array));
+    }
+
+    let field_keys: Vec<String> = js_sys::Object::keys(&Object::from(array[0].clone()))
+        .to_vec()
+        .iter()
+        .map(|val| val.as_string().unwrap())
+        .collect();
+
+    Ok((field_keys, array))
+}
+
+/// Runs `statements` through `D1Database::batch`, which applies them atomically
+fn run_batch(
+    binding: &D1Database,
+    statements: &[D1PreparedStatement],
+) -> SendableFuture<impl Future<Output = QueryResult<()>>> {
+    let promise = binding.batch(statements.iter().collect::<Array>());
+
+    SendableFuture(async move {
+        let promise = promise.map_err(|err| d1_error(&err))?;
+        SendableFuture(JsFuture::from(promise))
+            .await
+            .map(|_| ())
+            .map_err(|err| d1_error(&err))
+    })
+}
+
+fn prepare(binding: &D1Database, sql: &str) -> QueryResult<D1PreparedStatement> {
+    binding.prepare(sql).map_err(|err| d1_error(&err))
+}
+
+fn d1_error(err: &JsValue) -> diesel::result::Error {
+    diesel::result::Error::DatabaseError(
+        diesel::result::DatabaseErrorKind::Unknown,
+        Box::new(D1Error { message: js_error_message(err) }),
+    )
+}
+
 fn construct_bind_data<T>(query: &T) -> Result<Array, diesel::result::Error>
 where
     T: QueryFragment<D1Backend>,
@@ -0,0 +1,60 @@
+use diesel::{sql_types::Text, QueryResult, QueryableByName};
+use diesel_async::RunQueryDsl;
+
+use super::quote_literal;
+use crate::D1Connection;
+
+/// Table holding the Time Travel bookmark taken before each migration was applied
+pub const BOOKMARKS_TABLE: &str = "__diesel_d1_migration_bookmarks";
+
+/// The [Time Travel](https://developers.cloudflare.com/d1/reference/time-travel/) bookmark of
+/// the database right before a migration was applied
+///
+/// Bookmarks can only be requested through the Cloudflare REST API, so they're recorded by
+/// `diesel-d1 migrate` when it runs against a remote database. Restoring one
+/// (`diesel-d1 restore-to-bookmark --version ...`) undoes the migration and everything
+/// written after it.
+///
+/// Migrations applied from inside a Worker by [`D1Connection::run_pending_migrations`], or
+/// by Wrangler, get no bookmark. Restoring by their version fails naming it, restore to a
+/// bookmark from `wrangler d1 time-travel info` instead.
+#[derive(Debug, Clone, PartialEq, Eq, QueryableByName)]
+pub struct D1MigrationBookmark {
+    #[diesel(sql_type = Text)]
+    pub version: String,
+    #[diesel(sql_type = Text)]
+    pub bookmark: String,
+}
+
+impl D1MigrationBookmark {
+    /// Creates [`BOOKMARKS_TABLE`] if it doesn't exist yet
+    pub fn create_table_sql() -> String {
+        format!(
+            "CREATE TABLE IF NOT EXISTS {BOOKMARKS_TABLE} (\
+                version VARCHAR(50) PRIMARY KEY NOT NULL, \
+                bookmark TEXT NOT NULL, \
+                created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP\
+            )"
+        )
+    }
+
+    /// Records the bookmark, meant to be sent in the same batch as the migration
+    pub fn insert_sql(&self) -> String {
+        format!(
+            "INSERT OR REPLACE INTO {BOOKMARKS_TABLE} (version, bookmark) VALUES ({}, {})",
+            quote_literal(&self.version),
+            quote_literal(&self.bookmark)
+        )
+    }
+}
+
+impl D1Connection {
+    /// Bookmarks recorded before migrations were applied, newest migration first
+    pub async fn migration_bookmarks(&mut self) -> QueryResult<Vec<D1MigrationBookmark>> {
+        self.run_statements(&[D1MigrationBookmark::create_table_sql()]).await?;
+
+        diesel::sql_query(format!("SELECT version, bookmark FROM {BOOKMARKS_TABLE} ORDER BY version DESC"))
+            .load(self)
+            .await
+    }
+}
@@ -0,0 +1,207 @@
+use async_trait::async_trait;
+use diesel::{
+    migration::{self, Migration, MigrationSource, MigrationVersion},
+    sql_types::Text,
+    QueryResult, QueryableByName,
+};
+use diesel_async::RunQueryDsl;
+
+use crate::{backend::D1Backend, D1Connection};
+
+/// Table holding the progress of [`D1Migration`]s that were interrupted
+pub const CHECKPOINTS_TABLE: &str = "__diesel_d1_migration_checkpoints";
+
+/// A migration written in Rust, for changes SQL alone can't make like backfilling derived
+/// columns or re-encoding blobs
+///
+/// It's recorded in the same table as SQL migrations once `up` returns, see
+/// [`D1MigrationSet`]. `up` isn't atomic, so it may run again after failing or being cut off
+/// by the Workers CPU limit: keep it idempotent, and use a [`D1Checkpoint`] to pick up large
+/// backfills where they stopped.
+///
+/// ```ignore
+/// struct BackfillSlugs;
+///
+/// #[async_trait]
+/// impl D1Migration for BackfillSlugs {
+///     fn version(&self) -> &str {
+///         "2024-06-01-000000_backfill_slugs"
+///     }
+///
+///     async fn up(&self, conn: &mut D1Connection) -> migration::Result<()> {
+///         let mut checkpoint = conn.checkpoint(self.version()).await?;
+///         let mut last_id = checkpoint.cursor().map_or(Ok(0), str::parse)?;
+///         loop {
+///             let posts = posts::table
+///                 .filter(posts::id.gt(last_id))
+///                 .order(posts::id)
+///                 .limit(500)
+///                 .load::<Post>(conn)
+///                 .await?;
+///             let Some(last) = posts.last() else { break };
+///             // ... update the chunk ...
+///             last_id = last.id;
+///             checkpoint.save(conn, last_id.to_string()).await?;
+///         }
+///         Ok(())
+///     }
+/// }
+/// ```
+#[async_trait]
+pub trait D1Migration: Send + Sync {
+    /// Version recorded in the migrations table, sorted together with the SQL migrations
+    fn version(&self) -> &str;
+
+    async fn up(&self, conn: &mut D1Connection) -> migration::Result<()>;
+}
+
+/// Where an interrupted [`D1Migration`] left off, saved in [`CHECKPOINTS_TABLE`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct D1Checkpoint {
+    version: String,
+    cursor: Option<String>,
+}
+
+impl D1Checkpoint {
+    /// The last saved cursor, `None` if the migration hasn't saved one yet
+    pub fn cursor(&self) -> Option<&str> {
+        self.cursor.as_deref()
+    }
+
+    /// Saves the position to resume from. Do it after each chunk has been written, so a
+    /// chunk is at worst repeated, never skipped.
+    pub async fn save(&mut self, conn: &mut D1Connection, cursor: impl Into<String>) -> QueryResult<()> {
+        let cursor = cursor.into();
+        diesel::sql_query(format!(
+            "INSERT INTO {CHECKPOINTS_TABLE} (version, cursor) VALUES (?, ?) \
+            ON CONFLICT (version) DO UPDATE SET cursor = excluded.cursor, updated_at = CURRENT_TIMESTAMP"
+        ))
+        .bind::<Text, _>(&self.version)
+        .bind::<Text, _>(&cursor)
+        .execute(conn)
+        .await?;
+
+        self.cursor = Some(cursor);
+        Ok(())
+    }
+}
+
+#[derive(QueryableByName)]
+struct CheckpointRow {
+    #[diesel(sql_type = Text)]
+    cursor: String,
+}
+
+enum Entry {
+    Sql(Box<dyn Migration<D1Backend>>),
+    Code(Box<dyn D1Migration>),
+}
+
+/// SQL migrations of a `MigrationSource` together with [`D1Migration`]s, run in version order
+/// by [`D1Connection::run_migration_set`]
+///
+/// ```ignore
+/// let migrations = D1MigrationSet::new(MIGRATIONS)?.code_migration(BackfillSlugs);
+/// conn.run_migration_set(&migrations).await?;
+/// ```
+pub struct D1MigrationSet {
+    migrations: Vec<(MigrationVersion<'static>, Entry)>,
+}
+
+impl D1MigrationSet {
+    pub fn new<S>(source: S) -> migration::Result<Self>
+    where
+        S: MigrationSource<D1Backend>,
+    {
+        let migrations = source
+            .migrations()?
+            .into_iter()
+            .map(|migration| (migration.name().version().as_owned(), Entry::Sql(migration)))
+            .collect();
+        Ok(D1MigrationSet { migrations })
+    }
+
+    pub fn code_migration(mut self, migration: impl D1Migration + 'static) -> Self {
+        let version = MigrationVersion::from(migration.version().to_owned());
+        self.migrations.push((version, Entry::Code(Box::new(migration))));
+        self
+    }
+
+    /// Versions of every migration in the set, in the order they're run
+    pub fn versions(&self) -> Vec<MigrationVersion<'static>> {
+        let mut versions = self
+            .migrations
+            .iter()
+            .map(|(version, _)| version.as_owned())
+            .collect::<Vec<_>>();
+        versions.sort();
+        versions
+    }
+}
+
+impl D1Connection {
+    /// Loads the checkpoint of the migration with `version`, to resume it where it stopped
+    pub async fn checkpoint(&mut self, version: &str) -> QueryResult<D1Checkpoint> {
+        self.setup_checkpoints_table().await?;
+
+        let rows: Vec<CheckpointRow> =
+            diesel::sql_query(format!("SELECT cursor FROM {CHECKPOINTS_TABLE} WHERE version = ?"))
+                .bind::<Text, _>(version)
+                .load(self)
+                .await?;
+
+        Ok(D1Checkpoint {
+            version: version.to_owned(),
+            cursor: rows.into_iter().next().map(|row| row.cursor),
+        })
+    }
+
+    /// Runs every pending migration of `set` in version order
+    ///
+    /// SQL migrations run atomically as with [`run_pending_migrations`](Self::run_pending_migrations).
+    /// A [`D1Migration`] is recorded, and its checkpoint cleared, once its `up` returns.
+    pub async fn run_migration_set(&mut self, set: &D1MigrationSet) -> migration::Result<Vec<MigrationVersion<'static>>> {
+        let applied = self.applied_migrations().await?;
+        self.setup_checkpoints_table().await?;
+
+        let mut pending = set
+            .migrations
+            .iter()
+            .filter(|(version, _)| !applied.contains(version))
+            .collect::<Vec<_>>();
+        pending.sort_by_key(|(version, _)| version);
+
+        let mut versions = Vec::with_capacity(pending.len());
+        for (version, entry) in pending {
+            match entry {
+                Entry::Sql(migration) => {
+                    self.run_migration(&**migration).await?;
+                },
+                Entry::Code(migration) => {
+                    migration.up(self).await?;
+                    let statements = [
+                        self.migrations_table.insert_sql(version),
+                        format!(
+                            "DELETE FROM {CHECKPOINTS_TABLE} WHERE version = {}",
+                            super::quote_literal(&version.to_string())
+                        ),
+                    ];
+                    self.run_statements(&statements).await?;
+                },
+            }
+            versions.push(version.as_owned());
+        }
+        Ok(versions)
+    }
+
+    async fn setup_checkpoints_table(&mut self) -> QueryResult<()> {
+        self.run_statements(&[format!(
+            "CREATE TABLE IF NOT EXISTS {CHECKPOINTS_TABLE} (\
+                version VARCHAR(50) PRIMARY KEY NOT NULL, \
+                cursor TEXT NOT NULL, \
+                updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP\
+            )"
+        )])
+        .await
+    }
+}
@@ -0,0 +1,303 @@
+use std::fmt;
+
+use diesel::migration::{self, MigrationSource};
+
+use super::SqlRecorder;
+use crate::{backend::D1Backend, utils::split_statements};
+
+/// Longest statement D1 accepts, in bytes
+pub const MAX_STATEMENT_LENGTH: usize = 100_000;
+
+/// PRAGMAs D1 allows, see <https://developers.cloudflare.com/d1/sql-api/sql-statements/>
+const ALLOWED_PRAGMAS: &[&str] = &[
+    "case_sensitive_like",
+    "defer_foreign_keys",
+    "foreign_key_check",
+    "foreign_key_list",
+    "foreign_keys",
+    "ignore_check_constraints",
+    "index_info",
+    "index_list",
+    "index_xinfo",
+    "legacy_alter_table",
+    "optimize",
+    "quick_check",
+    "recursive_triggers",
+    "reverse_unordered_selects",
+    "table_info",
+    "table_list",
+    "table_xinfo",
+];
+
+#[derive(Debug, Copy, Clone, Hash, PartialEq, Eq)]
+pub enum D1LintSeverity {
+    /// D1 rejects the statement
+    Error,
+    /// D1 accepts the statement, but it likely doesn't do what was intended
+    Warning,
+}
+
+/// A statement of a migration that D1 rejects or handles differently than SQLite
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct D1LintIssue {
+    /// The migration the statement is part of, with `(revert)` appended for down migrations.
+    /// `None` for SQL passed to [`lint_sql`].
+    pub migration: Option<String>,
+    pub statement: String,
+    pub severity: D1LintSeverity,
+    pub message: String,
+}
+
+impl fmt::Display for D1LintIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let severity = match self.severity {
+            D1LintSeverity::Error => "error",
+            D1LintSeverity::Warning => "warning",
+        };
+        write!(f, "{severity}: ")?;
+        if let Some(migration) = &self.migration {
+            write!(f, "{migration}: ")?;
+        }
+        write!(f, "{}\n  in statement: {}", self.message, preview(&self.statement))
+    }
+}
+
+/// Checks every statement of `sql` against what D1 supports
+pub fn lint_sql(sql: &str) -> Vec<D1LintIssue> {
+    split_statements(sql)
+        .into_iter()
+        .flat_map(|statement| {
+            lint_statement(&statement)
+                .into_iter()
+                .map(move |(severity, message)| D1LintIssue {
+                    migration: None,
+                    statement: statement.clone(),
+                    severity,
+                    message,
+                })
+        })
+        .collect()
+}
+
+/// Lints both directions of every migration of `source`, e.g. the ones of
+/// `diesel_migrations::embed_migrations!` or [`WranglerMigrations`](super::WranglerMigrations)
+pub fn lint_migrations<S>(source: &S) -> migration::Result<Vec<D1LintIssue>>
+where
+    S: MigrationSource<D1Backend>,
+{
+    let mut issues = Vec::new();
+
+    for migration in source.migrations()? {
+        let name = migration.name().to_string();
+
+        let mut recorder = SqlRecorder::default();
+        migration.run(&mut recorder)?;
+        issues.extend(lint_recorded(recorder, &name));
+
+        // migrations without a down part fail to revert, there's nothing to lint then
+        let mut recorder = SqlRecorder::default();
+        if migration.revert(&mut recorder).is_ok() {
+            issues.extend(lint_recorded(recorder, &format!("{name} (revert)")));
+        }
+    }
+
+    Ok(issues)
+}
+
+fn lint_recorded(recorder: SqlRecorder, migration: &str) -> Vec<D1LintIssue> {
+    recorder
+        .sql
+        .iter()
+        .flat_map(|sql| lint_sql(sql))
+        .map(|issue| D1LintIssue {
+            migration: Some(migration.to_owned()),
+            ..issue
+        })
+        .collect()
+}
+
+fn lint_statement(statement: &str) -> Vec<(D1LintSeverity, String)> {
+    let mut issues = Vec::new();
+    let words = statement
+        .split(|c: char| !(c.is_alphanumeric() || c == '_'))
+        .filter(|word| !word.is_empty())
+        .take(6)
+        .map(str::to_ascii_uppercase)
+        .collect::<Vec<_>>();
+    let words = words.iter().map(String::as_str).collect::<Vec<_>>();
+
+    if statement.len() > MAX_STATEMENT_LENGTH {
+        issues.push((
+            D1LintSeverity::Error,
+            format!(
+                "statement is {} bytes long, D1 only accepts up to {MAX_STATEMENT_LENGTH}; split it up, e.g. into several INSERTs",
+                statement.len()
+            ),
+        ));
+    }
+
+    match words.as_slice() {
+        ["BEGIN", ..] | ["COMMIT", ..] | ["END", ..] | ["ROLLBACK", ..] => issues.push((
+            D1LintSeverity::Error,
+            "D1 doesn't support transaction statements, every migration already runs atomically as a single batch"
+                .to_owned(),
+        )),
+        ["SAVEPOINT", ..] | ["RELEASE", ..] => issues.push((
+            D1LintSeverity::Error,
+            "D1 doesn't support savepoints, every migration already runs atomically as a single batch".to_owned(),
+        )),
+        ["ATTACH", ..] | ["DETACH", ..] => issues.push((
+            D1LintSeverity::Error,
+            "D1 can't attach other databases, each database is a separate binding".to_owned(),
+        )),
+        ["VACUUM", ..] => issues.push((
+            D1LintSeverity::Error,
+            "D1 doesn't allow VACUUM, storage is managed by D1".to_owned(),
+        )),
+        ["PRAGMA", pragma, rest @ ..] => {
+            // `PRAGMA main.table_info(...)`
+            let pragma = match (*pragma, rest) {
+                ("MAIN", [pragma, ..]) => pragma,
+                _ => pragma,
+            };
+            let pragma = pragma.to_ascii_lowercase();
+            if !ALLOWED_PRAGMAS.contains(&pragma.as_str()) {
+                issues.push((D1LintSeverity::Error, format!("D1 doesn't allow `PRAGMA {pragma}`")));
+            } else if pragma == "foreign_keys" && ["OFF", "0", "FALSE", "NO"].iter().any(|value| rest.contains(value)) {
+                issues.push((
+                    D1LintSeverity::Warning,
+                    "turning foreign keys off has no effect inside D1's implicit transaction, use `PRAGMA defer_foreign_keys = on` instead"
+                        .to_owned(),
+                ));
+            }
+        },
+        ["CREATE", "VIRTUAL", "TABLE", ..] => {
+            let module = statement
+                .to_ascii_uppercase()
+                .split_once(" USING ")
+                .map(|(_, module)| module.trim_start().starts_with("FTS5"));
+            if module != Some(true) {
+                issues.push((
+                    D1LintSeverity::Error,
+                    "D1 only supports the FTS5 module for virtual tables".to_owned(),
+                ));
+            }
+        },
+        _ => {},
+    }
+
+    if let ["CREATE", rest @ ..] = words.as_slice() {
+        let name = rest
+            .iter()
+            .skip_while(|word| ["TEMP", "TEMPORARY", "UNIQUE", "VIRTUAL", "TABLE", "INDEX", "VIEW", "TRIGGER"].contains(word))
+            .find(|word| !["IF", "NOT", "EXISTS", "MAIN"].contains(word));
+        if name.is_some_and(|name| name.starts_with("_CF_")) {
+            issues.push((
+                D1LintSeverity::Error,
+                "names starting with `_cf_` are reserved by D1".to_owned(),
+            ));
+        }
+    }
+
+    issues
+}
+
+/// First line of `statement`, cut short for error messages
+fn preview(statement: &str) -> String {
+    let line = statement.lines().next().unwrap_or_default();
+    if line.chars().count() > 80 || statement.lines().nth(1).is_some() {
+        format!("{}...", line.chars().take(80).collect::<String>())
+    } else {
+        line.to_owned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn severities(sql: &str) -> Vec<D1LintSeverity> {
+        lint_sql(sql).into_iter().map(|issue| issue.severity).collect()
+    }
+
+    #[test]
+    fn rejects_transactions_and_savepoints() {
+        for statement in [
+            "BEGIN",
+            "begin transaction",
+            "BEGIN IMMEDIATE TRANSACTION",
+            "COMMIT",
+            "END TRANSACTION",
+            "ROLLBACK",
+            "ROLLBACK TO SAVEPOINT before_insert",
+            "SAVEPOINT before_insert",
+            "RELEASE before_insert",
+        ] {
+            assert_eq!(severities(statement), [D1LintSeverity::Error], "{statement}");
+        }
+        assert_eq!(
+            lint_sql("BEGIN; CREATE TABLE a (id INTEGER); COMMIT;")
+                .into_iter()
+                .map(|issue| issue.statement)
+                .collect::<Vec<_>>(),
+            ["BEGIN", "COMMIT"]
+        );
+    }
+
+    #[test]
+    fn checks_pragmas() {
+        assert!(lint_sql("PRAGMA table_info(users); PRAGMA defer_foreign_keys = on").is_empty());
+        assert!(lint_sql("PRAGMA main.table_info(users); pragma MAIN.index_list('users')").is_empty());
+
+        let issues = lint_sql("PRAGMA main.journal_mode = WAL");
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].message, "D1 doesn't allow `PRAGMA journal_mode`");
+        assert_eq!(severities("PRAGMA writable_schema = 1"), [D1LintSeverity::Error]);
+
+        assert_eq!(severities("PRAGMA foreign_keys = OFF"), [D1LintSeverity::Warning]);
+        assert_eq!(severities("PRAGMA main.foreign_keys = 0"), [D1LintSeverity::Warning]);
+        assert!(lint_sql("PRAGMA foreign_keys = on").is_empty());
+    }
+
+    #[test]
+    fn allows_triggers_with_case() {
+        let sql = "CREATE TRIGGER grade AFTER INSERT ON scores BEGIN \
+            UPDATE scores SET grade = CASE WHEN new.points > 50 THEN 'pass' ELSE 'fail' END; \
+            END; \
+            INSERT INTO scores (points) VALUES (1);";
+        assert!(lint_sql(sql).is_empty());
+    }
+
+    #[test]
+    fn ignores_keywords_in_quotes_and_comments() {
+        assert!(lint_sql("INSERT INTO log VALUES ('BEGIN; VACUUM; PRAGMA journal_mode');").is_empty());
+        assert!(lint_sql("-- VACUUM;\n/* ATTACH 'x' AS y; */ SELECT 1;").is_empty());
+        assert_eq!(severities("SELECT 1; /* ; */ VACUUM"), [D1LintSeverity::Error]);
+    }
+
+    #[test]
+    fn checks_other_statements() {
+        assert_eq!(severities("ATTACH 'other.db' AS other"), [D1LintSeverity::Error]);
+        assert_eq!(severities("CREATE VIRTUAL TABLE docs USING fts4(body)"), [D1LintSeverity::Error]);
+        assert!(lint_sql("CREATE VIRTUAL TABLE docs USING fts5(body)").is_empty());
+        assert_eq!(severities("CREATE TABLE IF NOT EXISTS _cf_kv (id INTEGER)"), [D1LintSeverity::Error]);
+    }
+
+    #[test]
+    fn checks_statement_length() {
+        let insert = |length: usize| {
+            let prefix = "INSERT INTO a VALUES ('";
+            format!("{prefix}{}')", "x".repeat(length - prefix.len() - 2))
+        };
+        assert!(lint_sql(&insert(MAX_STATEMENT_LENGTH)).is_empty());
+
+        let issues = lint_sql(&insert(MAX_STATEMENT_LENGTH + 1));
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].severity, D1LintSeverity::Error);
+        assert!(issues[0].message.starts_with("statement is 100001 bytes long"));
+        // the statement is reported by its first line
+        assert!(issues[0]
+            .to_string()
+            .ends_with(&format!("in statement: INSERT INTO a VALUES ('{}...", "x".repeat(57))));
+    }
+}
@@ -0,0 +1,542 @@
+//! Running diesel migrations against D1
+//!
+//! diesel's `MigrationHarness` needs a synchronous connection, so `D1Connection` gets async
+//! counterparts of its methods instead. They accept any `MigrationSource`, e.g. the one
+//! created by `diesel_migrations::embed_migrations!`:
+//!
+//! ```ignore
+//! const MIGRATIONS: EmbeddedMigrations = embed_migrations!();
+//!
+//! conn.run_pending_migrations(MIGRATIONS).await?;
+//! ```
+//!
+//! To share a database with `wrangler d1 migrations apply`, switch the connection to
+//! [`D1MigrationsTable::Wrangler`] and use [`WranglerMigrations`] as the source.
+
+use std::{any::Any, error::Error, fmt};
+
+use diesel::{
+    connection::{BoxableConnection, SimpleConnection},
+    migration::{self, Migration, MigrationSource, MigrationVersion},
+    QueryResult,
+};
+
+use crate::{backend::D1Backend, prepare, run_batch, utils::split_statements, D1Connection, D1DynamicValue};
+
+mod bookmark;
+mod data;
+mod lint;
+mod verify;
+mod wrangler;
+
+pub use bookmark::{D1MigrationBookmark, BOOKMARKS_TABLE};
+pub use data::{D1Checkpoint, D1Migration, D1MigrationSet, CHECKPOINTS_TABLE};
+pub use lint::{lint_migrations, lint_sql, D1LintIssue, D1LintSeverity, MAX_STATEMENT_LENGTH};
+pub use verify::{D1MigrationCheck, D1MigrationStatus, D1VerifyError};
+pub use wrangler::WranglerMigrations;
+
+/// Table used by diesel to keep track of the migrations that have been run
+pub const MIGRATIONS_TABLE: &str = "__diesel_schema_migrations";
+
+/// Table used by `wrangler d1 migrations` to keep track of the migrations that have been run
+pub const WRANGLER_MIGRATIONS_TABLE: &str = "d1_migrations";
+
+/// Where a [`D1Connection`] records the migrations it has run
+#[derive(Debug, Copy, Clone, Hash, PartialEq, Eq, Default)]
+pub enum D1MigrationsTable {
+    /// diesel's `__diesel_schema_migrations`, keyed by migration version
+    #[default]
+    Diesel,
+    /// Wrangler's `d1_migrations`, keyed by migration file name
+    Wrangler,
+}
+
+impl D1MigrationsTable {
+    fn name(self) -> &'static str {
+        match self {
+            D1MigrationsTable::Diesel => MIGRATIONS_TABLE,
+            D1MigrationsTable::Wrangler => WRANGLER_MIGRATIONS_TABLE,
+        }
+    }
+
+    /// Creates the table if it doesn't exist yet
+    pub fn create_sql(self) -> String {
+        match self {
+            D1MigrationsTable::Diesel => format!(
+                "CREATE TABLE IF NOT EXISTS {MIGRATIONS_TABLE} (\
+                    version VARCHAR(50) PRIMARY KEY NOT NULL, \
+                    run_on TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP\
+                )"
+            ),
+            // the same definition Wrangler creates the table with
+            D1MigrationsTable::Wrangler => format!(
+                "CREATE TABLE IF NOT EXISTS {WRANGLER_MIGRATIONS_TABLE} (\
+                    id INTEGER PRIMARY KEY AUTOINCREMENT, \
+                    name TEXT UNIQUE, \
+                    applied_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP NOT NULL\
+                )"
+            ),
+        }
+    }
+
+    /// Selects the applied versions, newest first
+    pub fn select_sql(self) -> String {
+        match self {
+            D1MigrationsTable::Diesel => {
+                format!("SELECT version FROM {MIGRATIONS_TABLE} ORDER BY version DESC")
+            },
+            D1MigrationsTable::Wrangler => {
+                format!("SELECT name FROM {WRANGLER_MIGRATIONS_TABLE} ORDER BY name DESC")
+            },
+        }
+    }
+
+    /// Records `version` as applied
+    pub fn insert_sql(self, version: &MigrationVersion<'_>) -> String {
+        let version = quote_literal(&version.to_string());
+        match self {
+            D1MigrationsTable::Diesel => format!("INSERT INTO {MIGRATIONS_TABLE} (version) VALUES ({version})"),
+            D1MigrationsTable::Wrangler => format!("INSERT INTO {WRANGLER_MIGRATIONS_TABLE} (name) VALUES ({version})"),
+        }
+    }
+
+    fn delete_sql(self, version: &MigrationVersion<'_>) -> String {
+        let version = quote_literal(&version.to_string());
+        match self {
+            D1MigrationsTable::Diesel => format!("DELETE FROM {MIGRATIONS_TABLE} WHERE version = {version}"),
+            D1MigrationsTable::Wrangler => format!("DELETE FROM {WRANGLER_MIGRATIONS_TABLE} WHERE name = {version}"),
+        }
+    }
+}
+
+/// Always fails, without touching any table, so replaying part of a batch followed by it
+/// rolls the replay back
+const PROBE_FAILURE: &str = "SELECT abs(-9223372036854775808)";
+
+/// What SQLite reports for [`PROBE_FAILURE`]
+const PROBE_MESSAGE: &str = "integer overflow";
+
+/// Returned when a migration couldn't be applied or reverted
+///
+/// The migration's batch was rolled back as a whole.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct D1MigrationError {
+    /// Version of the failing migration
+    pub version: String,
+    /// The error reported by D1
+    pub message: String,
+    /// The statement the batch stopped at. D1 only reports the error, so it's found by
+    /// replaying parts of the batch, and is `None` when that didn't narrow it down.
+    pub statement: Option<String>,
+}
+
+impl fmt::Display for D1MigrationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "migration {} failed: {}", self.version, self.message)?;
+        if let Some(statement) = &self.statement {
+            write!(f, "\n  in statement: {statement}")?;
+        }
+        Ok(())
+    }
+}
+
+impl Error for D1MigrationError {}
+
+/// Stands in for a synchronous connection, so diesel's `Migration::run`/`revert` hand over
+/// their SQL instead of executing it
+#[derive(Default)]
+struct SqlRecorder {
+    sql: Vec<String>,
+}
+
+impl SimpleConnection for SqlRecorder {
+    fn batch_execute(&mut self, query: &str) -> QueryResult<()> {
+        self.sql.push(query.to_owned());
+        Ok(())
+    }
+}
+
+impl BoxableConnection<D1Backend> for SqlRecorder {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+/// Statements `migration` runs when applied, split the way [`D1Connection`] sends them
+///
+/// For tools applying migrations without a Worker binding, e.g. through the REST API.
+pub fn migration_sql(migration: &dyn Migration<D1Backend>) -> migration::Result<Vec<String>> {
+    let mut recorder = SqlRecorder::default();
+    migration.run(&mut recorder)?;
+    Ok(recorder.sql.iter().flat_map(|sql| split_statements(sql)).collect())
+}
+
+/// Statements `migration` reverts with, split the way [`D1Connection`] sends them
+fn revert_sql(migration: &dyn Migration<D1Backend>) -> migration::Result<Vec<String>> {
+    let mut recorder = SqlRecorder::default();
+    migration.revert(&mut recorder)?;
+    Ok(recorder.sql.iter().flat_map(|sql| split_statements(sql)).collect())
+}
+
+/// Bisects the prefixes of a failed batch for the shortest one that fails as well, whose
+/// last statement is the one the batch stopped at
+///
+/// The empty prefix is known to succeed and the whole batch to fail, so a batch of `n`
+/// statements takes about `log2(n)` replays.
+#[derive(Debug)]
+struct FailureSearch {
+    succeeded: usize,
+    failed: usize,
+}
+
+impl FailureSearch {
+    fn new(statements: usize) -> Self {
+        FailureSearch {
+            succeeded: 0,
+            failed: statements,
+        }
+    }
+
+    /// Length of the next prefix to replay, `None` once the failing statement is found
+    fn next_probe(&self) -> Option<usize> {
+        (self.failed > self.succeeded + 1).then(|| (self.succeeded + self.failed) / 2)
+    }
+
+    /// Records how replaying the first `length` statements followed by [`PROBE_FAILURE`]
+    /// failed, `None` if it didn't. Returns `false` when the search has to give up.
+    fn record(&mut self, length: usize, error: Option<&str>) -> bool {
+        match error {
+            Some(message) if message.contains(PROBE_MESSAGE) => self.succeeded = length,
+            Some(_) => self.failed = length,
+            // the probe is meant to fail, give up rather than guess
+            None => return false,
+        }
+        true
+    }
+
+    /// Index of the failing statement, once [`next_probe`](Self::next_probe) returns `None`
+    fn failing_index(&self) -> Option<usize> {
+        self.failed.checked_sub(1)
+    }
+}
+
+impl D1Connection {
+    /// Sets the table the migration methods record applied migrations in, see [`D1MigrationsTable`]
+    pub fn set_migrations_table(&mut self, table: D1MigrationsTable) {
+        self.migrations_table = table;
+    }
+
+    /// Versions of every migration that has been run, newest first
+    pub async fn applied_migrations(&mut self) -> migration::Result<Vec<MigrationVersion<'static>>> {
+        self.setup_migrations_table().await?;
+        self.load_applied_migrations().await
+    }
+
+    /// [`applied_migrations`](Self::applied_migrations), for when the table is known to exist
+    async fn load_applied_migrations(&mut self) -> migration::Result<Vec<MigrationVersion<'static>>> {
+        let result = self
+            .load_dynamic(diesel::sql_query(self.migrations_table.select_sql()))
+            .await?;
+
+        result
+            .rows
+            .into_iter()
+            .map(|row| match row.into_iter().next() {
+                Some(D1DynamicValue::Text(version)) => Ok(MigrationVersion::from(version)),
+                other => Err(format!("unexpected migration version {other:?}").into()),
+            })
+            .collect()
+    }
+
+    /// Migrations of `source` that haven't been run yet, in the order they'd be run
+    pub async fn pending_migrations<S>(
+        &mut self,
+        source: S,
+    ) -> migration::Result<Vec<Box<dyn Migration<D1Backend>>>>
+    where
+        S: MigrationSource<D1Backend>,
+    {
+        let applied = self.applied_migrations().await?;
+        let mut migrations = source.migrations()?;
+        migrations.retain(|migration| !applied.contains(&migration.name().version().as_owned()));
+        migrations.sort_by_key(|migration| migration.name().version().as_owned());
+        Ok(migrations)
+    }
+
+    /// Runs every pending migration of `source`, each one in its own batch
+    pub async fn run_pending_migrations<S>(
+        &mut self,
+        source: S,
+    ) -> migration::Result<Vec<MigrationVersion<'static>>>
+    where
+        S: MigrationSource<D1Backend>,
+    {
+        let pending = self.pending_migrations(source).await?;
+
+        let mut versions = Vec::with_capacity(pending.len());
+        for migration in pending {
+            versions.push(self.run_migration(&*migration).await?);
+        }
+        Ok(versions)
+    }
+
+    /// Runs a single migration and records it as applied, both atomically
+    pub async fn run_migration(
+        &mut self,
+        migration: &dyn Migration<D1Backend>,
+    ) -> migration::Result<MigrationVersion<'static>> {
+        self.setup_migrations_table().await?;
+
+        let version = migration.name().version().as_owned();
+        let mut statements = migration_sql(migration)?;
+        statements.push(self.migrations_table.insert_sql(&version));
+
+        self.apply_migration_sql(&version, &statements).await?;
+        Ok(version)
+    }
+
+    /// Reverts the most recently run migration of `source`
+    pub async fn revert_last_migration<S>(
+        &mut self,
+        source: S,
+    ) -> migration::Result<MigrationVersion<'static>>
+    where
+        S: MigrationSource<D1Backend>,
+    {
+        let applied = self.applied_migrations().await?;
+        let last = applied.first().ok_or("there are no migrations to revert")?;
+
+        let migrations = source.migrations()?;
+        let migration = migrations
+            .iter()
+            .find(|migration| migration.name().version() == *last)
+            .ok_or_else(|| format!("migration {last} was run but isn't part of the migration source"))?;
+
+        self.revert_migration(&**migration).await
+    }
+
+    /// Reverts every migration of `source` that has been run, newest first
+    pub async fn revert_all_migrations<S>(
+        &mut self,
+        source: S,
+    ) -> migration::Result<Vec<MigrationVersion<'static>>>
+    where
+        S: MigrationSource<D1Backend>,
+    {
+        let applied = self.applied_migrations().await?;
+        let mut migrations = source.migrations()?;
+        migrations.retain(|migration| applied.contains(&migration.name().version().as_owned()));
+        migrations.sort_by_key(|migration| std::cmp::Reverse(migration.name().version().as_owned()));
+
+        let mut versions = Vec::with_capacity(migrations.len());
+        for migration in migrations {
+            versions.push(self.revert_migration(&*migration).await?);
+        }
+        Ok(versions)
+    }
+
+    /// Reverts a single migration and removes it from the applied ones, both atomically
+    pub async fn revert_migration(
+        &mut self,
+        migration: &dyn Migration<D1Backend>,
+    ) -> migration::Result<MigrationVersion<'static>> {
+        self.setup_migrations_table().await?;
+
+        let version = migration.name().version().as_owned();
+        let mut statements = revert_sql(migration)?;
+        statements.push(self.migrations_table.delete_sql(&version));
+
+        self.apply_migration_sql(&version, &statements).await?;
+        Ok(version)
+    }
+
+    async fn setup_migrations_table(&mut self) -> QueryResult<()> {
+        let statement = prepare(&self.binding, &self.migrations_table.create_sql())?;
+        run_batch(&self.binding, &[statement]).await
+    }
+
+    /// Sends the migration's statements, ending with its bookkeeping statement, as one batch
+    async fn apply_migration_sql(
+        &mut self,
+        version: &MigrationVersion<'_>,
+        statements: &[String],
+    ) -> Result<(), D1MigrationError> {
+        if let Err(err) = self.run_statements(statements).await {
+            let message = err.to_string();
+            let statement = self
+                .find_failing_statement(statements, &message)
+                .await
+                .map(|index| statements[index].clone());
+            return Err(D1MigrationError {
+                version: version.to_string(),
+                message,
+                statement,
+            });
+        }
+        Ok(())
+    }
+
+    /// Finds the statement a failed batch stopped at by replaying its prefixes, each
+    /// followed by [`PROBE_FAILURE`] so that D1 rolls the replay back
+    ///
+    /// Only runs after a migration failed, and assumes the batch fails the same way again.
+    async fn find_failing_statement(&mut self, statements: &[String], message: &str) -> Option<usize> {
+        // the probe's own failure couldn't be told apart
+        if message.contains(PROBE_MESSAGE) {
+            return None;
+        }
+
+        let mut search = FailureSearch::new(statements.len());
+        while let Some(length) = search.next_probe() {
+            let mut probe = statements[..length].to_vec();
+            probe.push(PROBE_FAILURE.to_owned());
+            let error = self.run_statements(&probe).await.err().map(|err| err.to_string());
+            if !search.record(length, error.as_deref()) {
+                return None;
+            }
+        }
+        search.failing_index()
+    }
+
+    async fn run_statements(&mut self, statements: &[String]) -> QueryResult<()> {
+        let prepared = statements
+            .iter()
+            .map(|statement| prepare(&self.binding, statement))
+            .collect::<QueryResult<Vec<_>>>()?;
+        run_batch(&self.binding, &prepared).await
+    }
+}
+
+fn quote_literal(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "''"))
+}
+
+#[cfg(test)]
+mod tests {
+    use diesel::migration::{MigrationMetadata, MigrationName};
+
+    use super::*;
+
+    #[derive(Debug)]
+    struct SqlMigration {
+        version: &'static str,
+        up: &'static str,
+        down: &'static str,
+    }
+
+    impl Migration<D1Backend> for SqlMigration {
+        fn run(&self, conn: &mut dyn BoxableConnection<D1Backend>) -> migration::Result<()> {
+            conn.batch_execute(self.up)?;
+            Ok(())
+        }
+
+        fn revert(&self, conn: &mut dyn BoxableConnection<D1Backend>) -> migration::Result<()> {
+            conn.batch_execute(self.down)?;
+            Ok(())
+        }
+
+        fn metadata(&self) -> &dyn MigrationMetadata {
+            self
+        }
+
+        fn name(&self) -> &dyn MigrationName {
+            self
+        }
+    }
+
+    impl MigrationMetadata for SqlMigration {}
+
+    impl MigrationName for SqlMigration {
+        fn version(&self) -> MigrationVersion<'_> {
+            MigrationVersion::from(self.version)
+        }
+    }
+
+    impl fmt::Display for SqlMigration {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.write_str(self.version)
+        }
+    }
+
+    const USERS: SqlMigration = SqlMigration {
+        version: "2024-01-01-000000",
+        up: "CREATE TABLE users (id INTEGER PRIMARY KEY);\nCREATE INDEX users_id ON users (id);",
+        down: "DROP INDEX users_id;\nDROP TABLE users;",
+    };
+
+    /// Replays `statements` the way D1 does, failing at `failing` with `message`
+    fn find_failing(statements: usize, failing: usize, message: &str) -> (Option<usize>, usize) {
+        let mut search = FailureSearch::new(statements);
+        let mut replays = 0;
+        while let Some(length) = search.next_probe() {
+            replays += 1;
+            let error = if failing < length { message } else { "D1_ERROR: integer overflow: SQLITE_ERROR" };
+            assert!(search.record(length, Some(error)));
+        }
+        (search.failing_index(), replays)
+    }
+
+    #[test]
+    fn records_the_statements_of_migrations() {
+        assert_eq!(
+            migration_sql(&USERS).unwrap(),
+            ["CREATE TABLE users (id INTEGER PRIMARY KEY)", "CREATE INDEX users_id ON users (id)"]
+        );
+        assert_eq!(revert_sql(&USERS).unwrap(), ["DROP INDEX users_id", "DROP TABLE users"]);
+    }
+
+    #[test]
+    fn wrangler_migrations_cannot_be_reverted() {
+        let source = WranglerMigrations::from_static(&[("0001_users.sql", "CREATE TABLE users (id INTEGER);")]);
+        let migrations = source.migrations().unwrap();
+
+        assert_eq!(migration_sql(&*migrations[0]).unwrap(), ["CREATE TABLE users (id INTEGER)"]);
+        assert_eq!(
+            revert_sql(&*migrations[0]).unwrap_err().to_string(),
+            "Wrangler migration 0001_users.sql has no down migration to revert"
+        );
+    }
+
+    #[test]
+    fn finds_every_failing_statement() {
+        for statements in 1..=40 {
+            let most_replays = (statements as f64).log2().ceil() as usize;
+            for failing in 0..statements {
+                let (found, replays) = find_failing(statements, failing, "D1_ERROR: no such table: users");
+                assert_eq!(found, Some(failing), "{failing} of {statements}");
+                assert!(replays <= most_replays, "{replays} replays for {statements} statements");
+            }
+        }
+    }
+
+    #[test]
+    fn gives_up_when_a_replay_succeeds() {
+        let mut search = FailureSearch::new(4);
+        let length = search.next_probe().unwrap();
+        assert!(!search.record(length, None));
+    }
+
+    #[test]
+    fn reports_the_failing_statement() {
+        let error = D1MigrationError {
+            version: USERS.version.to_owned(),
+            message: "D1_ERROR: index users_id already exists".to_owned(),
+            statement: Some("CREATE INDEX users_id ON users (id)".to_owned()),
+        };
+        assert_eq!(
+            error.to_string(),
+            "migration 2024-01-01-000000 failed: D1_ERROR: index users_id already exists\n  \
+             in statement: CREATE INDEX users_id ON users (id)"
+        );
+
+        let error = D1MigrationError { statement: None, ..error };
+        assert_eq!(
+            error.to_string(),
+            "migration 2024-01-01-000000 failed: D1_ERROR: index users_id already exists"
+        );
+    }
+}
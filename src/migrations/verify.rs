@@ -0,0 +1,283 @@
+use std::{error::Error, fmt, sync::Mutex};
+
+use diesel::migration::{MigrationSource, MigrationVersion};
+
+use super::D1MigrationSet;
+use crate::{backend::D1Backend, D1Connection, D1DynamicValue};
+
+/// How the migrations a Worker was built with compare to the ones the database has applied,
+/// see [`D1Connection::verify_migrations`]
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct D1MigrationStatus {
+    /// Embedded migrations the database hasn't applied yet
+    pub pending: Vec<MigrationVersion<'static>>,
+    /// Applied migrations the Worker doesn't know about, e.g. while an older version of it is
+    /// still deployed
+    pub unknown: Vec<MigrationVersion<'static>>,
+}
+
+impl D1MigrationStatus {
+    /// Compares the `embedded` versions, sorted, with the `applied` ones, newest first
+    fn new(embedded: &[MigrationVersion<'static>], applied: &[MigrationVersion<'static>]) -> Self {
+        D1MigrationStatus {
+            pending: embedded
+                .iter()
+                .filter(|version| !applied.contains(version))
+                .map(MigrationVersion::as_owned)
+                .collect(),
+            unknown: applied
+                .iter()
+                .rev()
+                .filter(|version| !embedded.contains(version))
+                .map(MigrationVersion::as_owned)
+                .collect(),
+        }
+    }
+}
+
+// `MigrationVersion` is only cloned through `as_owned`
+impl Clone for D1MigrationStatus {
+    fn clone(&self) -> Self {
+        let clone = |versions: &[MigrationVersion<'static>]| versions.iter().map(MigrationVersion::as_owned).collect();
+        D1MigrationStatus {
+            pending: clone(&self.pending),
+            unknown: clone(&self.unknown),
+        }
+    }
+}
+
+impl fmt::Display for D1MigrationStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let list = |versions: &[MigrationVersion<'static>]| {
+            versions.iter().map(ToString::to_string).collect::<Vec<_>>().join(", ")
+        };
+
+        if self.pending.is_empty() {
+            write!(f, "all migrations have been applied")?;
+        } else {
+            write!(f, "the database is missing migrations: {}", list(&self.pending))?;
+        }
+        if !self.unknown.is_empty() {
+            write!(f, " (it has applied unknown migrations: {})", list(&self.unknown))?;
+        }
+        Ok(())
+    }
+}
+
+/// Returned by [`D1Connection::verify_migrations`] and [`D1MigrationCheck`]
+#[derive(Debug)]
+pub enum D1VerifyError {
+    /// Some embedded migrations haven't been applied
+    Pending(D1MigrationStatus),
+    /// The migrations or the applied versions couldn't be read
+    Read(Box<dyn Error + Send + Sync>),
+}
+
+impl fmt::Display for D1VerifyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            D1VerifyError::Pending(status) => write!(f, "{status}"),
+            D1VerifyError::Read(err) => write!(f, "couldn't verify migrations: {err}"),
+        }
+    }
+}
+
+impl Error for D1VerifyError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            D1VerifyError::Pending(_) => None,
+            D1VerifyError::Read(err) => Some(&**err),
+        }
+    }
+}
+
+/// Remembers a successful [`D1Connection::verify_migrations`] for the lifetime of the isolate
+///
+/// The embedded migrations don't change while an isolate lives, so once they've all been
+/// applied there's no need to read the migrations table on every request. Failed checks
+/// aren't remembered, the next request checks again.
+///
+/// ```ignore
+/// static MIGRATIONS_CHECK: D1MigrationCheck = D1MigrationCheck::new();
+///
+/// if let Err(err) = MIGRATIONS_CHECK.verify(&mut conn, MIGRATIONS).await {
+///     return Response::error(err.to_string(), 503);
+/// }
+/// ```
+#[derive(Debug, Default)]
+pub struct D1MigrationCheck {
+    /// The embedded versions that were verified, and what the check returned
+    verified: Mutex<Option<(Vec<MigrationVersion<'static>>, D1MigrationStatus)>>,
+}
+
+impl D1MigrationCheck {
+    pub const fn new() -> Self {
+        D1MigrationCheck {
+            verified: Mutex::new(None),
+        }
+    }
+
+    /// [`D1Connection::verify_migrations`], unless `source` has been verified before
+    pub async fn verify<S>(&self, conn: &mut D1Connection, source: S) -> Result<D1MigrationStatus, D1VerifyError>
+    where
+        S: MigrationSource<D1Backend>,
+    {
+        self.verify_versions(conn, embedded_versions(source)?).await
+    }
+
+    /// [`verify`](Self::verify) for a [`D1MigrationSet`]
+    pub async fn verify_set(
+        &self,
+        conn: &mut D1Connection,
+        set: &D1MigrationSet,
+    ) -> Result<D1MigrationStatus, D1VerifyError> {
+        self.verify_versions(conn, set.versions()).await
+    }
+
+    async fn verify_versions(
+        &self,
+        conn: &mut D1Connection,
+        embedded: Vec<MigrationVersion<'static>>,
+    ) -> Result<D1MigrationStatus, D1VerifyError> {
+        if let Some(status) = self.cached(&embedded) {
+            return Ok(status);
+        }
+
+        let status = conn.verify_versions(&embedded).await?;
+        self.store(embedded, status.clone());
+        Ok(status)
+    }
+
+    fn cached(&self, embedded: &[MigrationVersion<'static>]) -> Option<D1MigrationStatus> {
+        let verified = self.verified.lock().unwrap_or_else(|err| err.into_inner());
+        match &*verified {
+            Some((versions, status)) if versions == embedded => Some(status.clone()),
+            _ => None,
+        }
+    }
+
+    fn store(&self, embedded: Vec<MigrationVersion<'static>>, status: D1MigrationStatus) {
+        let mut verified = self.verified.lock().unwrap_or_else(|err| err.into_inner());
+        *verified = Some((embedded, status));
+    }
+}
+
+/// Versions of `source`, sorted
+fn embedded_versions<S>(source: S) -> Result<Vec<MigrationVersion<'static>>, D1VerifyError>
+where
+    S: MigrationSource<D1Backend>,
+{
+    let mut versions = source
+        .migrations()
+        .map_err(D1VerifyError::Read)?
+        .iter()
+        .map(|migration| migration.name().version().as_owned())
+        .collect::<Vec<_>>();
+    versions.sort();
+    Ok(versions)
+}
+
+impl D1Connection {
+    /// Checks that every migration of `source` has been applied, without changing the database
+    ///
+    /// Fails with [`D1VerifyError::Pending`] if some are pending. Unknown migrations alone don't
+    /// fail the check, they're returned for logging. It costs one or two reads, use a
+    /// [`D1MigrationCheck`] to only pay for them until the check first succeeds.
+    pub async fn verify_migrations<S>(&mut self, source: S) -> Result<D1MigrationStatus, D1VerifyError>
+    where
+        S: MigrationSource<D1Backend>,
+    {
+        let embedded = embedded_versions(source)?;
+        self.verify_versions(&embedded).await
+    }
+
+    /// [`verify_migrations`](Self::verify_migrations) for a [`D1MigrationSet`]
+    pub async fn verify_migration_set(&mut self, set: &D1MigrationSet) -> Result<D1MigrationStatus, D1VerifyError> {
+        self.verify_versions(&set.versions()).await
+    }
+
+    async fn verify_versions(
+        &mut self,
+        embedded: &[MigrationVersion<'static>],
+    ) -> Result<D1MigrationStatus, D1VerifyError> {
+        let table_exists = self
+            .migrations_table_exists()
+            .await
+            .map_err(|err| D1VerifyError::Read(err.into()))?;
+        let applied = match table_exists {
+            true => self.load_applied_migrations().await.map_err(D1VerifyError::Read)?,
+            // nothing has been applied yet, and creating the table would be a write
+            false => Vec::new(),
+        };
+
+        let status = D1MigrationStatus::new(embedded, &applied);
+        if status.pending.is_empty() {
+            Ok(status)
+        } else {
+            Err(D1VerifyError::Pending(status))
+        }
+    }
+
+    async fn migrations_table_exists(&mut self) -> diesel::QueryResult<bool> {
+        let result = self
+            .load_dynamic(
+                diesel::sql_query("SELECT COUNT(*) FROM sqlite_master WHERE type = 'table' AND name = ?")
+                    .bind::<diesel::sql_types::Text, _>(self.migrations_table.name()),
+            )
+            .await?;
+
+        let count = result.rows.first().and_then(|row| row.first());
+        Ok(matches!(count, Some(D1DynamicValue::Integer(count)) if *count > 0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::migrations::WranglerMigrations;
+
+    fn versions(versions: &[&'static str]) -> Vec<MigrationVersion<'static>> {
+        versions.iter().map(|version| MigrationVersion::from(*version)).collect()
+    }
+
+    #[test]
+    fn compares_embedded_and_applied_versions() {
+        let status = D1MigrationStatus::new(&versions(&["0001", "0002", "0003"]), &versions(&["0004", "0002", "0001"]));
+        assert_eq!(
+            status,
+            D1MigrationStatus {
+                pending: versions(&["0003"]),
+                unknown: versions(&["0004"]),
+            }
+        );
+
+        let err = D1VerifyError::Pending(status);
+        assert_eq!(
+            err.to_string(),
+            "the database is missing migrations: 0003 (it has applied unknown migrations: 0004)"
+        );
+        assert!(matches!(err, D1VerifyError::Pending(status) if status.pending == versions(&["0003"])));
+    }
+
+    #[test]
+    fn sorts_embedded_versions() {
+        let source = WranglerMigrations::from_static(&[("0002_posts.sql", ""), ("0001_users.sql", "")]);
+        assert_eq!(embedded_versions(source).unwrap(), versions(&["0001_users.sql", "0002_posts.sql"]));
+    }
+
+    #[test]
+    fn remembers_the_verified_source() {
+        static CHECK: D1MigrationCheck = D1MigrationCheck::new();
+        let embedded = versions(&["0001", "0002"]);
+        assert_eq!(CHECK.cached(&embedded), None);
+
+        let status = D1MigrationStatus {
+            pending: Vec::new(),
+            unknown: versions(&["0003"]),
+        };
+        CHECK.store(versions(&["0001", "0002"]), status.clone());
+        assert_eq!(CHECK.cached(&embedded), Some(status));
+        // a different source is verified again
+        assert_eq!(CHECK.cached(&versions(&["0001"])), None);
+    }
+}
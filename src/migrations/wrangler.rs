@@ -0,0 +1,106 @@
+use std::{borrow::Cow, fmt, path::Path};
+
+use diesel::{
+    connection::BoxableConnection,
+    migration::{self, Migration, MigrationMetadata, MigrationName, MigrationSource, MigrationVersion},
+};
+
+use crate::backend::D1Backend;
+
+/// Migrations laid out the way `wrangler d1 migrations` expects them: a directory of
+/// `NNNN_name.sql` files, each holding the SQL to apply. There are no down migrations.
+///
+/// Use it together with [`D1MigrationsTable::Wrangler`](super::D1MigrationsTable::Wrangler), so
+/// applied migrations are recorded by file name in `d1_migrations` like Wrangler does.
+#[derive(Debug, Clone, Default)]
+pub struct WranglerMigrations {
+    migrations: Vec<WranglerMigration>,
+}
+
+impl WranglerMigrations {
+    /// Migrations compiled into the binary, as `(file name, SQL)` pairs. This is what
+    /// `embed_wrangler_migrations!` expands to, and the only option inside a Worker.
+    pub fn from_static(migrations: &'static [(&'static str, &'static str)]) -> Self {
+        WranglerMigrations {
+            migrations: migrations
+                .iter()
+                .map(|(name, sql)| WranglerMigration {
+                    name: Cow::Borrowed(name),
+                    sql: Cow::Borrowed(sql),
+                })
+                .collect(),
+        }
+    }
+
+    /// Reads every `.sql` file in `dir`, for tools running outside of Workers
+    pub fn from_dir(dir: impl AsRef<Path>) -> std::io::Result<Self> {
+        let mut migrations = Vec::new();
+        for entry in std::fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.extension().is_some_and(|extension| extension == "sql") && path.is_file() {
+                let name = path.file_name().unwrap_or_default().to_string_lossy().into_owned();
+                let sql = std::fs::read_to_string(&path)?;
+                migrations.push(WranglerMigration {
+                    name: Cow::Owned(name),
+                    sql: Cow::Owned(sql),
+                });
+            }
+        }
+
+        Ok(WranglerMigrations { migrations })
+    }
+}
+
+impl MigrationSource<D1Backend> for WranglerMigrations {
+    fn migrations(&self) -> migration::Result<Vec<Box<dyn Migration<D1Backend>>>> {
+        let mut migrations = self.migrations.clone();
+        // Wrangler applies them in file name order
+        migrations.sort_by(|a, b| a.name.cmp(&b.name));
+
+        Ok(migrations
+            .into_iter()
+            .map(|migration| Box::new(migration) as Box<dyn Migration<D1Backend>>)
+            .collect())
+    }
+}
+
+/// A single file of a [`WranglerMigrations`] directory
+#[derive(Debug, Clone)]
+struct WranglerMigration {
+    name: Cow<'static, str>,
+    sql: Cow<'static, str>,
+}
+
+impl Migration<D1Backend> for WranglerMigration {
+    fn run(&self, conn: &mut dyn BoxableConnection<D1Backend>) -> migration::Result<()> {
+        conn.batch_execute(&self.sql)?;
+        Ok(())
+    }
+
+    fn revert(&self, _conn: &mut dyn BoxableConnection<D1Backend>) -> migration::Result<()> {
+        Err(format!("Wrangler migration {} has no down migration to revert", self.name).into())
+    }
+
+    fn metadata(&self) -> &dyn MigrationMetadata {
+        self
+    }
+
+    fn name(&self) -> &dyn MigrationName {
+        self
+    }
+}
+
+impl MigrationMetadata for WranglerMigration {}
+
+impl MigrationName for WranglerMigration {
+    /// The whole file name, that's what Wrangler stores in `d1_migrations`
+    fn version(&self) -> MigrationVersion<'_> {
+        MigrationVersion::from(self.name.as_ref())
+    }
+}
+
+impl fmt::Display for WranglerMigration {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.name)
+    }
+}
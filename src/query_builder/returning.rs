@@ -1,9 +1,3 @@
-use diesel::backend::Backend;
-use diesel::query_builder::ReturningClause;
-use diesel::query_builder::{AstPass, QueryFragment};
-use diesel::result::QueryResult;
-use crate::backend::{D1Backend, SqliteReturningClause};
-
 // impl<Expr> QueryFragment<D1Backend, SqliteReturningClause> for ReturningClause<Expr>
 // where
 //     Expr: QueryFragment<D1Backend>,
@@ -0,0 +1,327 @@
+use diesel::{
+    helper_types::{Asc, Desc},
+    query_builder::{AsQuery, AstPass, Query, QueryFragment, QueryId},
+    sql_types::BigInt,
+    Column, QueryResult,
+};
+
+use crate::backend::D1Backend;
+
+/// `UNION`, `UNION ALL`, `INTERSECT` and `EXCEPT` for D1
+///
+/// diesel's `CombineDsl` only renders for backends implementing a marker trait it keeps
+/// private, so these are separate methods. Both sides are wrapped in `SELECT * FROM (...)`,
+/// as SQLite doesn't allow parentheses around the parts of a compound select, which also
+/// lets each side keep its own `ORDER BY` and `LIMIT`.
+///
+/// ```ignore
+/// let names = users::table
+///     .select(users::name)
+///     .d1_union(admins::table.select(admins::name))
+///     .order_by(users::name.asc())
+///     .limit(10)
+///     .load::<String>(&mut conn)
+///     .await?;
+/// ```
+pub trait D1CombineDsl: AsQuery + Sized {
+    fn d1_union<Rhs>(self, rhs: Rhs) -> D1CombinationClause<D1Union, Self::Query, Rhs::Query>
+    where
+        Rhs: AsQuery<SqlType = Self::SqlType>,
+    {
+        D1CombinationClause::new(self.as_query(), rhs.as_query())
+    }
+
+    fn d1_union_all<Rhs>(self, rhs: Rhs) -> D1CombinationClause<D1UnionAll, Self::Query, Rhs::Query>
+    where
+        Rhs: AsQuery<SqlType = Self::SqlType>,
+    {
+        D1CombinationClause::new(self.as_query(), rhs.as_query())
+    }
+
+    fn d1_intersect<Rhs>(self, rhs: Rhs) -> D1CombinationClause<D1Intersect, Self::Query, Rhs::Query>
+    where
+        Rhs: AsQuery<SqlType = Self::SqlType>,
+    {
+        D1CombinationClause::new(self.as_query(), rhs.as_query())
+    }
+
+    fn d1_except<Rhs>(self, rhs: Rhs) -> D1CombinationClause<D1Except, Self::Query, Rhs::Query>
+    where
+        Rhs: AsQuery<SqlType = Self::SqlType>,
+    {
+        D1CombinationClause::new(self.as_query(), rhs.as_query())
+    }
+}
+
+impl<T: AsQuery> D1CombineDsl for T {}
+
+macro_rules! combinators {
+    ($($(#[$meta:meta])* $name:ident => $sql:literal,)*) => {
+        $(
+            $(#[$meta])*
+            #[derive(Debug, Copy, Clone, Default, QueryId)]
+            pub struct $name;
+
+            impl QueryFragment<D1Backend> for $name {
+                fn walk_ast<'b>(&'b self, mut out: AstPass<'_, 'b, D1Backend>) -> QueryResult<()> {
+                    out.push_sql($sql);
+                    Ok(())
+                }
+            }
+        )*
+    };
+}
+
+combinators! {
+    /// Rows of either query, without duplicates
+    D1Union => " UNION ",
+    /// Rows of either query, keeping duplicates
+    D1UnionAll => " UNION ALL ",
+    /// Rows of the first query that the second one returns as well
+    D1Intersect => " INTERSECT ",
+    /// Rows of the first query that the second one doesn't return
+    D1Except => " EXCEPT ",
+}
+
+/// Two queries combined with one of the [`D1CombineDsl`] methods
+#[derive(Debug, Clone)]
+#[must_use = "Queries are only executed when calling `load`, `get_result` or similar."]
+pub struct D1CombinationClause<Combinator, Source, Rhs, Order = ()> {
+    combinator: Combinator,
+    source: Source,
+    rhs: Rhs,
+    order: Option<Order>,
+    limit: Option<i64>,
+    offset: Option<i64>,
+}
+
+impl<Combinator: Default, Source, Rhs> D1CombinationClause<Combinator, Source, Rhs> {
+    fn new(source: Source, rhs: Rhs) -> Self {
+        D1CombinationClause {
+            combinator: Combinator::default(),
+            source,
+            rhs,
+            order: None,
+            limit: None,
+            offset: None,
+        }
+    }
+}
+
+impl<Combinator, Source, Rhs, Order> D1CombinationClause<Combinator, Source, Rhs, Order> {
+    /// Orders the combined rows, see [`D1ResultOrder`]
+    pub fn order_by<O: D1ResultOrder>(self, order: O) -> D1CombinationClause<Combinator, Source, Rhs, O> {
+        D1CombinationClause {
+            combinator: self.combinator,
+            source: self.source,
+            rhs: self.rhs,
+            order: Some(order),
+            limit: self.limit,
+            offset: self.offset,
+        }
+    }
+
+    /// Limits the number of combined rows
+    pub fn limit(mut self, limit: i64) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    /// Skips the first `offset` combined rows
+    pub fn offset(mut self, offset: i64) -> Self {
+        self.offset = Some(offset);
+        self
+    }
+}
+
+impl<Combinator, Source, Rhs, Order> Query for D1CombinationClause<Combinator, Source, Rhs, Order>
+where
+    Source: Query,
+    Rhs: Query<SqlType = Source::SqlType>,
+{
+    type SqlType = Source::SqlType;
+}
+
+impl<Combinator, Source, Rhs, Order> QueryId for D1CombinationClause<Combinator, Source, Rhs, Order> {
+    type QueryId = ();
+
+    // the SQL depends on whether there's a limit and offset
+    const HAS_STATIC_QUERY_ID: bool = false;
+}
+
+impl<Combinator, Source, Rhs, Order> QueryFragment<D1Backend> for D1CombinationClause<Combinator, Source, Rhs, Order>
+where
+    Combinator: QueryFragment<D1Backend>,
+    Source: QueryFragment<D1Backend>,
+    Rhs: QueryFragment<D1Backend>,
+    Order: D1ResultOrder,
+{
+    fn walk_ast<'b>(&'b self, mut out: AstPass<'_, 'b, D1Backend>) -> QueryResult<()> {
+        out.push_sql("SELECT * FROM (");
+        self.source.walk_ast(out.reborrow())?;
+        out.push_sql(")");
+        self.combinator.walk_ast(out.reborrow())?;
+        out.push_sql("SELECT * FROM (");
+        self.rhs.walk_ast(out.reborrow())?;
+        out.push_sql(")");
+
+        if let Some(order) = &self.order {
+            out.push_sql(" ORDER BY ");
+            order.walk_result_order(out.reborrow())?;
+        }
+        match (&self.limit, &self.offset) {
+            (Some(limit), offset) => {
+                out.push_sql(" LIMIT ");
+                out.push_bind_param::<BigInt, _>(limit)?;
+                if let Some(offset) = offset {
+                    out.push_sql(" OFFSET ");
+                    out.push_bind_param::<BigInt, _>(offset)?;
+                }
+            },
+            // see the `LimitOffsetClause` impls
+            (None, Some(offset)) => {
+                out.push_sql(" LIMIT -1 OFFSET ");
+                out.push_bind_param::<BigInt, _>(offset)?;
+            },
+            (None, None) => {},
+        }
+        Ok(())
+    }
+}
+
+/// An `ORDER BY` term of a [`D1CombinationClause`]
+///
+/// SQLite only orders compound selects by their result columns, which are named after the
+/// columns of the first query. Terms are `column.asc()` or `column.desc()`, or tuples of
+/// them, and render as the bare column name.
+pub trait D1ResultOrder {
+    fn walk_result_order<'b>(&'b self, out: AstPass<'_, 'b, D1Backend>) -> QueryResult<()>;
+}
+
+impl D1ResultOrder for () {
+    fn walk_result_order<'b>(&'b self, _out: AstPass<'_, 'b, D1Backend>) -> QueryResult<()> {
+        Ok(())
+    }
+}
+
+impl<C: Column> D1ResultOrder for Asc<C> {
+    fn walk_result_order<'b>(&'b self, mut out: AstPass<'_, 'b, D1Backend>) -> QueryResult<()> {
+        out.push_identifier(C::NAME)?;
+        out.push_sql(" ASC");
+        Ok(())
+    }
+}
+
+impl<C: Column> D1ResultOrder for Desc<C> {
+    fn walk_result_order<'b>(&'b self, mut out: AstPass<'_, 'b, D1Backend>) -> QueryResult<()> {
+        out.push_identifier(C::NAME)?;
+        out.push_sql(" DESC");
+        Ok(())
+    }
+}
+
+macro_rules! tuple_result_order {
+    ($first:ident $(, $rest:ident)*) => {
+        impl<$first: D1ResultOrder, $($rest: D1ResultOrder),*> D1ResultOrder for ($first, $($rest,)*) {
+            #[allow(non_snake_case)]
+            fn walk_result_order<'b>(&'b self, mut out: AstPass<'_, 'b, D1Backend>) -> QueryResult<()> {
+                let ($first, $($rest,)*) = self;
+                $first.walk_result_order(out.reborrow())?;
+                $(
+                    out.push_sql(", ");
+                    $rest.walk_result_order(out.reborrow())?;
+                )*
+                Ok(())
+            }
+        }
+    };
+}
+
+tuple_result_order!(A);
+tuple_result_order!(A, B);
+tuple_result_order!(A, B, C);
+tuple_result_order!(A, B, C, D);
+tuple_result_order!(A, B, C, D, E);
+tuple_result_order!(A, B, C, D, E, F);
+tuple_result_order!(A, B, C, D, E, F, G);
+tuple_result_order!(A, B, C, D, E, F, G, H);
+
+#[cfg(test)]
+mod tests {
+    use diesel::{debug_query, prelude::*};
+
+    use super::*;
+
+    diesel::table! {
+        users (id) {
+            id -> Integer,
+            name -> Text,
+        }
+    }
+
+    diesel::table! {
+        admins (id) {
+            id -> Integer,
+            name -> Text,
+        }
+    }
+
+    #[test]
+    fn renders_combinators() {
+        let query = users::table
+            .select(users::name)
+            .d1_union(admins::table.select(admins::name));
+        assert_eq!(
+            debug_query::<D1Backend, _>(&query).to_string(),
+            "SELECT * FROM (SELECT `users`.`name` FROM `users`) UNION \
+            SELECT * FROM (SELECT `admins`.`name` FROM `admins`) -- binds: []"
+        );
+
+        let query = users::table.select(users::id).d1_union_all(admins::table.select(admins::id));
+        assert!(debug_query::<D1Backend, _>(&query).to_string().contains(") UNION ALL SELECT"));
+        let query = users::table.select(users::id).d1_intersect(admins::table.select(admins::id));
+        assert!(debug_query::<D1Backend, _>(&query).to_string().contains(") INTERSECT SELECT"));
+        let query = users::table.select(users::id).d1_except(admins::table.select(admins::id));
+        assert!(debug_query::<D1Backend, _>(&query).to_string().contains(") EXCEPT SELECT"));
+    }
+
+    #[test]
+    fn keeps_each_side_ordered_and_limited() {
+        let query = users::table
+            .select(users::name)
+            .filter(users::id.gt(10))
+            .order_by(users::id.desc())
+            .limit(5)
+            .d1_union(admins::table.select(admins::name));
+        assert_eq!(
+            debug_query::<D1Backend, _>(&query).to_string(),
+            "SELECT * FROM (SELECT `users`.`name` FROM `users` WHERE (`users`.`id` > ?) \
+            ORDER BY `users`.`id` DESC LIMIT ?) UNION \
+            SELECT * FROM (SELECT `admins`.`name` FROM `admins`) -- binds: [10, 5]"
+        );
+    }
+
+    #[test]
+    fn orders_and_limits_the_combined_rows() {
+        let query = users::table
+            .select((users::id, users::name))
+            .d1_union(admins::table.select((admins::id, admins::name)))
+            .order_by((users::name.asc(), users::id.desc()))
+            .limit(10)
+            .offset(20);
+        assert_eq!(
+            debug_query::<D1Backend, _>(&query).to_string(),
+            "SELECT * FROM (SELECT `users`.`id`, `users`.`name` FROM `users`) UNION \
+            SELECT * FROM (SELECT `admins`.`id`, `admins`.`name` FROM `admins`) \
+            ORDER BY `name` ASC, `id` DESC LIMIT ? OFFSET ? -- binds: [10, 20]"
+        );
+
+        let query = users::table
+            .select(users::name)
+            .d1_except(admins::table.select(admins::name))
+            .offset(3);
+        assert!(debug_query::<D1Backend, _>(&query)
+            .to_string()
+            .ends_with("FROM `admins`) LIMIT -1 OFFSET ? -- binds: [3]"));
+    }
+}
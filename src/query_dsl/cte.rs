@@ -0,0 +1,432 @@
+use std::marker::PhantomData;
+
+use diesel::{
+    query_builder::{AsQuery, AstPass, Query, QueryFragment, QueryId},
+    Column, QueryResult, Table,
+};
+
+use super::D1TableName;
+use crate::backend::D1Backend;
+
+/// Starts a `WITH` clause defining `table` as `query`
+///
+/// The common table expression is declared with `table!` like a real table, which is how the
+/// main query selects from it and joins it with other tables (add it to
+/// `allow_tables_to_appear_in_same_query!`, and `joinable!` where it has foreign keys).
+/// Its columns are named after the ones of `table`, and `query` has to select matching SQL
+/// types.
+///
+/// ```ignore
+/// table! {
+///     recent_posts (id) {
+///         id -> Integer,
+///         author_id -> Integer,
+///     }
+/// }
+///
+/// let authors = with(recent_posts::table, posts::table.select((posts::id, posts::author_id)).filter(posts::created_at.gt(since)))
+///     .query(recent_posts::table.inner_join(users::table).select(users::name).distinct())
+///     .load::<String>(&mut conn)
+///     .await?;
+/// ```
+pub fn with<T, Q>(table: T, query: Q) -> D1With<((), D1Cte<T, Q::Query>)>
+where
+    T: Table,
+    Q: AsQuery<SqlType = T::SqlType>,
+{
+    D1With {
+        ctes: ((), D1Cte::new(table, query.as_query())),
+        recursive: false,
+    }
+}
+
+/// Starts a `WITH RECURSIVE` clause defining `table` as `anchor UNION ALL step`
+///
+/// `step` selects from `table` itself, and runs on the rows the previous round added
+/// until it returns none. As SQLite requires, `table` can't be used in subqueries of `step`,
+/// and neither side can have its own `ORDER BY` or `LIMIT`.
+///
+/// ```ignore
+/// table! {
+///     folder_tree (id) {
+///         id -> Integer,
+///         depth -> Integer,
+///     }
+/// }
+///
+/// let anchor = folders::table.filter(folders::id.eq(root)).select((folders::id, 0.into_sql::<Integer>()));
+/// let step = folders::table
+///     .inner_join(folder_tree::table.on(folders::parent_id.eq(folder_tree::id.nullable())))
+///     .select((folders::id, folder_tree::depth + 1));
+///
+/// let subtree = with_recursive(folder_tree::table, anchor, step)
+///     .query(folder_tree::table.inner_join(folders::table.on(folders::id.eq(folder_tree::id))).select((folders::name, folder_tree::depth)))
+///     .load::<(String, i32)>(&mut conn)
+///     .await?;
+/// ```
+#[allow(clippy::type_complexity)]
+pub fn with_recursive<T, A, S>(
+    table: T,
+    anchor: A,
+    step: S,
+) -> D1With<((), D1Cte<T, D1RecursiveQuery<A::Query, S::Query>>)>
+where
+    T: Table,
+    A: AsQuery<SqlType = T::SqlType>,
+    S: AsQuery<SqlType = T::SqlType>,
+{
+    D1With {
+        ctes: ((), D1Cte::new(table, D1RecursiveQuery::new(anchor.as_query(), step.as_query()))),
+        recursive: true,
+    }
+}
+
+/// A `WITH` clause waiting for the query it prefixes, see [`with`] and [`with_recursive`]
+#[derive(Debug, Clone)]
+#[must_use = "Queries are only executed when calling `load`, `get_result` or similar."]
+pub struct D1With<Ctes> {
+    ctes: Ctes,
+    recursive: bool,
+}
+
+impl<Ctes> D1With<Ctes> {
+    /// Defines another common table expression, which can use the ones defined before
+    pub fn with<T, Q>(self, table: T, query: Q) -> D1With<(Ctes, D1Cte<T, Q::Query>)>
+    where
+        T: Table,
+        Q: AsQuery<SqlType = T::SqlType>,
+    {
+        D1With {
+            ctes: (self.ctes, D1Cte::new(table, query.as_query())),
+            recursive: self.recursive,
+        }
+    }
+
+    /// Defines another recursive common table expression, see [`with_recursive`]
+    #[allow(clippy::type_complexity)]
+    pub fn with_recursive<T, A, S>(
+        self,
+        table: T,
+        anchor: A,
+        step: S,
+    ) -> D1With<(Ctes, D1Cte<T, D1RecursiveQuery<A::Query, S::Query>>)>
+    where
+        T: Table,
+        A: AsQuery<SqlType = T::SqlType>,
+        S: AsQuery<SqlType = T::SqlType>,
+    {
+        D1With {
+            ctes: (self.ctes, D1Cte::new(table, D1RecursiveQuery::new(anchor.as_query(), step.as_query()))),
+            recursive: true,
+        }
+    }
+
+    /// The query the common table expressions are defined for
+    pub fn query<Q: AsQuery>(self, query: Q) -> D1WithQuery<Ctes, Q::Query> {
+        D1WithQuery {
+            with: self,
+            query: query.as_query(),
+        }
+    }
+}
+
+/// A query prefixed with a `WITH` clause, see [`D1With::query`]
+#[derive(Debug, Clone)]
+#[must_use = "Queries are only executed when calling `load`, `get_result` or similar."]
+pub struct D1WithQuery<Ctes, Q> {
+    with: D1With<Ctes>,
+    query: Q,
+}
+
+impl<Ctes, Q: Query> Query for D1WithQuery<Ctes, Q> {
+    type SqlType = Q::SqlType;
+}
+
+impl<Ctes, Q> QueryId for D1WithQuery<Ctes, Q> {
+    type QueryId = ();
+
+    const HAS_STATIC_QUERY_ID: bool = false;
+}
+
+impl<Ctes, Q> QueryFragment<D1Backend> for D1WithQuery<Ctes, Q>
+where
+    Ctes: D1CommonTableExpressions,
+    Q: QueryFragment<D1Backend>,
+{
+    fn walk_ast<'b>(&'b self, mut out: AstPass<'_, 'b, D1Backend>) -> QueryResult<()> {
+        out.push_sql(if self.with.recursive { "WITH RECURSIVE " } else { "WITH " });
+        self.with.ctes.walk_ctes(out.reborrow())?;
+        out.push_sql(" ");
+        self.query.walk_ast(out.reborrow())
+    }
+}
+
+/// `table(columns) AS (query)` in a `WITH` clause
+#[derive(Debug, Clone, Copy)]
+pub struct D1Cte<T, Q> {
+    table: PhantomData<T>,
+    query: Q,
+}
+
+impl<T, Q> D1Cte<T, Q> {
+    fn new(_table: T, query: Q) -> Self {
+        D1Cte {
+            table: PhantomData,
+            query,
+        }
+    }
+}
+
+/// `anchor UNION ALL step`, the body of a recursive [`D1Cte`]
+#[derive(Debug, Clone, Copy)]
+pub struct D1RecursiveQuery<A, S> {
+    anchor: A,
+    step: S,
+}
+
+impl<A, S> D1RecursiveQuery<A, S> {
+    fn new(anchor: A, step: S) -> Self {
+        D1RecursiveQuery { anchor, step }
+    }
+}
+
+impl<A, S> QueryFragment<D1Backend> for D1RecursiveQuery<A, S>
+where
+    A: QueryFragment<D1Backend>,
+    S: QueryFragment<D1Backend>,
+{
+    fn walk_ast<'b>(&'b self, mut out: AstPass<'_, 'b, D1Backend>) -> QueryResult<()> {
+        // no parentheses or subqueries here, SQLite only recurses into a plain compound select
+        self.anchor.walk_ast(out.reborrow())?;
+        out.push_sql(" UNION ALL ");
+        self.step.walk_ast(out.reborrow())
+    }
+}
+
+/// The common table expressions of a [`D1With`], built up by its methods
+pub trait D1CommonTableExpressions {
+    const IS_EMPTY: bool;
+
+    fn walk_ctes<'b>(&'b self, out: AstPass<'_, 'b, D1Backend>) -> QueryResult<()>;
+}
+
+impl D1CommonTableExpressions for () {
+    const IS_EMPTY: bool = true;
+
+    fn walk_ctes<'b>(&'b self, _out: AstPass<'_, 'b, D1Backend>) -> QueryResult<()> {
+        Ok(())
+    }
+}
+
+impl<Rest, T, Q> D1CommonTableExpressions for (Rest, D1Cte<T, Q>)
+where
+    Rest: D1CommonTableExpressions,
+    T: D1TableName,
+    T::AllColumns: D1ColumnNames,
+    Q: QueryFragment<D1Backend>,
+{
+    const IS_EMPTY: bool = false;
+
+    fn walk_ctes<'b>(&'b self, mut out: AstPass<'_, 'b, D1Backend>) -> QueryResult<()> {
+        let (rest, cte) = self;
+        rest.walk_ctes(out.reborrow())?;
+        if !Rest::IS_EMPTY {
+            out.push_sql(", ");
+        }
+
+        out.push_identifier(T::TABLE_NAME)?;
+        out.push_sql("(");
+        T::AllColumns::walk_column_names(out.reborrow())?;
+        out.push_sql(") AS (");
+        cte.query.walk_ast(out.reborrow())?;
+        out.push_sql(")");
+        Ok(())
+    }
+}
+
+/// The `AllColumns` tuple of a table
+#[doc(hidden)]
+pub trait D1ColumnNames {
+    fn walk_column_names(out: AstPass<'_, '_, D1Backend>) -> QueryResult<()>;
+}
+
+macro_rules! column_names {
+    ($first:ident $($column:ident)*) => {
+        impl<$first: Column, $($column: Column),*> D1ColumnNames for ($first, $($column,)*) {
+            fn walk_column_names(mut out: AstPass<'_, '_, D1Backend>) -> QueryResult<()> {
+                out.push_identifier($first::NAME)?;
+                $(
+                    out.push_sql(", ");
+                    out.push_identifier($column::NAME)?;
+                )*
+                Ok(())
+            }
+        }
+    };
+}
+
+macro_rules! all_column_names {
+    ($first:ident) => {
+        column_names!($first);
+    };
+    ($first:ident $($rest:ident)+) => {
+        column_names!($first $($rest)+);
+        all_column_names!($($rest)+);
+    };
+}
+
+// diesel's default limit is 32 columns per table
+all_column_names!(
+    C1 C2 C3 C4 C5 C6 C7 C8 C9 C10 C11 C12 C13 C14 C15 C16
+    C17 C18 C19 C20 C21 C22 C23 C24 C25 C26 C27 C28 C29 C30 C31 C32
+);
+
+#[cfg(test)]
+mod tests {
+    use diesel::{debug_query, dsl::sql, prelude::*, sql_types::Integer};
+
+    use super::*;
+
+    diesel::table! {
+        users (id) {
+            id -> Integer,
+            name -> Text,
+        }
+    }
+
+    diesel::table! {
+        posts (id) {
+            id -> Integer,
+            author_id -> Integer,
+            views -> Integer,
+        }
+    }
+
+    diesel::table! {
+        popular_posts (id) {
+            id -> Integer,
+            author_id -> Integer,
+        }
+    }
+
+    diesel::table! {
+        popular_authors (id) {
+            id -> Integer,
+        }
+    }
+
+    diesel::table! {
+        folders (id) {
+            id -> Integer,
+            parent_id -> Nullable<Integer>,
+            name -> Text,
+        }
+    }
+
+    diesel::table! {
+        folder_tree (id) {
+            id -> Integer,
+            depth -> Integer,
+        }
+    }
+
+    diesel::joinable!(popular_posts -> users (author_id));
+    diesel::allow_tables_to_appear_in_same_query!(users, posts, popular_posts, popular_authors, folders, folder_tree);
+
+    #[test]
+    fn renders_with() {
+        let query = with(
+            popular_posts::table,
+            posts::table.select((posts::id, posts::author_id)).filter(posts::views.gt(100)),
+        )
+        .query(popular_posts::table.inner_join(users::table).select(users::name).distinct());
+        assert_eq!(
+            debug_query::<D1Backend, _>(&query).to_string(),
+            "WITH `popular_posts`(`id`, `author_id`) AS (\
+            SELECT `posts`.`id`, `posts`.`author_id` FROM `posts` WHERE (`posts`.`views` > ?)) \
+            SELECT DISTINCT `users`.`name` FROM (`popular_posts` \
+            INNER JOIN `users` ON (`popular_posts`.`author_id` = `users`.`id`)) -- binds: [100]"
+        );
+    }
+
+    #[test]
+    fn renders_several_ctes() {
+        let query = with(
+            popular_posts::table,
+            posts::table.select((posts::id, posts::author_id)).filter(posts::views.gt(100)),
+        )
+        .with(popular_authors::table, popular_posts::table.select((popular_posts::author_id,)).distinct())
+        .query(
+            users::table
+                .filter(users::id.eq_any(popular_authors::table.select(popular_authors::id)))
+                .select(users::name),
+        );
+        assert_eq!(
+            debug_query::<D1Backend, _>(&query).to_string(),
+            "WITH `popular_posts`(`id`, `author_id`) AS (\
+            SELECT `posts`.`id`, `posts`.`author_id` FROM `posts` WHERE (`posts`.`views` > ?)), \
+            `popular_authors`(`id`) AS (SELECT DISTINCT `popular_posts`.`author_id` FROM `popular_posts`) \
+            SELECT `users`.`name` FROM `users` \
+            WHERE (`users`.`id` IN (SELECT `popular_authors`.`id` FROM `popular_authors`)) -- binds: [100]"
+        );
+    }
+
+    #[test]
+    fn renders_with_recursive() {
+        let anchor = folders::table
+            .filter(folders::id.eq(1))
+            .select((folders::id, sql::<Integer>("0")));
+        let step = folders::table
+            .inner_join(folder_tree::table.on(folders::parent_id.eq(folder_tree::id.nullable())))
+            .select((folders::id, folder_tree::depth + 1));
+        let query = with_recursive(folder_tree::table, anchor, step).query(
+            folder_tree::table
+                .inner_join(folders::table.on(folders::id.eq(folder_tree::id)))
+                .select((folders::name, folder_tree::depth)),
+        );
+        assert_eq!(
+            debug_query::<D1Backend, _>(&query).to_string(),
+            "WITH RECURSIVE `folder_tree`(`id`, `depth`) AS (\
+            SELECT `folders`.`id`, 0 FROM `folders` WHERE (`folders`.`id` = ?) \
+            UNION ALL \
+            SELECT `folders`.`id`, (`folder_tree`.`depth` + ?) FROM (`folders` \
+            INNER JOIN `folder_tree` ON (`folders`.`parent_id` = `folder_tree`.`id`))) \
+            SELECT `folders`.`name`, `folder_tree`.`depth` FROM (`folder_tree` \
+            INNER JOIN `folders` ON (`folders`.`id` = `folder_tree`.`id`)) -- binds: [1, 1]"
+        );
+
+        // a recursive CTE after a plain one makes the whole clause recursive
+        let query = with(popular_posts::table, posts::table.select((posts::id, posts::author_id)))
+            .with_recursive(
+                folder_tree::table,
+                folders::table.select((folders::id, sql::<Integer>("0"))),
+                folders::table
+                    .inner_join(folder_tree::table.on(folders::parent_id.eq(folder_tree::id.nullable())))
+                    .select((folders::id, folder_tree::depth + 1)),
+            )
+            .query(folder_tree::table.select(folder_tree::id));
+        assert!(debug_query::<D1Backend, _>(&query)
+            .to_string()
+            .starts_with("WITH RECURSIVE `popular_posts`(`id`, `author_id`) AS ("));
+
+        // and a plain CTE after a recursive one keeps it recursive
+        let query = with_recursive(
+            folder_tree::table,
+            folders::table.select((folders::id, sql::<Integer>("0"))),
+            folders::table
+                .inner_join(folder_tree::table.on(folders::parent_id.eq(folder_tree::id.nullable())))
+                .select((folders::id, folder_tree::depth + 1)),
+        )
+        .with(popular_posts::table, posts::table.select((posts::id, posts::author_id)))
+        .query(popular_posts::table.select(popular_posts::id));
+        let sql = debug_query::<D1Backend, _>(&query).to_string();
+        assert!(sql.starts_with("WITH RECURSIVE `folder_tree`(`id`, `depth`) AS ("), "{sql}");
+        assert!(
+            sql.contains(
+                "), `popular_posts`(`id`, `author_id`) AS (SELECT `posts`.`id`, `posts`.`author_id` FROM `posts`) \
+                SELECT `popular_posts`.`id` FROM `popular_posts`"
+            ),
+            "{sql}"
+        );
+    }
+}
@@ -0,0 +1,38 @@
+//! Query builder extensions for SQL that diesel can't express against `D1Backend`
+//!
+//! They render through `D1QueryBuilder` and run with `diesel_async::RunQueryDsl` like any
+//! other query.
+
+use diesel::{
+    internal::table_macro::{Identifier, StaticQueryFragment},
+    Table,
+};
+
+mod combination;
+mod cte;
+mod update_from;
+mod window;
+
+pub use combination::{D1CombinationClause, D1CombineDsl, D1Except, D1Intersect, D1ResultOrder, D1Union, D1UnionAll};
+pub use cte::{with, with_recursive, D1ColumnNames, D1CommonTableExpressions, D1Cte, D1RecursiveQuery, D1With, D1WithQuery};
+pub use update_from::{update_from, D1Assignment, D1Assignments, D1Predicate, D1Predicates, D1UpdateFrom, D1UpdateFromSource};
+pub use window::{
+    cume_dist, dense_rank, first_value, lag, last_value, lead, nth_value, ntile, percent_rank, rank, row_number, window,
+    D1FrameBound, D1NullableOf, D1Over, D1Window, D1WindowDsl, D1WindowFunction, D1WindowTerms, D1WindowTermsOn,
+};
+
+/// The SQL name of a table generated by `table!`
+///
+/// diesel only exposes it through `diesel::internal`, which has no semver guarantee, so it's
+/// read in this one place. Cargo.toml keeps diesel at 2.2.x for that reason.
+#[doc(hidden)]
+pub trait D1TableName: Table {
+    const TABLE_NAME: &'static str;
+}
+
+impl<T> D1TableName for T
+where
+    T: Table + StaticQueryFragment<Component = Identifier<'static>>,
+{
+    const TABLE_NAME: &'static str = T::STATIC_COMPONENT.0;
+}
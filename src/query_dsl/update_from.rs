@@ -0,0 +1,295 @@
+use std::marker::PhantomData;
+
+use diesel::{
+    expression::AsExpression,
+    query_builder::{AstPass, QueryFragment, QueryId},
+    query_source::{AppearsInFromClause, Plus, QuerySource},
+    sql_types::BoolOrNullableBool,
+    AppearsOnTable, Column, Expression, QueryResult, Table,
+};
+
+use crate::backend::D1Backend;
+
+/// Creates an `UPDATE target SET ... FROM from WHERE ...` statement
+///
+/// SQLite joins `target` with `from` before updating, which reads each row once instead of
+/// running a correlated subquery per updated row. `from` can be a table or a join, as long
+/// as it doesn't contain `target` itself. Values and filters can use columns of both sides,
+/// the tables have to be listed in `allow_tables_to_appear_in_same_query!`.
+///
+/// ```ignore
+/// update_from(posts::table, authors::table)
+///     .set(posts::author_name, authors::name)
+///     .filter(posts::author_id.eq(authors::id))
+///     .execute(&mut conn)
+///     .await?;
+/// ```
+pub fn update_from<T, F>(target: T, from: F) -> D1UpdateFrom<T, F>
+where
+    T: Table,
+    F: QuerySource,
+{
+    D1UpdateFrom {
+        target: target.from_clause(),
+        from: from.from_clause(),
+        assignments: (),
+        predicates: (),
+    }
+}
+
+/// An `UPDATE ... FROM` statement, see [`update_from`]
+///
+/// It can only be executed once at least one column has been [`set`](Self::set).
+#[derive(Debug, Clone)]
+#[must_use = "Queries are only executed when calling `execute`."]
+pub struct D1UpdateFrom<T: QuerySource, F: QuerySource, Assignments = (), Predicates = ()> {
+    target: T::FromClause,
+    from: F::FromClause,
+    assignments: Assignments,
+    predicates: Predicates,
+}
+
+/// The tables of a [`D1UpdateFrom`] seen together, which its values and filters are
+/// checked against
+#[derive(Debug, Clone, Copy)]
+pub struct D1UpdateFromSource<T, F>(PhantomData<(T, F)>);
+
+impl<QS, T, F> AppearsInFromClause<QS> for D1UpdateFromSource<T, F>
+where
+    T: AppearsInFromClause<QS>,
+    F: AppearsInFromClause<QS>,
+    T::Count: Plus<F::Count>,
+{
+    type Count = <T::Count as Plus<F::Count>>::Output;
+}
+
+impl<T: QuerySource, F: QuerySource, Assignments, Predicates> D1UpdateFrom<T, F, Assignments, Predicates> {
+    /// Sets `column` of the updated table to `value`
+    #[allow(clippy::type_complexity)]
+    pub fn set<C, V>(
+        self,
+        _column: C,
+        value: V,
+    ) -> D1UpdateFrom<T, F, (Assignments, D1Assignment<C, V::Expression>), Predicates>
+    where
+        C: Column<Table = T>,
+        C::SqlType: diesel::sql_types::SqlType + diesel::expression::TypedExpressionType,
+        V: AsExpression<C::SqlType>,
+        V::Expression: AppearsOnTable<D1UpdateFromSource<T, F>>,
+    {
+        D1UpdateFrom {
+            target: self.target,
+            from: self.from,
+            assignments: (
+                self.assignments,
+                D1Assignment {
+                    column: PhantomData,
+                    value: value.as_expression(),
+                },
+            ),
+            predicates: self.predicates,
+        }
+    }
+
+    /// Adds a `WHERE` condition, several are combined with `AND`
+    pub fn filter<P>(self, predicate: P) -> D1UpdateFrom<T, F, Assignments, (Predicates, D1Predicate<P>)>
+    where
+        P: Expression + AppearsOnTable<D1UpdateFromSource<T, F>>,
+        P::SqlType: BoolOrNullableBool,
+    {
+        D1UpdateFrom {
+            target: self.target,
+            from: self.from,
+            assignments: self.assignments,
+            predicates: (self.predicates, D1Predicate(predicate)),
+        }
+    }
+}
+
+impl<T: QuerySource, F: QuerySource, Assignments, Predicates> QueryId for D1UpdateFrom<T, F, Assignments, Predicates> {
+    type QueryId = ();
+
+    const HAS_STATIC_QUERY_ID: bool = false;
+}
+
+impl<T, F, Assignments, C, V, Predicates> QueryFragment<D1Backend>
+    for D1UpdateFrom<T, F, (Assignments, D1Assignment<C, V>), Predicates>
+where
+    T: Table,
+    T::FromClause: QueryFragment<D1Backend>,
+    F: QuerySource,
+    F::FromClause: QueryFragment<D1Backend>,
+    (Assignments, D1Assignment<C, V>): D1Assignments,
+    Predicates: D1Predicates,
+{
+    fn walk_ast<'b>(&'b self, mut out: AstPass<'_, 'b, D1Backend>) -> QueryResult<()> {
+        out.push_sql("UPDATE ");
+        self.target.walk_ast(out.reborrow())?;
+        out.push_sql(" SET ");
+        self.assignments.walk_assignments(out.reborrow())?;
+        out.push_sql(" FROM ");
+        self.from.walk_ast(out.reborrow())?;
+        self.predicates.walk_predicates(out.reborrow())?;
+        Ok(())
+    }
+}
+
+/// `column = value` in the `SET` clause of a [`D1UpdateFrom`]
+#[derive(Debug, Clone, Copy)]
+pub struct D1Assignment<C, V> {
+    column: PhantomData<C>,
+    value: V,
+}
+
+/// A condition of the `WHERE` clause of a [`D1UpdateFrom`]
+#[derive(Debug, Clone, Copy)]
+pub struct D1Predicate<P>(P);
+
+/// The assignments of a [`D1UpdateFrom`], built up by [`D1UpdateFrom::set`]
+pub trait D1Assignments {
+    const IS_EMPTY: bool;
+
+    fn walk_assignments<'b>(&'b self, out: AstPass<'_, 'b, D1Backend>) -> QueryResult<()>;
+}
+
+impl D1Assignments for () {
+    const IS_EMPTY: bool = true;
+
+    fn walk_assignments<'b>(&'b self, _out: AstPass<'_, 'b, D1Backend>) -> QueryResult<()> {
+        Ok(())
+    }
+}
+
+impl<Rest, C, V> D1Assignments for (Rest, D1Assignment<C, V>)
+where
+    Rest: D1Assignments,
+    C: Column,
+    V: QueryFragment<D1Backend>,
+{
+    const IS_EMPTY: bool = false;
+
+    fn walk_assignments<'b>(&'b self, mut out: AstPass<'_, 'b, D1Backend>) -> QueryResult<()> {
+        let (rest, assignment) = self;
+        rest.walk_assignments(out.reborrow())?;
+        if !Rest::IS_EMPTY {
+            out.push_sql(", ");
+        }
+        // SQLite doesn't accept the table name in front of the assigned column
+        out.push_identifier(C::NAME)?;
+        out.push_sql(" = ");
+        assignment.value.walk_ast(out.reborrow())
+    }
+}
+
+/// The conditions of a [`D1UpdateFrom`], built up by [`D1UpdateFrom::filter`]
+pub trait D1Predicates {
+    const IS_EMPTY: bool;
+
+    fn walk_predicates<'b>(&'b self, out: AstPass<'_, 'b, D1Backend>) -> QueryResult<()>;
+}
+
+impl D1Predicates for () {
+    const IS_EMPTY: bool = true;
+
+    fn walk_predicates<'b>(&'b self, _out: AstPass<'_, 'b, D1Backend>) -> QueryResult<()> {
+        Ok(())
+    }
+}
+
+impl<Rest, P> D1Predicates for (Rest, D1Predicate<P>)
+where
+    Rest: D1Predicates,
+    P: QueryFragment<D1Backend>,
+{
+    const IS_EMPTY: bool = false;
+
+    fn walk_predicates<'b>(&'b self, mut out: AstPass<'_, 'b, D1Backend>) -> QueryResult<()> {
+        let (rest, predicate) = self;
+        rest.walk_predicates(out.reborrow())?;
+        out.push_sql(if Rest::IS_EMPTY { " WHERE (" } else { " AND (" });
+        predicate.0.walk_ast(out.reborrow())?;
+        out.push_sql(")");
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use diesel::{debug_query, prelude::*};
+
+    use super::*;
+
+    diesel::table! {
+        posts (id) {
+            id -> Integer,
+            author_id -> Integer,
+            author_name -> Text,
+            views -> Integer,
+        }
+    }
+
+    diesel::table! {
+        authors (id) {
+            id -> Integer,
+            name -> Text,
+            country_id -> Integer,
+        }
+    }
+
+    diesel::table! {
+        countries (id) {
+            id -> Integer,
+            active -> Bool,
+        }
+    }
+
+    diesel::joinable!(authors -> countries (country_id));
+    diesel::allow_tables_to_appear_in_same_query!(posts, authors, countries);
+
+    #[test]
+    fn renders_update_from() {
+        let query = update_from(posts::table, authors::table)
+            .set(posts::author_name, authors::name)
+            .filter(posts::author_id.eq(authors::id));
+        assert_eq!(
+            debug_query::<D1Backend, _>(&query).to_string(),
+            "UPDATE `posts` SET `author_name` = `authors`.`name` FROM `authors` \
+            WHERE ((`posts`.`author_id` = `authors`.`id`)) -- binds: []"
+        );
+    }
+
+    #[test]
+    fn renders_several_assignments_and_filters() {
+        let query = update_from(posts::table, authors::table)
+            .set(posts::author_name, "unknown")
+            .set(posts::views, posts::views + 1)
+            .filter(posts::author_id.eq(authors::id))
+            .filter(authors::name.is_null().or(authors::name.eq("")));
+        assert_eq!(
+            debug_query::<D1Backend, _>(&query).to_string(),
+            "UPDATE `posts` SET `author_name` = ?, `views` = (`posts`.`views` + ?) FROM `authors` \
+            WHERE ((`posts`.`author_id` = `authors`.`id`)) \
+            AND (((`authors`.`name` IS NULL) OR (`authors`.`name` = ?))) -- binds: [\"unknown\", 1, \"\"]"
+        );
+
+        let query = update_from(posts::table, authors::table).set(posts::views, 0);
+        assert_eq!(
+            debug_query::<D1Backend, _>(&query).to_string(),
+            "UPDATE `posts` SET `views` = ? FROM `authors` -- binds: [0]"
+        );
+    }
+
+    #[test]
+    fn updates_from_joins() {
+        let query = update_from(posts::table, authors::table.inner_join(countries::table))
+            .set(posts::author_name, authors::name)
+            .filter(posts::author_id.eq(authors::id))
+            .filter(countries::active.eq(true));
+        assert_eq!(
+            debug_query::<D1Backend, _>(&query).to_string(),
+            "UPDATE `posts` SET `author_name` = `authors`.`name` \
+            FROM (`authors` INNER JOIN `countries` ON (`authors`.`country_id` = `countries`.`id`)) \
+            WHERE ((`posts`.`author_id` = `authors`.`id`)) AND ((`countries`.`active` = ?)) -- binds: [true]"
+        );
+    }
+}
@@ -0,0 +1,511 @@
+use std::marker::PhantomData;
+
+use diesel::{
+    dsl::AsExprOf,
+    expression::{is_aggregate, AsExpression, TypedExpressionType, ValidGrouping},
+    query_builder::{AstPass, QueryFragment, QueryId},
+    sql_types::{BigInt, Double, IntoNullable},
+    AppearsOnTable, Expression, QueryResult, SelectableExpression,
+};
+
+use crate::backend::D1Backend;
+
+/// `function(...) OVER (...)` for D1
+///
+/// Available on diesel's aggregates (`sum`, `count`, `max`, ...) and on the window functions
+/// of this module. The result is computed per row, so it can be selected along with plain
+/// columns, and ordered by in the outer query. Filtering on it needs a subquery, as SQL
+/// evaluates window functions after `WHERE`.
+///
+/// ```ignore
+/// let leaderboard = scores::table
+///     .select((scores::player, scores::points, rank().over(window().order_by(scores::points.desc()))))
+///     .load::<(String, i32, i64)>(&mut conn)
+///     .await?;
+///
+/// let running_totals = payments::table
+///     .select((
+///         payments::id,
+///         sum(payments::amount).over(
+///             window()
+///                 .partition_by(payments::account_id)
+///                 .order_by(payments::created_at)
+///                 .rows_between(D1FrameBound::UnboundedPreceding, D1FrameBound::CurrentRow),
+///         ),
+///     ))
+///     .load::<(i32, Option<i64>)>(&mut conn)
+///     .await?;
+/// ```
+pub trait D1WindowDsl: Expression + ValidGrouping<(), IsAggregate = is_aggregate::Yes> + Sized {
+    fn over<Partition, Order>(self, window: D1Window<Partition, Order>) -> D1Over<Self, Partition, Order> {
+        D1Over { function: self, window }
+    }
+}
+
+impl<T: Expression + ValidGrouping<(), IsAggregate = is_aggregate::Yes>> D1WindowDsl for T {}
+
+/// Creates an empty window definition, covering every row of the query
+pub fn window() -> D1Window {
+    D1Window {
+        partition: (),
+        order: (),
+        frame: None,
+    }
+}
+
+/// The window of a [`D1Over`], see [`window`]
+#[derive(Debug, Clone, Copy)]
+pub struct D1Window<Partition = (), Order = ()> {
+    partition: Partition,
+    order: Order,
+    frame: Option<D1Frame>,
+}
+
+impl<Partition, Order> D1Window<Partition, Order> {
+    /// Splits the rows into groups of equal `expressions`, the function runs on each group
+    /// separately
+    pub fn partition_by<P: Expression>(self, expressions: P) -> D1Window<(P,), Order> {
+        D1Window {
+            partition: (expressions,),
+            order: self.order,
+            frame: self.frame,
+        }
+    }
+
+    /// Orders the rows of each partition, which ranking functions and frames rely on
+    pub fn order_by<O: Expression>(self, order: O) -> D1Window<Partition, (O,)> {
+        D1Window {
+            partition: self.partition,
+            order: (order,),
+            frame: self.frame,
+        }
+    }
+
+    /// Limits the function to the rows from `start` to `end` around the current one, counted
+    /// in rows
+    pub fn rows_between(self, start: D1FrameBound, end: D1FrameBound) -> Self {
+        self.frame("ROWS", start, end)
+    }
+
+    /// Limits the function to the rows from `start` to `end` around the current one, measured
+    /// by the value of the `ORDER BY` term
+    ///
+    /// With a `Preceding` or `Following` offset, the window has to be ordered by exactly one
+    /// term, `order_by(a)` rather than `order_by((a, b))` or none at all. SQLite fails the
+    /// query otherwise.
+    pub fn range_between(self, start: D1FrameBound, end: D1FrameBound) -> Self {
+        self.frame("RANGE", start, end)
+    }
+
+    /// Limits the function to the rows from `start` to `end` around the current one, counted
+    /// in groups of rows with equal `ORDER BY` terms
+    pub fn groups_between(self, start: D1FrameBound, end: D1FrameBound) -> Self {
+        self.frame("GROUPS", start, end)
+    }
+
+    fn frame(mut self, units: &'static str, start: D1FrameBound, end: D1FrameBound) -> Self {
+        self.frame = Some(D1Frame { units, start, end });
+        self
+    }
+}
+
+impl<Partition, Order> QueryFragment<D1Backend> for D1Window<Partition, Order>
+where
+    Partition: D1WindowTerms,
+    Order: D1WindowTerms,
+{
+    fn walk_ast<'b>(&'b self, mut out: AstPass<'_, 'b, D1Backend>) -> QueryResult<()> {
+        let mut separate = false;
+        if !Partition::IS_EMPTY {
+            out.push_sql("PARTITION BY ");
+            self.partition.walk_terms(out.reborrow())?;
+            separate = true;
+        }
+        if !Order::IS_EMPTY {
+            out.push_sql(if separate { " ORDER BY " } else { "ORDER BY " });
+            self.order.walk_terms(out.reborrow())?;
+            separate = true;
+        }
+        if let Some(frame) = &self.frame {
+            if separate {
+                out.push_sql(" ");
+            }
+            out.push_sql(frame.units);
+            out.push_sql(" BETWEEN ");
+            frame.start.walk_ast(out.reborrow())?;
+            out.push_sql(" AND ");
+            frame.end.walk_ast(out.reborrow())?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct D1Frame {
+    units: &'static str,
+    start: D1FrameBound,
+    end: D1FrameBound,
+}
+
+/// A boundary of the frame set with [`D1Window::rows_between`] and similar
+///
+/// SQLite rejects frames ending before they start, like `CurrentRow` to `UnboundedPreceding`.
+/// Offsets are written into the SQL, as SQLite needs them to be constant.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum D1FrameBound {
+    UnboundedPreceding,
+    Preceding(u64),
+    CurrentRow,
+    Following(u64),
+    UnboundedFollowing,
+}
+
+impl QueryFragment<D1Backend> for D1FrameBound {
+    fn walk_ast<'b>(&'b self, mut out: AstPass<'_, 'b, D1Backend>) -> QueryResult<()> {
+        match self {
+            D1FrameBound::UnboundedPreceding => out.push_sql("UNBOUNDED PRECEDING"),
+            D1FrameBound::Preceding(offset) => out.push_sql(&format!("{offset} PRECEDING")),
+            D1FrameBound::CurrentRow => out.push_sql("CURRENT ROW"),
+            D1FrameBound::Following(offset) => out.push_sql(&format!("{offset} FOLLOWING")),
+            D1FrameBound::UnboundedFollowing => out.push_sql("UNBOUNDED FOLLOWING"),
+        }
+        Ok(())
+    }
+}
+
+/// A function evaluated over a window, see [`D1WindowDsl::over`]
+#[derive(Debug, Clone, Copy)]
+pub struct D1Over<F, Partition, Order> {
+    function: F,
+    window: D1Window<Partition, Order>,
+}
+
+impl<F: Expression, Partition, Order> Expression for D1Over<F, Partition, Order> {
+    type SqlType = F::SqlType;
+}
+
+// the function runs once per row after grouping, so it mixes with grouped and plain columns
+impl<F, Partition, Order, GB> ValidGrouping<GB> for D1Over<F, Partition, Order> {
+    type IsAggregate = is_aggregate::Never;
+}
+
+// arguments from the nullable side of a left join are fine, every value function returns a
+// nullable type
+impl<F, Partition, Order, QS> AppearsOnTable<QS> for D1Over<F, Partition, Order>
+where
+    F: AppearsOnTable<QS>,
+    Partition: D1WindowTermsOn<QS>,
+    Order: D1WindowTermsOn<QS>,
+{
+}
+
+impl<F, Partition, Order, QS> SelectableExpression<QS> for D1Over<F, Partition, Order>
+where
+    F: AppearsOnTable<QS>,
+    Partition: D1WindowTermsOn<QS>,
+    Order: D1WindowTermsOn<QS>,
+{
+}
+
+impl<F, Partition, Order> QueryId for D1Over<F, Partition, Order> {
+    type QueryId = ();
+
+    // the SQL depends on the frame
+    const HAS_STATIC_QUERY_ID: bool = false;
+}
+
+impl<F, Partition, Order> QueryFragment<D1Backend> for D1Over<F, Partition, Order>
+where
+    F: QueryFragment<D1Backend>,
+    D1Window<Partition, Order>: QueryFragment<D1Backend>,
+{
+    fn walk_ast<'b>(&'b self, mut out: AstPass<'_, 'b, D1Backend>) -> QueryResult<()> {
+        self.function.walk_ast(out.reborrow())?;
+        out.push_sql(" OVER (");
+        self.window.walk_ast(out.reborrow())?;
+        out.push_sql(")");
+        Ok(())
+    }
+}
+
+/// A function that only exists with `OVER`, see the functions of this module
+///
+/// It counts as an aggregate for diesel, which keeps it out of select clauses until
+/// [`over`](D1WindowDsl::over) is called.
+#[derive(Debug, Clone, Copy)]
+pub struct D1WindowFunction<ST, Args> {
+    name: &'static str,
+    args: Args,
+    sql_type: PhantomData<ST>,
+}
+
+impl<ST, Args> D1WindowFunction<ST, Args> {
+    fn new(name: &'static str, args: Args) -> Self {
+        D1WindowFunction {
+            name,
+            args,
+            sql_type: PhantomData,
+        }
+    }
+}
+
+impl<ST: TypedExpressionType, Args> Expression for D1WindowFunction<ST, Args> {
+    type SqlType = ST;
+}
+
+impl<ST, Args, GB> ValidGrouping<GB> for D1WindowFunction<ST, Args> {
+    type IsAggregate = is_aggregate::Yes;
+}
+
+impl<ST: TypedExpressionType, Args: D1WindowTermsOn<QS>, QS> AppearsOnTable<QS> for D1WindowFunction<ST, Args> {}
+
+impl<ST, Args> QueryId for D1WindowFunction<ST, Args> {
+    type QueryId = ();
+
+    const HAS_STATIC_QUERY_ID: bool = false;
+}
+
+impl<ST, Args: D1WindowTerms> QueryFragment<D1Backend> for D1WindowFunction<ST, Args> {
+    fn walk_ast<'b>(&'b self, mut out: AstPass<'_, 'b, D1Backend>) -> QueryResult<()> {
+        out.push_sql(self.name);
+        out.push_sql("(");
+        self.args.walk_terms(out.reborrow())?;
+        out.push_sql(")");
+        Ok(())
+    }
+}
+
+/// The nullable SQL type of `E`, which value functions return for rows their frame or offset
+/// doesn't cover
+pub type D1NullableOf<E> = <<E as Expression>::SqlType as IntoNullable>::Nullable;
+
+/// The number of the row in its partition, starting at 1
+pub fn row_number() -> D1WindowFunction<BigInt, ()> {
+    D1WindowFunction::new("row_number", ())
+}
+
+/// The rank of the row in its partition, with gaps after ties (1, 1, 3)
+pub fn rank() -> D1WindowFunction<BigInt, ()> {
+    D1WindowFunction::new("rank", ())
+}
+
+/// The rank of the row in its partition, without gaps after ties (1, 1, 2)
+pub fn dense_rank() -> D1WindowFunction<BigInt, ()> {
+    D1WindowFunction::new("dense_rank", ())
+}
+
+/// `(rank - 1) / (rows in the partition - 1)`, between 0 and 1
+pub fn percent_rank() -> D1WindowFunction<Double, ()> {
+    D1WindowFunction::new("percent_rank", ())
+}
+
+/// The share of rows of the partition ranked before or tied with the row, between 0 and 1
+pub fn cume_dist() -> D1WindowFunction<Double, ()> {
+    D1WindowFunction::new("cume_dist", ())
+}
+
+/// The number of the bucket the row falls in, when splitting its partition into `buckets`
+/// groups of about the same size
+pub fn ntile(buckets: i64) -> D1WindowFunction<BigInt, (AsExprOf<i64, BigInt>,)> {
+    D1WindowFunction::new("ntile", (AsExpression::<BigInt>::as_expression(buckets),))
+}
+
+/// `expr` for the row `offset` rows before this one in the partition, `NULL` past the start
+pub fn lag<E>(expr: E, offset: i64) -> D1WindowFunction<D1NullableOf<E>, (E, AsExprOf<i64, BigInt>)>
+where
+    E: Expression,
+    E::SqlType: IntoNullable,
+{
+    D1WindowFunction::new("lag", (expr, AsExpression::<BigInt>::as_expression(offset)))
+}
+
+/// `expr` for the row `offset` rows after this one in the partition, `NULL` past the end
+pub fn lead<E>(expr: E, offset: i64) -> D1WindowFunction<D1NullableOf<E>, (E, AsExprOf<i64, BigInt>)>
+where
+    E: Expression,
+    E::SqlType: IntoNullable,
+{
+    D1WindowFunction::new("lead", (expr, AsExpression::<BigInt>::as_expression(offset)))
+}
+
+/// `expr` for the first row of the frame
+pub fn first_value<E>(expr: E) -> D1WindowFunction<D1NullableOf<E>, (E,)>
+where
+    E: Expression,
+    E::SqlType: IntoNullable,
+{
+    D1WindowFunction::new("first_value", (expr,))
+}
+
+/// `expr` for the last row of the frame, which is the current row (or its last tie) unless
+/// a frame is set
+pub fn last_value<E>(expr: E) -> D1WindowFunction<D1NullableOf<E>, (E,)>
+where
+    E: Expression,
+    E::SqlType: IntoNullable,
+{
+    D1WindowFunction::new("last_value", (expr,))
+}
+
+/// `expr` for the `n`th row of the frame, starting at 1
+pub fn nth_value<E>(expr: E, n: i64) -> D1WindowFunction<D1NullableOf<E>, (E, AsExprOf<i64, BigInt>)>
+where
+    E: Expression,
+    E::SqlType: IntoNullable,
+{
+    D1WindowFunction::new("nth_value", (expr, AsExpression::<BigInt>::as_expression(n)))
+}
+
+/// Comma-separated expressions in a window function call or a [`D1Window`], `()` when there
+/// are none
+pub trait D1WindowTerms {
+    const IS_EMPTY: bool;
+
+    fn walk_terms<'b>(&'b self, out: AstPass<'_, 'b, D1Backend>) -> QueryResult<()>;
+}
+
+/// [`D1WindowTerms`] that can be used in a query selecting from `QS`
+pub trait D1WindowTermsOn<QS>: D1WindowTerms {}
+
+impl D1WindowTerms for () {
+    const IS_EMPTY: bool = true;
+
+    fn walk_terms<'b>(&'b self, _out: AstPass<'_, 'b, D1Backend>) -> QueryResult<()> {
+        Ok(())
+    }
+}
+
+impl<QS> D1WindowTermsOn<QS> for () {}
+
+impl<A: QueryFragment<D1Backend>> D1WindowTerms for (A,) {
+    const IS_EMPTY: bool = false;
+
+    fn walk_terms<'b>(&'b self, out: AstPass<'_, 'b, D1Backend>) -> QueryResult<()> {
+        self.0.walk_ast(out)
+    }
+}
+
+impl<A: QueryFragment<D1Backend> + AppearsOnTable<QS>, QS> D1WindowTermsOn<QS> for (A,) {}
+
+impl<A: QueryFragment<D1Backend>, B: QueryFragment<D1Backend>> D1WindowTerms for (A, B) {
+    const IS_EMPTY: bool = false;
+
+    fn walk_terms<'b>(&'b self, mut out: AstPass<'_, 'b, D1Backend>) -> QueryResult<()> {
+        self.0.walk_ast(out.reborrow())?;
+        out.push_sql(", ");
+        self.1.walk_ast(out.reborrow())
+    }
+}
+
+impl<A, B, QS> D1WindowTermsOn<QS> for (A, B)
+where
+    A: QueryFragment<D1Backend> + AppearsOnTable<QS>,
+    B: QueryFragment<D1Backend> + AppearsOnTable<QS>,
+{
+}
+
+#[cfg(test)]
+mod tests {
+    use diesel::{debug_query, dsl::sum, prelude::*};
+
+    use super::*;
+
+    diesel::table! {
+        scores (id) {
+            id -> Integer,
+            player -> Text,
+            league -> Text,
+            points -> Integer,
+        }
+    }
+
+    fn sql<Q: QueryFragment<D1Backend>>(query: &Q) -> String {
+        debug_query::<D1Backend, _>(query).to_string()
+    }
+
+    #[test]
+    fn renders_ranking_functions() {
+        let query = scores::table.select((scores::player, rank().over(window().order_by(scores::points.desc()))));
+        assert_eq!(
+            sql(&query),
+            "SELECT `scores`.`player`, rank() OVER (ORDER BY `scores`.`points` DESC) FROM `scores` -- binds: []"
+        );
+
+        let query = scores::table.select((
+            row_number().over(window()),
+            dense_rank().over(window().partition_by(scores::league)),
+            percent_rank().over(window().order_by(scores::points)),
+            cume_dist().over(window().order_by(scores::points)),
+            ntile(4).over(window().order_by(scores::points)),
+        ));
+        assert_eq!(
+            sql(&query),
+            "SELECT row_number() OVER (), dense_rank() OVER (PARTITION BY `scores`.`league`), \
+            percent_rank() OVER (ORDER BY `scores`.`points`), cume_dist() OVER (ORDER BY `scores`.`points`), \
+            ntile(?) OVER (ORDER BY `scores`.`points`) FROM `scores` -- binds: [4]"
+        );
+    }
+
+    #[test]
+    fn renders_value_functions() {
+        let by_points = || window().partition_by(scores::league).order_by(scores::points);
+        let query = scores::table.select((
+            lag(scores::points, 1).over(by_points()),
+            lead(scores::player, 2).over(by_points()),
+            first_value(scores::player).over(by_points()),
+            last_value(scores::player).over(by_points()),
+            nth_value(scores::points, 3).over(by_points()),
+        ));
+        assert_eq!(
+            sql(&query),
+            "SELECT lag(`scores`.`points`, ?) OVER (PARTITION BY `scores`.`league` ORDER BY `scores`.`points`), \
+            lead(`scores`.`player`, ?) OVER (PARTITION BY `scores`.`league` ORDER BY `scores`.`points`), \
+            first_value(`scores`.`player`) OVER (PARTITION BY `scores`.`league` ORDER BY `scores`.`points`), \
+            last_value(`scores`.`player`) OVER (PARTITION BY `scores`.`league` ORDER BY `scores`.`points`), \
+            nth_value(`scores`.`points`, ?) OVER (PARTITION BY `scores`.`league` ORDER BY `scores`.`points`) \
+            FROM `scores` -- binds: [1, 2, 3]"
+        );
+    }
+
+    #[test]
+    fn renders_aggregates_over_frames() {
+        let query = scores::table.select((
+            scores::id,
+            sum(scores::points).over(
+                window()
+                    .partition_by(scores::player)
+                    .order_by(scores::id)
+                    .rows_between(D1FrameBound::UnboundedPreceding, D1FrameBound::CurrentRow),
+            ),
+        ));
+        assert_eq!(
+            sql(&query),
+            "SELECT `scores`.`id`, sum(`scores`.`points`) OVER (PARTITION BY `scores`.`player` \
+            ORDER BY `scores`.`id` ROWS BETWEEN UNBOUNDED PRECEDING AND CURRENT ROW) FROM `scores` -- binds: []"
+        );
+
+        let query = scores::table.select(sum(scores::points).over(
+            window()
+                .order_by(scores::points)
+                .range_between(D1FrameBound::Preceding(10), D1FrameBound::Following(5)),
+        ));
+        assert_eq!(
+            sql(&query),
+            "SELECT sum(`scores`.`points`) OVER (ORDER BY `scores`.`points` RANGE BETWEEN 10 PRECEDING AND 5 FOLLOWING) \
+            FROM `scores` -- binds: []"
+        );
+
+        // a frame alone, and the last frame set wins
+        let query = scores::table.select(
+            sum(scores::points).over(
+                window()
+                    .rows_between(D1FrameBound::Preceding(1), D1FrameBound::CurrentRow)
+                    .groups_between(D1FrameBound::CurrentRow, D1FrameBound::UnboundedFollowing),
+            ),
+        );
+        assert_eq!(
+            sql(&query),
+            "SELECT sum(`scores`.`points`) OVER (GROUPS BETWEEN CURRENT ROW AND UNBOUNDED FOLLOWING) FROM `scores` -- binds: []"
+        );
+    }
+}
@@ -0,0 +1,974 @@
+//! Generating table rebuilds for schema changes SQLite's `ALTER TABLE` can't make
+//!
+//! Changing a column's type, nullability or default, dropping constrained columns and adding
+//! constraints all need SQLite's "create a new table, copy, drop, rename" procedure. This
+//! module writes that procedure out for D1, which doesn't allow `PRAGMA foreign_keys = off` and
+//! needs `PRAGMA defer_foreign_keys = on` instead:
+//!
+//! ```ignore
+//! let schema = conn.table_schema("users").await?;
+//! let sql = schema.rebuild_sql(&[
+//!     D1TableChange::AlterColumn {
+//!         column: D1ColumnDefinition::new("email", "TEXT").not_null(),
+//!         value: Some("COALESCE(email, '')".to_owned()),
+//!     },
+//!     D1TableChange::AddConstraint("UNIQUE (email)".to_owned()),
+//! ])?;
+//! // save `sql` as a migration
+//! ```
+
+use std::{error::Error, fmt};
+
+use diesel::{
+    sql_types::{Integer, Nullable, Text},
+    QueryResult, QueryableByName,
+};
+use diesel_async::RunQueryDsl;
+
+use crate::{utils::quote_identifier, D1Connection};
+
+/// A column of a [`D1TableSchema`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct D1ColumnDefinition {
+    pub name: String,
+    /// Empty for columns without a declared type
+    pub declared_type: String,
+    pub not_null: bool,
+    /// Default value as an SQL expression
+    pub default_value: Option<String>,
+    /// Any other column constraints, e.g. `COLLATE NOCASE` or `CHECK (age >= 0)`
+    pub constraints: Option<String>,
+}
+
+impl D1ColumnDefinition {
+    pub fn new(name: impl Into<String>, declared_type: impl Into<String>) -> Self {
+        D1ColumnDefinition {
+            name: name.into(),
+            declared_type: declared_type.into(),
+            not_null: false,
+            default_value: None,
+            constraints: None,
+        }
+    }
+
+    pub fn not_null(mut self) -> Self {
+        self.not_null = true;
+        self
+    }
+
+    pub fn default_value(mut self, default_value: impl Into<String>) -> Self {
+        self.default_value = Some(default_value.into());
+        self
+    }
+
+    pub fn constraints(mut self, constraints: impl Into<String>) -> Self {
+        self.constraints = Some(constraints.into());
+        self
+    }
+}
+
+/// A foreign key of a [`D1TableSchema`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct D1ForeignKeyDefinition {
+    pub columns: Vec<String>,
+    pub parent_table: String,
+    /// Empty when the key references the parent's primary key implicitly
+    pub parent_columns: Vec<String>,
+    pub on_update: String,
+    pub on_delete: String,
+}
+
+/// A foreign key of another table that references the rebuilt one
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct D1ReferencingForeignKey {
+    pub table: String,
+    pub foreign_key: D1ForeignKeyDefinition,
+}
+
+/// An index, trigger or view, kept as the SQL it was created with
+#[derive(Debug, Clone, PartialEq, Eq, QueryableByName)]
+pub struct D1SchemaObject {
+    #[diesel(sql_type = Text)]
+    pub name: String,
+    #[diesel(sql_type = Text)]
+    pub sql: String,
+}
+
+/// Everything needed to rebuild a table, see [`D1Connection::table_schema`]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct D1TableSchema {
+    pub name: String,
+    pub columns: Vec<D1ColumnDefinition>,
+    pub primary_key: Vec<String>,
+    /// Whether the primary key is `INTEGER PRIMARY KEY AUTOINCREMENT`
+    pub autoincrement: bool,
+    pub foreign_keys: Vec<D1ForeignKeyDefinition>,
+    /// Table constraints, e.g. `UNIQUE (a, b)` or `CHECK (a < b)`
+    pub constraints: Vec<String>,
+    /// Table options, e.g. `WITHOUT ROWID` or `STRICT`
+    pub options: Vec<String>,
+    /// Indexes created with `CREATE INDEX`, the ones of `UNIQUE` constraints are in `constraints`
+    pub indexes: Vec<D1SchemaObject>,
+    pub triggers: Vec<D1SchemaObject>,
+    /// Foreign keys of other tables pointing at this one. Dropping the old table deletes its
+    /// rows, which `defer_foreign_keys` doesn't keep from cascading to these.
+    pub referenced_by: Vec<D1ReferencingForeignKey>,
+    /// Triggers of other tables that use this one, dropped and created again around the
+    /// rebuild like views
+    pub referencing_triggers: Vec<D1SchemaObject>,
+    /// Views are dropped and created again around the rebuild, as SQLite checks them when
+    /// the new table is renamed
+    pub views: Vec<D1SchemaObject>,
+    /// Parts of the table SQLite doesn't report through PRAGMAs. [`rebuild_sql`] refuses to
+    /// run while there are any: add them back through `constraints` or the column definitions,
+    /// then clear this to rebuild without them.
+    ///
+    /// [`rebuild_sql`]: D1TableSchema::rebuild_sql
+    pub warnings: Vec<String>,
+}
+
+/// A change to make to a [`D1TableSchema`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum D1TableChange {
+    /// `value` is an SQL expression over the old columns to fill the new column with,
+    /// otherwise it gets its default
+    AddColumn {
+        column: D1ColumnDefinition,
+        value: Option<String>,
+    },
+    DropColumn(String),
+    RenameColumn {
+        from: String,
+        to: String,
+    },
+    /// Replaces the definition of the column with the same name. `value` is an SQL expression
+    /// over the old columns to copy instead of the column itself, e.g. `COALESCE(email, '')`
+    /// when making it `NOT NULL`.
+    AlterColumn {
+        column: D1ColumnDefinition,
+        value: Option<String>,
+    },
+    SetPrimaryKey(Vec<String>),
+    AddForeignKey(D1ForeignKeyDefinition),
+    /// Drops the foreign key over exactly these columns
+    DropForeignKey(Vec<String>),
+    AddConstraint(String),
+    /// Drops a table constraint, written exactly as in [`D1TableSchema::constraints`]
+    DropConstraint(String),
+    /// Full `CREATE INDEX` statement, run after the rebuild
+    CreateIndex(String),
+    DropIndex(String),
+    /// Full `CREATE TRIGGER` statement, run after the rebuild
+    CreateTrigger(String),
+    DropTrigger(String),
+    /// Full `CREATE VIEW` statement, run after the rebuild
+    CreateView(String),
+    DropView(String),
+}
+
+/// Why [`D1TableSchema::rebuild_sql`] couldn't generate a rebuild
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum D1RebuildError {
+    UnknownColumn(String),
+    DuplicateColumn(String),
+    UnknownForeignKey(Vec<String>),
+    UnknownConstraint(String),
+    UnknownIndex(String),
+    UnknownTrigger(String),
+    UnknownView(String),
+    /// A new `NOT NULL` column has neither a default nor a value to fill it with
+    MissingValue(String),
+    /// An index, trigger or view that is kept uses a dropped or renamed column. Drop it and
+    /// create it again with the new column names.
+    ColumnInUse {
+        object: String,
+        column: String,
+    },
+    /// Another table references this one with an `ON DELETE` action, which would run on its
+    /// rows when the old table is dropped. Change the key to `NO ACTION` first.
+    CascadingForeignKey {
+        table: String,
+        on_delete: String,
+    },
+    /// A foreign key of another table references a dropped or renamed column
+    ReferencedColumn {
+        table: String,
+        column: String,
+    },
+    /// A column of the primary key is dropped, set a primary key without it first
+    PrimaryKeyColumn(String),
+    /// The table uses what [`D1TableSchema::warnings`] lists, which the rebuild would lose
+    NotRecreated(Vec<String>),
+}
+
+impl fmt::Display for D1RebuildError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            D1RebuildError::UnknownColumn(column) => write!(f, "unknown column `{column}`"),
+            D1RebuildError::DuplicateColumn(column) => write!(f, "column `{column}` already exists"),
+            D1RebuildError::UnknownForeignKey(columns) => {
+                write!(f, "no foreign key over columns ({})", columns.join(", "))
+            },
+            D1RebuildError::UnknownConstraint(constraint) => write!(f, "unknown constraint `{constraint}`"),
+            D1RebuildError::UnknownIndex(index) => write!(f, "unknown index `{index}`"),
+            D1RebuildError::UnknownTrigger(trigger) => write!(f, "unknown trigger `{trigger}`"),
+            D1RebuildError::UnknownView(view) => write!(f, "unknown view `{view}`"),
+            D1RebuildError::MissingValue(column) => write!(
+                f,
+                "column `{column}` is NOT NULL without a default, give it a value to fill existing rows with"
+            ),
+            D1RebuildError::ColumnInUse { object, column } => write!(
+                f,
+                "`{object}` uses column `{column}`, drop it and create it again with the new columns"
+            ),
+            D1RebuildError::CascadingForeignKey { table, on_delete } => write!(
+                f,
+                "`{table}` references the table with ON DELETE {on_delete}, which dropping the old table would run"
+            ),
+            D1RebuildError::ReferencedColumn { table, column } => {
+                write!(f, "column `{column}` is referenced by a foreign key of `{table}`")
+            },
+            D1RebuildError::PrimaryKeyColumn(column) => {
+                write!(f, "column `{column}` is part of the primary key, set one without it first")
+            },
+            D1RebuildError::NotRecreated(warnings) => write!(
+                f,
+                "{}; add them back by hand and clear `warnings` to rebuild anyway",
+                warnings.join("; ")
+            ),
+        }
+    }
+}
+
+impl Error for D1RebuildError {}
+
+impl D1TableSchema {
+    /// The migration script that applies `changes` by rebuilding the table
+    pub fn rebuild_sql(&self, changes: &[D1TableChange]) -> Result<String, D1RebuildError> {
+        if !self.warnings.is_empty() {
+            return Err(D1RebuildError::NotRecreated(self.warnings.clone()));
+        }
+        // RESTRICT fails the rebuild by itself instead of changing other tables
+        for referencing in &self.referenced_by {
+            let on_delete = &referencing.foreign_key.on_delete;
+            if !on_delete.is_empty() && !on_delete.eq_ignore_ascii_case("NO ACTION") && !on_delete.eq_ignore_ascii_case("RESTRICT") {
+                return Err(D1RebuildError::CascadingForeignKey {
+                    table: referencing.table.clone(),
+                    on_delete: on_delete.clone(),
+                });
+            }
+        }
+
+        let mut new = self.clone();
+        // new column name -> expression over the old table
+        let mut values: Vec<(String, Option<String>)> = self
+            .columns
+            .iter()
+            .map(|column| (column.name.clone(), Some(quote_identifier(&column.name))))
+            .collect();
+        let mut removed_columns = Vec::new();
+        let mut created = Vec::new();
+
+        for change in changes {
+            match change {
+                D1TableChange::AddColumn { column, value } => {
+                    if new.column_index(&column.name).is_some() {
+                        return Err(D1RebuildError::DuplicateColumn(column.name.clone()));
+                    }
+                    new.columns.push(column.clone());
+                    values.push((column.name.clone(), value.clone()));
+                },
+                D1TableChange::DropColumn(name) => {
+                    let index = new.existing_column(name)?;
+                    if new.primary_key.contains(name) {
+                        return Err(D1RebuildError::PrimaryKeyColumn(name.clone()));
+                    }
+                    self.check_unreferenced(name)?;
+                    new.columns.remove(index);
+                    values.retain(|(column, _)| column != name);
+                    removed_columns.push(name.clone());
+                },
+                D1TableChange::RenameColumn { from, to } => {
+                    let index = new.existing_column(from)?;
+                    self.check_unreferenced(from)?;
+                    if new.column_index(to).is_some() {
+                        return Err(D1RebuildError::DuplicateColumn(to.clone()));
+                    }
+                    new.columns[index].name = to.clone();
+                    for (column, _) in values.iter_mut().filter(|(column, _)| column == from) {
+                        column.clone_from(to);
+                    }
+                    for column in new.primary_key.iter_mut().chain(
+                        new.foreign_keys.iter_mut().flat_map(|foreign_key| foreign_key.columns.iter_mut()),
+                    ) {
+                        if column == from {
+                            column.clone_from(to);
+                        }
+                    }
+                    removed_columns.push(from.clone());
+                },
+                D1TableChange::AlterColumn { column, value } => {
+                    let index = new.existing_column(&column.name)?;
+                    new.columns[index] = column.clone();
+                    if let Some(value) = value {
+                        for (_, expression) in values.iter_mut().filter(|(name, _)| *name == column.name) {
+                            *expression = Some(value.clone());
+                        }
+                    }
+                },
+                D1TableChange::SetPrimaryKey(columns) => {
+                    for column in columns {
+                        new.existing_column(column)?;
+                    }
+                    new.primary_key.clone_from(columns);
+                    new.autoincrement = false;
+                },
+                D1TableChange::AddForeignKey(foreign_key) => new.foreign_keys.push(foreign_key.clone()),
+                D1TableChange::DropForeignKey(columns) => {
+                    let index = new
+                        .foreign_keys
+                        .iter()
+                        .position(|foreign_key| foreign_key.columns == *columns)
+                        .ok_or_else(|| D1RebuildError::UnknownForeignKey(columns.clone()))?;
+                    new.foreign_keys.remove(index);
+                },
+                D1TableChange::AddConstraint(constraint) => new.constraints.push(constraint.clone()),
+                D1TableChange::DropConstraint(constraint) => {
+                    let index = new
+                        .constraints
+                        .iter()
+                        .position(|existing| existing == constraint)
+                        .ok_or_else(|| D1RebuildError::UnknownConstraint(constraint.clone()))?;
+                    new.constraints.remove(index);
+                },
+                D1TableChange::CreateIndex(sql) | D1TableChange::CreateTrigger(sql) | D1TableChange::CreateView(sql) => {
+                    created.push(sql.clone())
+                },
+                D1TableChange::DropIndex(name) => {
+                    let index = new
+                        .indexes
+                        .iter()
+                        .position(|index| index.name == *name)
+                        .ok_or_else(|| D1RebuildError::UnknownIndex(name.clone()))?;
+                    new.indexes.remove(index);
+                },
+                D1TableChange::DropTrigger(name) => {
+                    if let Some(index) = new.triggers.iter().position(|trigger| trigger.name == *name) {
+                        new.triggers.remove(index);
+                    } else {
+                        let index = new
+                            .referencing_triggers
+                            .iter()
+                            .position(|trigger| trigger.name == *name)
+                            .ok_or_else(|| D1RebuildError::UnknownTrigger(name.clone()))?;
+                        new.referencing_triggers.remove(index);
+                    }
+                },
+                D1TableChange::DropView(name) => {
+                    let index = new
+                        .views
+                        .iter()
+                        .position(|view| view.name == *name)
+                        .ok_or_else(|| D1RebuildError::UnknownView(name.clone()))?;
+                    new.views.remove(index);
+                },
+            }
+        }
+
+        // whatever is created again as it was can't refer to columns that are gone
+        let kept = new
+            .indexes
+            .iter()
+            .chain(&new.triggers)
+            .chain(&new.referencing_triggers)
+            .chain(&new.views)
+            .map(|object| (object.name.as_str(), object.sql.as_str()))
+            .chain(new.constraints.iter().map(|constraint| (constraint.as_str(), constraint.as_str())));
+        for (object, sql) in kept {
+            if let Some(column) = removed_columns.iter().find(|column| mentions(sql, column)) {
+                return Err(D1RebuildError::ColumnInUse {
+                    object: object.to_owned(),
+                    column: column.clone(),
+                });
+            }
+        }
+        for foreign_key in &new.foreign_keys {
+            if let Some(column) = foreign_key.columns.iter().find(|column| new.column_index(column).is_none()) {
+                return Err(D1RebuildError::ColumnInUse {
+                    object: format!("FOREIGN KEY ({})", quote_identifiers(&foreign_key.columns)),
+                    column: column.clone(),
+                });
+            }
+        }
+
+        // columns without a value are left to their default
+        let mut copied_columns = Vec::new();
+        let mut copied_values = Vec::new();
+        for (column, value) in &values {
+            match value {
+                Some(value) => {
+                    copied_columns.push(quote_identifier(column));
+                    copied_values.push(value.clone());
+                },
+                None => {
+                    let definition = &new.columns[new.existing_column(column)?];
+                    if definition.not_null && definition.default_value.is_none() {
+                        return Err(D1RebuildError::MissingValue(column.clone()));
+                    }
+                },
+            }
+        }
+
+        let table = quote_identifier(&self.name);
+        let new_table = quote_identifier(&format!("_new_{}", self.name));
+        let mut sql = String::new();
+
+        // D1 doesn't allow turning foreign keys off, but checks can wait until the end
+        sql.push_str("PRAGMA defer_foreign_keys = on;\n");
+        for view in &self.views {
+            sql.push_str(&format!("DROP VIEW {};\n", quote_identifier(&view.name)));
+        }
+        // they would stop the rename like views, and aren't dropped with the table
+        for trigger in &self.referencing_triggers {
+            sql.push_str(&format!("DROP TRIGGER {};\n", quote_identifier(&trigger.name)));
+        }
+        sql.push_str(&new.create_table_sql(&new_table));
+        if !copied_columns.is_empty() {
+            sql.push_str(&format!(
+                "INSERT INTO {new_table} ({}) SELECT {} FROM {table};\n",
+                copied_columns.join(", "),
+                copied_values.join(", ")
+            ));
+        }
+        sql.push_str(&format!("DROP TABLE {table};\n"));
+        sql.push_str(&format!("ALTER TABLE {new_table} RENAME TO {table};\n"));
+        for object in new
+            .indexes
+            .iter()
+            .chain(&new.triggers)
+            .chain(&new.referencing_triggers)
+            .chain(&new.views)
+        {
+            sql.push_str(&format!("{};\n", object.sql.trim().trim_end_matches(';')));
+        }
+        for object in created {
+            sql.push_str(&format!("{};\n", object.trim().trim_end_matches(';')));
+        }
+        sql.push_str("PRAGMA defer_foreign_keys = off;\n");
+
+        Ok(sql)
+    }
+
+    fn create_table_sql(&self, name: &str) -> String {
+        // AUTOINCREMENT can only be written inline on the column
+        let inline_primary_key = match self.primary_key.as_slice() {
+            [column] if self.autoincrement => Some(column),
+            _ => None,
+        };
+
+        let mut definitions = self
+            .columns
+            .iter()
+            .map(|column| {
+                let mut definition = quote_identifier(&column.name);
+                if !column.declared_type.is_empty() {
+                    definition.push(' ');
+                    definition.push_str(&column.declared_type);
+                }
+                if inline_primary_key == Some(&column.name) {
+                    definition.push_str(" PRIMARY KEY AUTOINCREMENT");
+                }
+                if column.not_null {
+                    definition.push_str(" NOT NULL");
+                }
+                if let Some(default_value) = &column.default_value {
+                    definition.push_str(&format!(" DEFAULT {default_value}"));
+                }
+                if let Some(constraints) = &column.constraints {
+                    definition.push(' ');
+                    definition.push_str(constraints);
+                }
+                definition
+            })
+            .collect::<Vec<_>>();
+
+        if !self.primary_key.is_empty() && inline_primary_key.is_none() {
+            definitions.push(format!("PRIMARY KEY ({})", quote_identifiers(&self.primary_key)));
+        }
+        for foreign_key in &self.foreign_keys {
+            let mut definition = format!(
+                "FOREIGN KEY ({}) REFERENCES {}",
+                quote_identifiers(&foreign_key.columns),
+                quote_identifier(&foreign_key.parent_table)
+            );
+            if !foreign_key.parent_columns.is_empty() {
+                definition.push_str(&format!(" ({})", quote_identifiers(&foreign_key.parent_columns)));
+            }
+            for (action, rule) in [("UPDATE", &foreign_key.on_update), ("DELETE", &foreign_key.on_delete)] {
+                if !rule.is_empty() && !rule.eq_ignore_ascii_case("NO ACTION") {
+                    definition.push_str(&format!(" ON {action} {rule}"));
+                }
+            }
+            definitions.push(definition);
+        }
+        definitions.extend(self.constraints.iter().cloned());
+
+        let mut sql = format!("CREATE TABLE {name} (\n    {}\n)", definitions.join(",\n    "));
+        if !self.options.is_empty() {
+            sql.push(' ');
+            sql.push_str(&self.options.join(", "));
+        }
+        sql.push_str(";\n");
+        sql
+    }
+
+    /// Foreign keys of other tables keep referring to `column` by name, whatever the rebuild
+    /// does with it. Keys to the implicit primary key follow it instead.
+    fn check_unreferenced(&self, column: &str) -> Result<(), D1RebuildError> {
+        let referencing = self.referenced_by.iter().find(|referencing| {
+            let parent_columns = &referencing.foreign_key.parent_columns;
+            parent_columns.iter().any(|parent_column| parent_column.eq_ignore_ascii_case(column))
+        });
+        match referencing {
+            Some(referencing) => Err(D1RebuildError::ReferencedColumn {
+                table: referencing.table.clone(),
+                column: column.to_owned(),
+            }),
+            None => Ok(()),
+        }
+    }
+
+    fn column_index(&self, name: &str) -> Option<usize> {
+        self.columns.iter().position(|column| column.name == name)
+    }
+
+    fn existing_column(&self, name: &str) -> Result<usize, D1RebuildError> {
+        self.column_index(name)
+            .ok_or_else(|| D1RebuildError::UnknownColumn(name.to_owned()))
+    }
+}
+
+fn quote_identifiers(identifiers: &[String]) -> String {
+    identifiers
+        .iter()
+        .map(|identifier| quote_identifier(identifier))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Whether `identifier` appears in `sql` as a whole word, ignoring case like SQLite does
+fn mentions(sql: &str, identifier: &str) -> bool {
+    let sql = sql.to_lowercase();
+    let identifier = identifier.to_lowercase();
+    let is_word = |char: char| char.is_alphanumeric() || char == '_';
+
+    sql.match_indices(&identifier).any(|(start, _)| {
+        let before = sql[..start].chars().next_back();
+        let after = sql[start + identifier.len()..].chars().next();
+        !before.is_some_and(is_word) && !after.is_some_and(is_word)
+    })
+}
+
+/// The parts of `CREATE TABLE` after the closing parenthesis
+fn table_options(sql: &str) -> Vec<String> {
+    sql.rfind(')')
+        .map(|end| {
+            sql[end + 1..]
+                .split(',')
+                .map(|option| option.split_whitespace().collect::<Vec<_>>().join(" ").to_uppercase())
+                .filter(|option| !option.is_empty() && option != ";")
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+#[derive(QueryableByName)]
+struct ObjectRow {
+    #[diesel(sql_type = Text)]
+    kind: String,
+    #[diesel(sql_type = Text)]
+    name: String,
+    #[diesel(sql_type = Text)]
+    table_name: String,
+    #[diesel(sql_type = Nullable<Text>)]
+    sql: Option<String>,
+}
+
+/// One column of another table's foreign key to the rebuilt table
+#[derive(QueryableByName)]
+struct ReferencingKeyRow {
+    #[diesel(sql_type = Text)]
+    table: String,
+    #[diesel(sql_type = Integer)]
+    seq: i32,
+    #[diesel(sql_type = Text)]
+    column: String,
+    #[diesel(sql_type = Nullable<Text>)]
+    parent_column: Option<String>,
+    #[diesel(sql_type = Text)]
+    on_update: String,
+    #[diesel(sql_type = Text)]
+    on_delete: String,
+}
+
+impl D1Connection {
+    /// Reads what [`D1TableSchema::rebuild_sql`] needs to rebuild `table`
+    pub async fn table_schema(&mut self, table: &str) -> QueryResult<D1TableSchema> {
+        let objects: Vec<ObjectRow> = diesel::sql_query(
+            "SELECT type AS kind, name, tbl_name AS table_name, sql FROM sqlite_master \
+            WHERE (tbl_name = ? AND type IN ('table', 'index')) OR type IN ('trigger', 'view') \
+            ORDER BY rowid",
+        )
+        .bind::<Text, _>(table)
+        .load(self)
+        .await?;
+
+        let columns = self.columns(table).await?;
+        let foreign_keys = self.foreign_keys(table).await?;
+        let indexes = self.indexes(table).await?;
+        let referencing_keys: Vec<ReferencingKeyRow> = diesel::sql_query(
+            "SELECT list.name AS \"table\", keys.seq, keys.\"from\" AS \"column\", keys.\"to\" AS parent_column, \
+            keys.on_update, keys.on_delete \
+            FROM sqlite_master AS list \
+            JOIN pragma_foreign_key_list(list.name) AS keys \
+            WHERE list.type = 'table' AND keys.\"table\" = ? COLLATE NOCASE AND list.name <> ? \
+            ORDER BY list.name, keys.id, keys.seq",
+        )
+        .bind::<Text, _>(table)
+        .bind::<Text, _>(table)
+        .load(self)
+        .await?;
+
+        let mut schema = D1TableSchema {
+            name: table.to_owned(),
+            ..Default::default()
+        };
+
+        let mut primary_key = columns
+            .iter()
+            .filter(|column| column.primary_key > 0)
+            .collect::<Vec<_>>();
+        primary_key.sort_by_key(|column| column.primary_key);
+        schema.primary_key = primary_key.iter().map(|column| column.name.clone()).collect();
+
+        schema.columns = columns
+            .into_iter()
+            .map(|column| D1ColumnDefinition {
+                name: column.name,
+                declared_type: column.declared_type,
+                not_null: column.not_null,
+                default_value: column.default_value,
+                constraints: None,
+            })
+            .collect();
+
+        for foreign_key in foreign_keys {
+            match schema.foreign_keys.last_mut() {
+                // the columns of a key are listed one after the other, starting at seq 0
+                Some(last) if foreign_key.seq > 0 => {
+                    last.columns.push(foreign_key.column);
+                    last.parent_columns.extend(foreign_key.parent_column);
+                },
+                _ => schema.foreign_keys.push(D1ForeignKeyDefinition {
+                    columns: vec![foreign_key.column],
+                    parent_table: foreign_key.parent_table,
+                    parent_columns: foreign_key.parent_column.into_iter().collect(),
+                    on_update: foreign_key.on_update,
+                    on_delete: foreign_key.on_delete,
+                }),
+            }
+        }
+
+        for key in referencing_keys {
+            match schema.referenced_by.last_mut() {
+                Some(last) if key.seq > 0 => {
+                    last.foreign_key.columns.push(key.column);
+                    last.foreign_key.parent_columns.extend(key.parent_column);
+                },
+                _ => schema.referenced_by.push(D1ReferencingForeignKey {
+                    table: key.table,
+                    foreign_key: D1ForeignKeyDefinition {
+                        columns: vec![key.column],
+                        parent_table: table.to_owned(),
+                        parent_columns: key.parent_column.into_iter().collect(),
+                        on_update: key.on_update,
+                        on_delete: key.on_delete,
+                    },
+                }),
+            }
+        }
+
+        for index in indexes.iter().filter(|index| index.origin == "u") {
+            let columns = index.columns.iter().flatten().cloned().collect::<Vec<_>>();
+            schema.constraints.push(format!("UNIQUE ({})", quote_identifiers(&columns)));
+        }
+
+        for object in objects {
+            let Some(sql) = object.sql else {
+                continue;
+            };
+            match object.kind.as_str() {
+                "table" => {
+                    let upper = sql.to_uppercase();
+                    schema.autoincrement = upper.contains("AUTOINCREMENT");
+                    schema.options = table_options(&sql);
+                    for keyword in ["CHECK", "COLLATE", "GENERATED"] {
+                        if mentions(&upper, keyword) {
+                            schema.warnings.push(format!(
+                                "`{table}` uses {keyword}, which isn't recreated by the rebuild"
+                            ));
+                        }
+                    }
+                },
+                "index" => schema.indexes.push(D1SchemaObject { name: object.name, sql }),
+                "trigger" if object.table_name == table => {
+                    schema.triggers.push(D1SchemaObject { name: object.name, sql })
+                },
+                "trigger" if mentions(&sql, table) => {
+                    schema.referencing_triggers.push(D1SchemaObject { name: object.name, sql })
+                },
+                _ if mentions(&sql, table) => schema.views.push(D1SchemaObject { name: object.name, sql }),
+                _ => {},
+            }
+        }
+
+        Ok(schema)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parent(on_delete: &str) -> D1TableSchema {
+        D1TableSchema {
+            name: "users".to_owned(),
+            columns: vec![
+                D1ColumnDefinition::new("id", "INTEGER").not_null(),
+                D1ColumnDefinition::new("email", "TEXT"),
+            ],
+            primary_key: vec!["id".to_owned()],
+            referenced_by: vec![D1ReferencingForeignKey {
+                table: "posts".to_owned(),
+                foreign_key: D1ForeignKeyDefinition {
+                    columns: vec!["user_id".to_owned()],
+                    parent_table: "users".to_owned(),
+                    parent_columns: vec!["id".to_owned()],
+                    on_update: "NO ACTION".to_owned(),
+                    on_delete: on_delete.to_owned(),
+                },
+            }],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn refuses_cascading_foreign_keys() {
+        for on_delete in ["CASCADE", "SET NULL", "SET DEFAULT"] {
+            assert_eq!(
+                parent(on_delete).rebuild_sql(&[D1TableChange::DropColumn("email".to_owned())]),
+                Err(D1RebuildError::CascadingForeignKey {
+                    table: "posts".to_owned(),
+                    on_delete: on_delete.to_owned(),
+                })
+            );
+        }
+        for on_delete in ["NO ACTION", "RESTRICT", ""] {
+            assert!(parent(on_delete)
+                .rebuild_sql(&[D1TableChange::DropColumn("email".to_owned())])
+                .is_ok());
+        }
+    }
+
+    #[test]
+    fn recreates_referencing_triggers() {
+        let trigger = "CREATE TRIGGER count_posts AFTER INSERT ON posts BEGIN \
+            UPDATE users SET email = email WHERE id = new.user_id; END";
+        let mut schema = parent("NO ACTION");
+        schema.referencing_triggers.push(D1SchemaObject {
+            name: "count_posts".to_owned(),
+            sql: trigger.to_owned(),
+        });
+
+        let sql = schema
+            .rebuild_sql(&[D1TableChange::AlterColumn {
+                column: D1ColumnDefinition::new("email", "TEXT").not_null().default_value("''"),
+                value: Some(r#"COALESCE("email", '')"#.to_owned()),
+            }])
+            .unwrap();
+        let position = |statement: &str| sql.find(statement).unwrap_or_else(|| panic!("{statement} missing from {sql}"));
+        assert!(position(r#"DROP TRIGGER "count_posts";"#) < position(r#"DROP TABLE "users";"#));
+        assert!(position(r#"ALTER TABLE "_new_users" RENAME TO "users";"#) < position(&format!("{trigger};")));
+
+        assert_eq!(
+            schema.rebuild_sql(&[D1TableChange::DropColumn("email".to_owned())]),
+            Err(D1RebuildError::ColumnInUse {
+                object: "count_posts".to_owned(),
+                column: "email".to_owned(),
+            })
+        );
+        let sql = schema
+            .rebuild_sql(&[
+                D1TableChange::DropColumn("email".to_owned()),
+                D1TableChange::DropTrigger("count_posts".to_owned()),
+            ])
+            .unwrap();
+        assert!(sql.contains(r#"DROP TRIGGER "count_posts";"#) && !sql.contains("CREATE TRIGGER"));
+    }
+
+    fn object(name: &str, sql: &str) -> D1SchemaObject {
+        D1SchemaObject {
+            name: name.to_owned(),
+            sql: sql.to_owned(),
+        }
+    }
+
+    #[test]
+    fn rebuilds_tables_with_their_indexes_triggers_and_views() {
+        let mut schema = parent("NO ACTION");
+        schema.columns.push(D1ColumnDefinition::new("team_id", "INTEGER"));
+        schema.autoincrement = true;
+        schema.foreign_keys.push(D1ForeignKeyDefinition {
+            columns: vec!["team_id".to_owned()],
+            parent_table: "teams".to_owned(),
+            parent_columns: Vec::new(),
+            on_update: "NO ACTION".to_owned(),
+            on_delete: "SET NULL".to_owned(),
+        });
+        schema.constraints.push(r#"UNIQUE ("email", "team_id")"#.to_owned());
+        schema.indexes.push(object("users_team", "CREATE INDEX users_team ON users (team_id)"));
+        schema.triggers.push(object(
+            "users_lowercase",
+            "CREATE TRIGGER users_lowercase AFTER INSERT ON users BEGIN \
+                UPDATE users SET email = lower(email) WHERE id = new.id; END",
+        ));
+        schema.referencing_triggers.push(object(
+            "posts_touch",
+            "CREATE TRIGGER posts_touch AFTER INSERT ON posts BEGIN \
+                UPDATE users SET team_id = team_id WHERE id = new.user_id; END;",
+        ));
+        schema.views.push(object("user_emails", "CREATE VIEW user_emails AS SELECT id, email FROM users"));
+
+        let sql = schema
+            .rebuild_sql(&[
+                D1TableChange::AlterColumn {
+                    column: D1ColumnDefinition::new("email", "TEXT").not_null(),
+                    value: Some(r#"COALESCE("email", '')"#.to_owned()),
+                },
+                D1TableChange::AddColumn {
+                    column: D1ColumnDefinition::new("active", "BOOLEAN").not_null().default_value("1"),
+                    value: None,
+                },
+                D1TableChange::DropIndex("users_team".to_owned()),
+                D1TableChange::CreateIndex("CREATE INDEX users_active ON users (active, team_id)".to_owned()),
+            ])
+            .unwrap();
+
+        assert_eq!(
+            sql,
+            r#"PRAGMA defer_foreign_keys = on;
+DROP VIEW "user_emails";
+DROP TRIGGER "posts_touch";
+CREATE TABLE "_new_users" (
+    "id" INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    "email" TEXT NOT NULL,
+    "team_id" INTEGER,
+    "active" BOOLEAN NOT NULL DEFAULT 1,
+    FOREIGN KEY ("team_id") REFERENCES "teams" ON DELETE SET NULL,
+    UNIQUE ("email", "team_id")
+);
+INSERT INTO "_new_users" ("id", "email", "team_id") SELECT "id", COALESCE("email", ''), "team_id" FROM "users";
+DROP TABLE "users";
+ALTER TABLE "_new_users" RENAME TO "users";
+CREATE TRIGGER users_lowercase AFTER INSERT ON users BEGIN UPDATE users SET email = lower(email) WHERE id = new.id; END;
+CREATE TRIGGER posts_touch AFTER INSERT ON posts BEGIN UPDATE users SET team_id = team_id WHERE id = new.user_id; END;
+CREATE VIEW user_emails AS SELECT id, email FROM users;
+CREATE INDEX users_active ON users (active, team_id);
+PRAGMA defer_foreign_keys = off;
+"#
+        );
+    }
+
+    #[test]
+    fn refuses_changing_referenced_columns() {
+        let mut schema = parent("NO ACTION");
+        schema.columns.push(D1ColumnDefinition::new("handle", "TEXT"));
+        schema.referenced_by.push(D1ReferencingForeignKey {
+            table: "mentions".to_owned(),
+            foreign_key: D1ForeignKeyDefinition {
+                columns: vec!["user_handle".to_owned()],
+                parent_table: "users".to_owned(),
+                parent_columns: vec!["handle".to_owned()],
+                on_update: "NO ACTION".to_owned(),
+                on_delete: "NO ACTION".to_owned(),
+            },
+        });
+
+        let referenced = Err(D1RebuildError::ReferencedColumn {
+            table: "mentions".to_owned(),
+            column: "handle".to_owned(),
+        });
+        assert_eq!(schema.rebuild_sql(&[D1TableChange::DropColumn("handle".to_owned())]), referenced);
+        assert_eq!(
+            schema.rebuild_sql(&[D1TableChange::RenameColumn {
+                from: "handle".to_owned(),
+                to: "username".to_owned(),
+            }]),
+            referenced
+        );
+        assert_eq!(
+            schema.rebuild_sql(&[D1TableChange::RenameColumn {
+                from: "id".to_owned(),
+                to: "user_id".to_owned(),
+            }]),
+            Err(D1RebuildError::ReferencedColumn {
+                table: "posts".to_owned(),
+                column: "id".to_owned(),
+            })
+        );
+
+        // keys to the implicit primary key follow whatever it's called
+        schema.referenced_by.truncate(1);
+        schema.referenced_by[0].foreign_key.parent_columns.clear();
+        assert!(schema
+            .rebuild_sql(&[D1TableChange::RenameColumn {
+                from: "id".to_owned(),
+                to: "user_id".to_owned(),
+            }])
+            .is_ok());
+    }
+
+    #[test]
+    fn refuses_dropping_primary_key_columns() {
+        let mut schema = parent("NO ACTION");
+        schema.referenced_by.clear();
+        let drop_id = D1TableChange::DropColumn("id".to_owned());
+
+        assert_eq!(
+            schema.rebuild_sql(&[drop_id.clone()]),
+            Err(D1RebuildError::PrimaryKeyColumn("id".to_owned()))
+        );
+        let sql = schema
+            .rebuild_sql(&[D1TableChange::SetPrimaryKey(vec!["email".to_owned()]), drop_id])
+            .unwrap();
+        assert!(sql.contains(r#"PRIMARY KEY ("email")"#), "{sql}");
+    }
+
+    #[test]
+    fn refuses_losing_what_pragmas_dont_report() {
+        let mut schema = parent("NO ACTION");
+        schema.warnings = vec!["`users` uses CHECK, which isn't recreated by the rebuild".to_owned()];
+        let change = D1TableChange::AddConstraint("CHECK (length(email) > 3)".to_owned());
+
+        let error = schema.rebuild_sql(&[change.clone()]).unwrap_err();
+        assert_eq!(error, D1RebuildError::NotRecreated(schema.warnings.clone()));
+        assert_eq!(
+            error.to_string(),
+            "`users` uses CHECK, which isn't recreated by the rebuild; \
+             add them back by hand and clear `warnings` to rebuild anyway"
+        );
+
+        schema.warnings.clear();
+        assert!(schema.rebuild_sql(&[change]).unwrap().contains("CHECK (length(email) > 3)"));
+    }
+}
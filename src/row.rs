@@ -6,11 +6,15 @@ use std::{
 use diesel::row::{Field, PartialRow, Row, RowIndex, RowSealed};
 use wasm_bindgen::JsValue;
 
-use crate::{backend::D1Backend, value::D1Value};
+use crate::{
+    backend::D1Backend,
+    value::{D1DecodingMode, D1Value},
+};
 
 pub struct D1Row {
     _js_obj: Rc<RefCell<JsValue>>,
     field_vec: Vec<String>,
+    decoding_mode: D1DecodingMode,
 }
 
 // SAFETY: this is safe under WASM and workers because there's no threads and therefore no race conditions (at least memory ones)
@@ -18,11 +22,12 @@ unsafe impl Send for D1Row {}
 unsafe impl Sync for D1Row {}
 
 impl D1Row {
-    pub fn new(js_value: JsValue, field_vec: Vec<String>) -> Self {
+    pub fn new(js_value: JsValue, field_vec: Vec<String>, decoding_mode: D1DecodingMode) -> Self {
         Self {
             // again
             _js_obj: Rc::new(RefCell::new(js_value)),
             field_vec,
+            decoding_mode,
         }
     }
 }
@@ -49,6 +54,7 @@ impl<'stmt> Row<'stmt, D1Backend> for D1Row {
         Some(D1Field {
             name: name.to_string(),
             row: self._js_obj.borrow(),
+            decoding_mode: self.decoding_mode,
         })
     }
 
@@ -79,6 +85,7 @@ impl RowIndex<&str> for D1Row {
 pub struct D1Field<'stmt> {
     row: Ref<'stmt, JsValue>,
     name: String,
+    decoding_mode: D1DecodingMode,
 }
 
 impl<'stmt> Field<'stmt, D1Backend> for D1Field<'stmt> {
@@ -88,7 +95,13 @@ impl<'stmt> Field<'stmt, D1Backend> for D1Field<'stmt> {
 
     fn value(&self) -> Option<D1Value> {
         let js_value = js_sys::Reflect::get(&self.row, &self.name.clone().into()).ok()?;
+        let value = D1Value::new(js_value, self.name.clone(), self.decoding_mode);
+
+        // returning `None` lets diesel handle `Option<T>` and report unexpected NULLs itself
+        if value.check_null() {
+            return None;
+        }
 
-        Some(D1Value::new(js_value))
+        Some(value)
     }
 }
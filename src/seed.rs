@@ -0,0 +1,759 @@
+//! Seed data for preview databases and tests
+//!
+//! Fixtures are keyed by table name. Records listed in a map get a symbolic name other
+//! records can reference with `"@table.name"`, records listed in an array are anonymous:
+//!
+//! ```json
+//! {
+//!     "users": {
+//!         "alice": { "name": "Alice" },
+//!         "bob": { "name": "Bob" }
+//!     },
+//!     "posts": [
+//!         { "title": "Hello", "author_id": "@users.alice" }
+//!     ]
+//! }
+//! ```
+//!
+//! A reference resolves to the column the foreign key points at, or the primary key of the
+//! referenced table if the column has no foreign key. Records that leave out an `INTEGER
+//! PRIMARY KEY` are given the next free key before anything is inserted, so references to
+//! them resolve without reading rows back. Strings that really start with `@` are written
+//! as `@@`.
+//!
+//! Tables and records are inserted in the order the file lists them, apart from tables that
+//! have to wait for the ones they reference.
+//!
+//! ```ignore
+//! let fixtures = D1Fixtures::from_json(include_str!("../fixtures/preview.json"))?;
+//! let seeded = conn.seed(&fixtures).await?;
+//! let alice_id = seeded.get("users", "alice").and_then(|alice| alice.get("id"));
+//! ```
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    error::Error,
+    fmt,
+};
+
+use js_sys::Array;
+use serde_json::{Number, Value};
+use wasm_bindgen::JsValue;
+
+use crate::{
+    d1_error, prepare, run_batch, utils::quote_identifier, D1Column, D1Connection, D1DynamicValue, D1ForeignKey,
+};
+
+/// Most parameters D1 binds to a single statement
+pub const MAX_BOUND_PARAMETERS: usize = 100;
+
+/// Statements sent per `batch()` call while seeding
+const STATEMENTS_PER_BATCH: usize = 50;
+
+/// Column values of a single fixture record
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct D1FixtureRecord {
+    values: BTreeMap<String, Value>,
+}
+
+impl D1FixtureRecord {
+    pub fn new() -> Self {
+        D1FixtureRecord::default()
+    }
+
+    /// Sets `column`, replacing the value it had
+    pub fn set(mut self, column: impl Into<String>, value: impl Into<Value>) -> Self {
+        self.values.insert(column.into(), value.into());
+        self
+    }
+
+    /// Sets `column` to a reference to the record `name` of `table`
+    pub fn reference(self, column: impl Into<String>, table: &str, name: &str) -> Self {
+        self.set(column, format!("@{table}.{name}"))
+    }
+
+    pub fn get(&self, column: &str) -> Option<&Value> {
+        self.values.get(column)
+    }
+}
+
+/// Records of a table, with their names
+type NamedRecords = Vec<(Option<String>, D1FixtureRecord)>;
+
+/// Records to seed a database with, grouped by table
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct D1Fixtures {
+    tables: Vec<(String, NamedRecords)>,
+}
+
+impl D1Fixtures {
+    pub fn new() -> Self {
+        D1Fixtures::default()
+    }
+
+    pub fn from_json(source: &str) -> Result<Self, D1SeedError> {
+        let value = serde_json::from_str(source).map_err(|err| D1SeedError::Parse(err.to_string()))?;
+        D1Fixtures::from_value(value)
+    }
+
+    pub fn from_yaml(source: &str) -> Result<Self, D1SeedError> {
+        let value = serde_yaml::from_str(source).map_err(|err| D1SeedError::Parse(err.to_string()))?;
+        D1Fixtures::from_value(value)
+    }
+
+    pub fn from_toml(source: &str) -> Result<Self, D1SeedError> {
+        let value = toml::from_str(source).map_err(|err| D1SeedError::Parse(err.to_string()))?;
+        D1Fixtures::from_value(value)
+    }
+
+    /// Reads fixtures in the layout described in the [module docs](self)
+    pub fn from_value(value: Value) -> Result<Self, D1SeedError> {
+        let Value::Object(tables) = value else {
+            return Err(D1SeedError::Parse("fixtures must map table names to records".to_owned()));
+        };
+
+        let mut fixtures = D1Fixtures::new();
+        for (table, records) in tables {
+            let records = match records {
+                Value::Object(records) => records
+                    .into_iter()
+                    .map(|(name, record)| (Some(name), record))
+                    .collect::<Vec<_>>(),
+                Value::Array(records) => records.into_iter().map(|record| (None, record)).collect(),
+                _ => {
+                    return Err(D1SeedError::Parse(format!(
+                        "records of `{table}` must be a map of named records or an array"
+                    )))
+                },
+            };
+
+            for (name, record) in records {
+                let Value::Object(values) = record else {
+                    return Err(D1SeedError::Parse(format!("records of `{table}` must map columns to values")));
+                };
+                let record = D1FixtureRecord {
+                    values: values.into_iter().collect(),
+                };
+                fixtures.add(table.clone(), name.as_deref(), record);
+            }
+        }
+        Ok(fixtures)
+    }
+
+    /// Adds a record to `table`, replacing the record with the same `name` if there is one
+    pub fn add(&mut self, table: impl Into<String>, name: Option<&str>, record: D1FixtureRecord) {
+        let table = table.into();
+        let records = match self.tables.iter().position(|(existing, _)| *existing == table) {
+            Some(index) => &mut self.tables[index].1,
+            None => {
+                self.tables.push((table, Vec::new()));
+                &mut self.tables.last_mut().unwrap().1
+            },
+        };
+
+        let name = name.map(str::to_owned);
+        match records.iter_mut().find(|(existing, _)| name.is_some() && *existing == name) {
+            Some((_, existing)) => *existing = record,
+            None => records.push((name, record)),
+        }
+    }
+
+    /// Adds every record of `other`, e.g. to layer test specific records over shared ones
+    pub fn merge(&mut self, other: D1Fixtures) {
+        for (table, records) in other.tables {
+            for (name, record) in records {
+                self.add(table.clone(), name.as_deref(), record);
+            }
+        }
+    }
+
+    /// The record `name` of `table`
+    pub fn get(&self, table: &str, name: &str) -> Option<&D1FixtureRecord> {
+        self.tables
+            .iter()
+            .find(|(existing, _)| existing == table)?
+            .1
+            .iter()
+            .find(|(existing, _)| existing.as_deref() == Some(name))
+            .map(|(_, record)| record)
+    }
+}
+
+/// Computes a column's default from the number of the record
+type ColumnDefault = Box<dyn Fn(usize) -> Value + Send + Sync>;
+
+/// Builds records for a table from defaults, which single records override
+///
+/// ```ignore
+/// let mut users = D1Factory::new("users")
+///     .sequence("email", |n| format!("user{n}@example.com").into())
+///     .default("active", true);
+///
+/// users.add_to(&mut fixtures, Some("admin"), D1FixtureRecord::new().set("role", "admin"));
+/// for _ in 0..20 {
+///     users.add_to(&mut fixtures, None, D1FixtureRecord::new());
+/// }
+/// ```
+pub struct D1Factory {
+    table: String,
+    defaults: Vec<(String, ColumnDefault)>,
+    built: usize,
+}
+
+impl D1Factory {
+    pub fn new(table: impl Into<String>) -> Self {
+        D1Factory {
+            table: table.into(),
+            defaults: Vec::new(),
+            built: 0,
+        }
+    }
+
+    /// Gives `column` the same value in every record
+    pub fn default(self, column: impl Into<String>, value: impl Into<Value>) -> Self {
+        let value = value.into();
+        self.sequence(column, move |_| value.clone())
+    }
+
+    /// Computes `column` from the number of the record, starting at 1
+    pub fn sequence(mut self, column: impl Into<String>, value: impl Fn(usize) -> Value + Send + Sync + 'static) -> Self {
+        let column = column.into();
+        self.defaults.retain(|(existing, _)| *existing != column);
+        self.defaults.push((column, Box::new(value)));
+        self
+    }
+
+    pub fn table(&self) -> &str {
+        &self.table
+    }
+
+    /// The next record, made of the defaults alone
+    pub fn build(&mut self) -> D1FixtureRecord {
+        self.built += 1;
+        let values = self
+            .defaults
+            .iter()
+            .map(|(column, value)| (column.clone(), value(self.built)))
+            .collect();
+        D1FixtureRecord { values }
+    }
+
+    /// Adds the next record with `overrides` applied to `fixtures`
+    pub fn add_to(&mut self, fixtures: &mut D1Fixtures, name: Option<&str>, overrides: D1FixtureRecord) {
+        let mut record = self.build();
+        record.values.extend(overrides.values);
+        fixtures.add(self.table.clone(), name, record);
+    }
+}
+
+#[derive(Debug)]
+pub enum D1SeedError {
+    /// A fixture file couldn't be parsed or isn't laid out as fixtures
+    Parse(String),
+    UnknownTable(String),
+    UnknownColumn {
+        table: String,
+        column: String,
+    },
+    /// A reference to a record that isn't part of the fixtures
+    UnknownRecord(String),
+    /// The referenced record has no value for the column the reference resolves to
+    MissingKey {
+        reference: String,
+        column: String,
+    },
+    /// Tables whose records reference each other, which can't be inserted one after the other
+    CyclicReferences(Vec<String>),
+    Query(diesel::result::Error),
+}
+
+impl fmt::Display for D1SeedError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            D1SeedError::Parse(message) => write!(f, "invalid fixtures: {message}"),
+            D1SeedError::UnknownTable(table) => write!(f, "unknown table `{table}`"),
+            D1SeedError::UnknownColumn { table, column } => write!(f, "unknown column `{column}` of `{table}`"),
+            D1SeedError::UnknownRecord(reference) => write!(f, "reference to unknown record `{reference}`"),
+            D1SeedError::MissingKey { reference, column } => {
+                write!(f, "`{reference}` has no value for `{column}`, which references to it resolve to")
+            },
+            D1SeedError::CyclicReferences(tables) => {
+                write!(f, "records of {} reference each other", tables.join(", "))
+            },
+            D1SeedError::Query(err) => write!(f, "{err}"),
+        }
+    }
+}
+
+impl Error for D1SeedError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            D1SeedError::Query(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<diesel::result::Error> for D1SeedError {
+    fn from(err: diesel::result::Error) -> Self {
+        D1SeedError::Query(err)
+    }
+}
+
+struct TableSchema {
+    columns: Vec<D1Column>,
+    foreign_keys: Vec<D1ForeignKey>,
+}
+
+impl TableSchema {
+    fn primary_key(&self) -> Option<&D1Column> {
+        match self.columns.iter().filter(|column| column.primary_key > 0).collect::<Vec<_>>()[..] {
+            [column] => Some(column),
+            _ => None,
+        }
+    }
+}
+
+impl D1Connection {
+    /// Inserts `fixtures`, referenced tables first, and returns them with generated keys and
+    /// resolved references filled in
+    ///
+    /// Rows are inserted with as many rows per statement as D1's parameter limit allows, and
+    /// the statements sent in batches of 50. Each batch is atomic, the seeding as a whole
+    /// isn't.
+    pub async fn seed(&mut self, fixtures: &D1Fixtures) -> Result<D1Fixtures, D1SeedError> {
+        let existing = self.tables().await?;
+        let mut schemas = HashMap::new();
+        for (table, records) in &fixtures.tables {
+            if !existing.iter().any(|existing| existing.name == *table) {
+                return Err(D1SeedError::UnknownTable(table.clone()));
+            }
+            let schema = TableSchema {
+                columns: self.columns(table).await?,
+                foreign_keys: self.foreign_keys(table).await?,
+            };
+
+            for column in records.iter().flat_map(|(_, record)| record.values.keys()) {
+                if !schema.columns.iter().any(|existing| existing.name == *column) {
+                    return Err(D1SeedError::UnknownColumn {
+                        table: table.clone(),
+                        column: column.clone(),
+                    });
+                }
+            }
+            schemas.insert(table.clone(), schema);
+        }
+
+        let order = insertion_order(fixtures)?;
+        let mut seeded = fixtures.clone();
+        for (table, records) in &mut seeded.tables {
+            self.assign_keys(&schemas[table], table, records).await?;
+        }
+
+        let keyed = seeded.clone();
+        for (table, records) in &mut seeded.tables {
+            for (_, record) in records.iter_mut() {
+                for (column, value) in record.values.iter_mut() {
+                    let Some(text) = value.as_str() else { continue };
+                    if let Some(literal) = text.strip_prefix("@@") {
+                        *value = Value::from(format!("@{literal}"));
+                    } else if let Some(reference) = text.strip_prefix('@') {
+                        *value = resolve_reference(&keyed, &schemas, &schemas[table], column, reference)?;
+                    }
+                }
+            }
+        }
+
+        let statements = order
+            .into_iter()
+            .flat_map(|index| {
+                let (table, records) = &seeded.tables[index];
+                insert_statements(table, records)
+            })
+            .collect::<Vec<_>>();
+
+        for batch in statements.chunks(STATEMENTS_PER_BATCH) {
+            // self-references within a table are only checked once the batch is done
+            let mut prepared = vec![prepare(&self.binding, "PRAGMA defer_foreign_keys = on")?];
+            for (sql, binds) in batch {
+                let statement = prepare(&self.binding, sql)?;
+                let binds = binds.iter().map(|value| bind_value(value)).collect::<Array>();
+                prepared.push(statement.bind(binds).map_err(|err| d1_error(&err))?);
+            }
+            run_batch(&self.binding, &prepared).await?;
+        }
+
+        Ok(seeded)
+    }
+
+    /// Gives records without a value for an `INTEGER PRIMARY KEY` the next free one
+    async fn assign_keys(
+        &mut self,
+        schema: &TableSchema,
+        table: &str,
+        records: &mut [(Option<String>, D1FixtureRecord)],
+    ) -> Result<(), D1SeedError> {
+        let Some(key) = schema.primary_key().filter(|key| key.declared_type.eq_ignore_ascii_case("INTEGER")) else {
+            return Ok(());
+        };
+        if records.iter().all(|(_, record)| record.values.contains_key(&key.name)) {
+            return Ok(());
+        }
+
+        let result = self
+            .load_dynamic(diesel::sql_query(format!(
+                "SELECT COALESCE(MAX({}), 0) FROM {}",
+                quote_identifier(&key.name),
+                quote_identifier(table)
+            )))
+            .await?;
+        let mut next = match result.rows.first().and_then(|row| row.first()) {
+            Some(D1DynamicValue::Integer(max)) => *max,
+            _ => 0,
+        };
+        // keys given in the fixtures aren't free either
+        for (_, record) in records.iter() {
+            if let Some(given) = record.values.get(&key.name).and_then(Value::as_i64) {
+                next = next.max(given);
+            }
+        }
+
+        for (_, record) in records.iter_mut() {
+            if !record.values.contains_key(&key.name) {
+                next += 1;
+                record.values.insert(key.name.clone(), Value::from(next));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Indexes of the fixture tables, with every table after the ones its records reference
+fn insertion_order(fixtures: &D1Fixtures) -> Result<Vec<usize>, D1SeedError> {
+    let dependencies = fixtures
+        .tables
+        .iter()
+        .map(|(table, records)| {
+            records
+                .iter()
+                .flat_map(|(_, record)| record.values.values())
+                .filter_map(Value::as_str)
+                .filter(|value| !value.starts_with("@@"))
+                .filter_map(|value| value.strip_prefix('@')?.split_once('.'))
+                .filter(|(referenced, _)| referenced != table)
+                .filter_map(|(referenced, _)| fixtures.tables.iter().position(|(existing, _)| existing == referenced))
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>();
+
+    let mut order = Vec::with_capacity(fixtures.tables.len());
+    while order.len() < fixtures.tables.len() {
+        let next = (0..fixtures.tables.len()).find(|index| {
+            !order.contains(index) && dependencies[*index].iter().all(|dependency| order.contains(dependency))
+        });
+        match next {
+            Some(index) => order.push(index),
+            None => {
+                let tables = (0..fixtures.tables.len())
+                    .filter(|index| !order.contains(index))
+                    .map(|index| fixtures.tables[index].0.clone())
+                    .collect();
+                return Err(D1SeedError::CyclicReferences(tables));
+            },
+        }
+    }
+    Ok(order)
+}
+
+fn resolve_reference(
+    fixtures: &D1Fixtures,
+    schemas: &HashMap<String, TableSchema>,
+    schema: &TableSchema,
+    column: &str,
+    reference: &str,
+) -> Result<Value, D1SeedError> {
+    let unknown = || D1SeedError::UnknownRecord(reference.to_owned());
+    let (table, name) = reference.split_once('.').ok_or_else(unknown)?;
+    let record = fixtures.get(table, name).ok_or_else(unknown)?;
+
+    let target = schema
+        .foreign_keys
+        .iter()
+        .find(|foreign_key| foreign_key.column == column && foreign_key.parent_table == table)
+        .and_then(|foreign_key| foreign_key.parent_column.clone())
+        .or_else(|| schemas[table].primary_key().map(|key| key.name.clone()))
+        .unwrap_or_else(|| "rowid".to_owned());
+
+    record.get(&target).cloned().ok_or_else(|| D1SeedError::MissingKey {
+        reference: reference.to_owned(),
+        column: target,
+    })
+}
+
+/// Multi-row `INSERT`s for `records`, one per run of records setting the same columns
+fn insert_statements<'a>(table: &str, records: &'a [(Option<String>, D1FixtureRecord)]) -> Vec<(String, Vec<&'a Value>)> {
+    let mut statements = Vec::new();
+    let mut group: Vec<&D1FixtureRecord> = Vec::new();
+
+    for (_, record) in records {
+        let columns = record.values.len().max(1);
+        let same_columns = group
+            .first()
+            .is_some_and(|first| first.values.keys().eq(record.values.keys()));
+        if !same_columns || group.len() * columns + columns > MAX_BOUND_PARAMETERS {
+            statements.extend(insert_statement(table, &group));
+            group.clear();
+        }
+        group.push(record);
+    }
+    statements.extend(insert_statement(table, &group));
+    statements
+}
+
+fn insert_statement<'a>(table: &str, group: &[&'a D1FixtureRecord]) -> Vec<(String, Vec<&'a Value>)> {
+    let Some(first) = group.first() else {
+        return Vec::new();
+    };
+    if first.values.is_empty() {
+        let sql = format!("INSERT INTO {} DEFAULT VALUES", quote_identifier(table));
+        return vec![(sql, Vec::new()); group.len()];
+    }
+
+    let columns = first.values.keys().map(|column| quote_identifier(column)).collect::<Vec<_>>();
+    let row = format!("({})", vec!["?"; columns.len()].join(", "));
+    let sql = format!(
+        "INSERT INTO {} ({}) VALUES {}",
+        quote_identifier(table),
+        columns.join(", "),
+        vec![row; group.len()].join(", ")
+    );
+    let binds = group.iter().flat_map(|record| record.values.values()).collect();
+    vec![(sql, binds)]
+}
+
+fn bind_value(value: &Value) -> JsValue {
+    match value {
+        Value::Null => JsValue::null(),
+        Value::Bool(value) => JsValue::from_f64(f64::from(u8::from(*value))),
+        Value::Number(value) => match number_bind(value) {
+            NumberBind::Number(number) => JsValue::from_f64(number),
+            NumberBind::Text(text) => JsValue::from_str(&text),
+        },
+        Value::String(value) => JsValue::from_str(value),
+        // arrays and objects are stored as JSON text, for SQLite's JSON functions
+        value => JsValue::from_str(&value.to_string()),
+    }
+}
+
+/// How a JSON number is bound, see [`number_bind`]
+#[derive(Debug, Clone, PartialEq)]
+enum NumberBind {
+    Number(f64),
+    Text(String),
+}
+
+/// D1 binds JavaScript numbers, which hold integers exactly up to 2^53. Larger ones are bound
+/// as text instead, which columns with INTEGER or NUMERIC affinity turn back into the exact
+/// integer.
+fn number_bind(number: &Number) -> NumberBind {
+    const MAX_SAFE_INTEGER: u64 = (1 << 53) - 1;
+
+    match number.as_i64() {
+        Some(integer) if integer.unsigned_abs() <= MAX_SAFE_INTEGER => NumberBind::Number(integer as f64),
+        Some(integer) => NumberBind::Text(integer.to_string()),
+        None => number
+            .as_f64()
+            .map_or_else(|| NumberBind::Text(number.to_string()), NumberBind::Number),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn table_names(fixtures: &D1Fixtures, order: &[usize]) -> Vec<String> {
+        order.iter().map(|index| fixtures.tables[*index].0.clone()).collect()
+    }
+
+    fn record_names(fixtures: &D1Fixtures, table: usize) -> Vec<&str> {
+        fixtures.tables[table].1.iter().filter_map(|(name, _)| name.as_deref()).collect()
+    }
+
+    #[test]
+    fn keeps_the_order_of_the_file() {
+        let json = D1Fixtures::from_json(
+            r#"{
+                "zebras": { "zoe": {}, "abe": {}, "mia": {} },
+                "apes": [{ "name": "Kong" }]
+            }"#,
+        )
+        .unwrap();
+        let yaml = D1Fixtures::from_yaml("zebras:\n  zoe: {}\n  abe: {}\n  mia: {}\napes:\n  - name: Kong\n").unwrap();
+        let toml = D1Fixtures::from_toml("[zebras.zoe]\n[zebras.abe]\n[zebras.mia]\n\n[[apes]]\nname = \"Kong\"\n").unwrap();
+
+        for fixtures in [json, yaml, toml] {
+            let order = insertion_order(&fixtures).unwrap();
+            assert_eq!(table_names(&fixtures, &order), ["zebras", "apes"]);
+            assert_eq!(record_names(&fixtures, 0), ["zoe", "abe", "mia"]);
+        }
+    }
+
+    #[test]
+    fn binds_numbers_exactly() {
+        let bind = |json: &str| number_bind(&serde_json::from_str(json).unwrap());
+
+        assert_eq!(bind("42"), NumberBind::Number(42.0));
+        assert_eq!(bind("-9007199254740991"), NumberBind::Number(-9007199254740991.0));
+        assert_eq!(bind("1.5"), NumberBind::Number(1.5));
+        // past 2^53 a JavaScript number would round them
+        assert_eq!(bind("9007199254740993"), NumberBind::Text("9007199254740993".to_owned()));
+        assert_eq!(bind("-9223372036854775808"), NumberBind::Text("-9223372036854775808".to_owned()));
+        // too large for INTEGER columns anyway, SQLite stores them as REAL
+        assert_eq!(bind("18446744073709551615"), NumberBind::Number(18446744073709551615.0));
+    }
+
+    #[test]
+    fn orders_referenced_tables_first() {
+        let fixtures = D1Fixtures::from_json(
+            r#"{
+                "comments": [{ "post_id": "@posts.hello", "author_id": "@users.bob" }],
+                "posts": { "hello": { "author_id": "@users.alice", "body": "@@not a reference" } },
+                "users": {
+                    "alice": { "name": "Alice" },
+                    "bob": { "name": "Bob", "invited_by": "@users.alice" }
+                },
+                "tags": [{ "name": "@@users.alice" }]
+            }"#,
+        )
+        .unwrap();
+
+        let order = insertion_order(&fixtures).unwrap();
+        let tables = table_names(&fixtures, &order);
+        let position = |table: &str| tables.iter().position(|existing| existing == table).unwrap();
+        assert_eq!(tables.len(), 4);
+        assert!(position("users") < position("posts"));
+        assert!(position("posts") < position("comments"));
+    }
+
+    #[test]
+    fn rejects_cyclic_references() {
+        let mut fixtures = D1Fixtures::new();
+        fixtures.add("teams", Some("core"), D1FixtureRecord::new().reference("lead_id", "people", "ada"));
+        fixtures.add("people", Some("ada"), D1FixtureRecord::new().reference("team_id", "teams", "core"));
+        fixtures.add("offices", None, D1FixtureRecord::new().set("city", "Lisbon"));
+
+        let err = insertion_order(&fixtures).unwrap_err();
+        assert!(matches!(&err, D1SeedError::CyclicReferences(tables) if tables == &["teams", "people"]));
+        assert_eq!(err.to_string(), "records of teams, people reference each other");
+
+        // references within a table don't form a cycle
+        let mut fixtures = D1Fixtures::new();
+        fixtures.add("people", Some("ada"), D1FixtureRecord::new());
+        fixtures.add("people", Some("alan"), D1FixtureRecord::new().reference("mentor_id", "people", "ada"));
+        assert_eq!(insertion_order(&fixtures).unwrap(), [0]);
+    }
+
+    fn column(name: &str, declared_type: &str, primary_key: i32) -> D1Column {
+        D1Column {
+            position: 0,
+            name: name.to_owned(),
+            declared_type: declared_type.to_owned(),
+            not_null: false,
+            default_value: None,
+            primary_key,
+        }
+    }
+
+    #[test]
+    fn resolves_references() {
+        let mut fixtures = D1Fixtures::new();
+        fixtures.add("users", Some("alice"), D1FixtureRecord::new().set("id", 7).set("email", "alice@example.com"));
+        fixtures.add("users", Some("bob"), D1FixtureRecord::new().set("email", "bob@example.com"));
+
+        let users = TableSchema {
+            columns: vec![column("id", "INTEGER", 1), column("email", "TEXT", 0)],
+            foreign_keys: Vec::new(),
+        };
+        let posts = TableSchema {
+            columns: vec![column("author_id", "INTEGER", 0), column("author_email", "TEXT", 0)],
+            foreign_keys: vec![D1ForeignKey {
+                id: 0,
+                seq: 0,
+                column: "author_email".to_owned(),
+                parent_table: "users".to_owned(),
+                parent_column: Some("email".to_owned()),
+                on_update: "NO ACTION".to_owned(),
+                on_delete: "NO ACTION".to_owned(),
+            }],
+        };
+        let schemas = HashMap::from([("users".to_owned(), users)]);
+        let resolve = |column, reference| resolve_reference(&fixtures, &schemas, &posts, column, reference);
+
+        // columns without a foreign key get the primary key
+        assert_eq!(resolve("author_id", "users.alice").unwrap(), json!(7));
+        assert_eq!(resolve("author_email", "users.alice").unwrap(), json!("alice@example.com"));
+        assert!(matches!(
+            resolve("author_id", "users.bob"),
+            Err(D1SeedError::MissingKey { reference, column }) if reference == "users.bob" && column == "id"
+        ));
+        assert!(matches!(resolve("author_id", "users.carol"), Err(D1SeedError::UnknownRecord(_))));
+        assert!(matches!(resolve("author_id", "alice"), Err(D1SeedError::UnknownRecord(_))));
+    }
+
+    #[test]
+    fn splits_inserts_by_parameter_limit() {
+        let records = (0..60)
+            .map(|n| (None, D1FixtureRecord::new().set("id", n).set("name", format!("user {n}"))))
+            .collect::<Vec<_>>();
+
+        let statements = insert_statements("users", &records);
+        assert_eq!(statements.len(), 2);
+        assert_eq!(statements[0].1.len(), MAX_BOUND_PARAMETERS);
+        assert_eq!(statements[1].1.len(), 20);
+        assert!(statements[1].0.starts_with(r#"INSERT INTO "users" ("id", "name") VALUES (?, ?), (?, ?)"#));
+        assert_eq!(statements[1].1[..2], [&json!(50), &json!("user 50")]);
+    }
+
+    #[test]
+    fn splits_inserts_by_columns() {
+        let records = vec![
+            (None, D1FixtureRecord::new().set("name", "a")),
+            (None, D1FixtureRecord::new().set("name", "b")),
+            (None, D1FixtureRecord::new().set("name", "c").set("active", true)),
+            (None, D1FixtureRecord::new()),
+            (None, D1FixtureRecord::new()),
+        ];
+
+        let statements = insert_statements("users", &records)
+            .into_iter()
+            .map(|(sql, binds)| (sql, binds.len()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            statements,
+            [
+                (r#"INSERT INTO "users" ("name") VALUES (?), (?)"#.to_owned(), 2),
+                (r#"INSERT INTO "users" ("active", "name") VALUES (?, ?)"#.to_owned(), 2),
+                (r#"INSERT INTO "users" DEFAULT VALUES"#.to_owned(), 0),
+                (r#"INSERT INTO "users" DEFAULT VALUES"#.to_owned(), 0),
+            ]
+        );
+    }
+
+    #[test]
+    fn factories_number_records() {
+        let mut fixtures = D1Fixtures::new();
+        let mut users = D1Factory::new("users")
+            .sequence("email", |n| format!("user{n}@example.com").into())
+            .default("active", true);
+
+        users.add_to(&mut fixtures, Some("admin"), D1FixtureRecord::new().set("active", false));
+        users.add_to(&mut fixtures, None, D1FixtureRecord::new());
+
+        let admin = fixtures.get("users", "admin").unwrap();
+        assert_eq!(admin.get("email"), Some(&json!("user1@example.com")));
+        assert_eq!(admin.get("active"), Some(&json!(false)));
+        assert_eq!(fixtures.tables[0].1[1].1.get("email"), Some(&json!("user2@example.com")));
+    }
+}
@@ -0,0 +1,202 @@
+use std::ops::{Deref, DerefMut};
+
+use diesel::migration;
+use js_sys::{Array, Object, Promise, Reflect};
+use wasm_bindgen::{prelude::*, JsCast};
+use wasm_bindgen_futures::{spawn_local, JsFuture};
+
+use crate::{
+    binding::D1Database, migrations::D1MigrationSet, seed::D1Fixtures, utils::js_error_message, D1Connection,
+};
+
+/// Name of the database binding inside the Miniflare instance
+const BINDING: &str = "DB";
+
+/// Miniflare needs a Worker to run, the tests only use its D1 binding
+const WORKER_SCRIPT: &str = "export default { fetch() { return new Response(null, { status: 404 }); } };";
+
+#[wasm_bindgen(module = "miniflare")]
+extern "C" {
+    type Miniflare;
+
+    #[wasm_bindgen(constructor, catch)]
+    fn new(options: &Object) -> Result<Miniflare, JsValue>;
+
+    #[wasm_bindgen(method, catch, js_name = getD1Database)]
+    fn get_d1_database(this: &Miniflare, binding: &str) -> Result<Promise, JsValue>;
+
+    #[wasm_bindgen(method, catch)]
+    fn dispose(this: &Miniflare) -> Result<Promise, JsValue>;
+}
+
+#[wasm_bindgen(module = "node:fs")]
+extern "C" {
+    #[wasm_bindgen(catch, js_name = mkdtempSync)]
+    fn mkdtemp_sync(prefix: &str) -> Result<String, JsValue>;
+
+    #[wasm_bindgen(catch, js_name = rmSync)]
+    fn rm_sync(path: &str, options: &Object) -> Result<(), JsValue>;
+}
+
+#[wasm_bindgen(module = "node:os")]
+extern "C" {
+    fn tmpdir() -> String;
+}
+
+/// A fresh database, migrated and seeded for a single test, see [`D1TestDatabase::open`]
+///
+/// Every `D1TestDatabase` starts its own [Miniflare](https://developers.cloudflare.com/workers/testing/miniflare/)
+/// instance, the local D1 `wrangler dev` runs on, persisting to a temporary directory of its
+/// own. Tests never share a database and never touch a binding of the Worker. Dropping it
+/// stops the instance and removes the directory.
+///
+/// The tests run under Node with `wasm-bindgen-test`, e.g. `wasm-pack test --node --features
+/// test-utils`, and need `miniflare` installed next to the crate (`npm install --save-dev
+/// miniflare`).
+///
+/// ```ignore
+/// #[wasm_bindgen_test]
+/// async fn lists_users() {
+///     let migrations = D1MigrationSet::new(MIGRATIONS).unwrap();
+///     let mut db = D1TestDatabase::open(&migrations, Some(&fixtures)).await.unwrap();
+///     let alice_id = db.fixtures().get("users", "alice").and_then(|alice| alice.get("id")).cloned();
+///
+///     let users = users::table.load::<User>(&mut *db).await.unwrap();
+///     db.close().await.unwrap();
+/// }
+/// ```
+pub struct D1TestDatabase {
+    connection: D1Connection,
+    fixtures: D1Fixtures,
+    instance: LocalInstance,
+}
+
+impl D1TestDatabase {
+    /// Creates an empty database, runs `migrations` on it and inserts `fixtures`
+    pub async fn open(migrations: &D1MigrationSet, fixtures: Option<&D1Fixtures>) -> migration::Result<Self> {
+        let (instance, binding) = LocalInstance::start().await?;
+        let mut connection = D1Connection::from_binding(binding);
+        connection.run_migration_set(migrations).await?;
+        let fixtures = match fixtures {
+            Some(fixtures) => connection.seed(fixtures).await?,
+            None => D1Fixtures::new(),
+        };
+
+        Ok(D1TestDatabase {
+            connection,
+            fixtures,
+            instance,
+        })
+    }
+
+    /// The fixtures passed to [`D1TestDatabase::open`], with generated keys and resolved
+    /// references filled in
+    pub fn fixtures(&self) -> &D1Fixtures {
+        &self.fixtures
+    }
+
+    /// Stops the Miniflare instance and removes its directory, waiting for both
+    ///
+    /// Dropping the database does the same without waiting. A test that panics can't clean up
+    /// at all, as panics abort under wasm; it leaves a directory in the system's temporary
+    /// directory, which no other test ever opens.
+    pub async fn close(mut self) -> migration::Result<()> {
+        self.instance.stop().await
+    }
+}
+
+impl Deref for D1TestDatabase {
+    type Target = D1Connection;
+
+    fn deref(&self) -> &D1Connection {
+        &self.connection
+    }
+}
+
+impl DerefMut for D1TestDatabase {
+    fn deref_mut(&mut self) -> &mut D1Connection {
+        &mut self.connection
+    }
+}
+
+/// A Miniflare instance and its persistence directory, released when dropped
+struct LocalInstance {
+    /// `None` once stopped
+    miniflare: Option<Miniflare>,
+    persist_dir: String,
+}
+
+impl LocalInstance {
+    async fn start() -> migration::Result<(Self, D1Database)> {
+        let persist_dir = mkdtemp_sync(&format!("{}/diesel-d1-test-", tmpdir())).map_err(js_error)?;
+
+        let options = Object::new();
+        let databases = Array::of1(&BINDING.into());
+        for (key, value) in [
+            ("modules", JsValue::TRUE),
+            ("script", WORKER_SCRIPT.into()),
+            ("d1Databases", databases.into()),
+            ("d1Persist", persist_dir.as_str().into()),
+        ] {
+            Reflect::set(&options, &key.into(), &value).map_err(js_error)?;
+        }
+
+        let miniflare = match Miniflare::new(&options) {
+            Ok(miniflare) => miniflare,
+            Err(err) => {
+                remove_dir(&persist_dir);
+                return Err(js_error(err));
+            },
+        };
+        let database = miniflare.get_d1_database(BINDING);
+        // from here on dropping the instance cleans up
+        let instance = LocalInstance {
+            miniflare: Some(miniflare),
+            persist_dir,
+        };
+
+        let database = JsFuture::from(database.map_err(js_error)?).await.map_err(js_error)?;
+        Ok((instance, database.unchecked_into()))
+    }
+
+    async fn stop(&mut self) -> migration::Result<()> {
+        let Some(miniflare) = self.miniflare.take() else {
+            return Ok(());
+        };
+        let result = match miniflare.dispose() {
+            Ok(disposed) => JsFuture::from(disposed).await.map(drop),
+            Err(err) => Err(err),
+        };
+        remove_dir(&self.persist_dir);
+        result.map_err(js_error)
+    }
+}
+
+impl Drop for LocalInstance {
+    fn drop(&mut self) {
+        let Some(miniflare) = self.miniflare.take() else {
+            return;
+        };
+        // drop can't wait for the instance to stop, the directory is removed once it has
+        let persist_dir = std::mem::take(&mut self.persist_dir);
+        match miniflare.dispose() {
+            Ok(disposed) => spawn_local(async move {
+                let _ = JsFuture::from(disposed).await;
+                remove_dir(&persist_dir);
+            }),
+            Err(_) => remove_dir(&persist_dir),
+        }
+    }
+}
+
+fn remove_dir(path: &str) {
+    let options = Object::new();
+    for key in ["recursive", "force"] {
+        let _ = Reflect::set(&options, &key.into(), &JsValue::TRUE);
+    }
+    let _ = rm_sync(path, &options);
+}
+
+fn js_error(err: JsValue) -> Box<dyn std::error::Error + Send + Sync> {
+    js_error_message(&err).into()
+}
@@ -52,7 +52,7 @@ impl TransactionManager<D1Connection> for D1TransactionManager {
                     Err(_) => todo!(),
                 };
 
-                let result: D1Result = match SendableFuture(JsFuture::from(promise)).await {
+                let _result: D1Result = match SendableFuture(JsFuture::from(promise)).await {
                     Ok(res) => res.into(),
                     Err(_) => todo!(),
                 };
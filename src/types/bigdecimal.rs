@@ -0,0 +1,25 @@
+use std::str::FromStr;
+
+use bigdecimal::BigDecimal;
+use diesel::{
+    deserialize::{self, FromSql},
+    serialize::{self, IsNull, Output, ToSql},
+    sql_types,
+};
+
+use crate::{backend::D1Backend, value::D1Value};
+
+impl FromSql<sql_types::Numeric, D1Backend> for BigDecimal {
+    fn from_sql(value: D1Value) -> deserialize::Result<Self> {
+        let text = value.read_numeric_text()?;
+        BigDecimal::from_str(&text).map_err(|err| format!("invalid decimal `{text}` in column `{}`: {err}", value.column()).into())
+    }
+}
+
+impl ToSql<sql_types::Numeric, D1Backend> for BigDecimal {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, D1Backend>) -> serialize::Result {
+        // `to_string` switches to exponent notation for large exponents, keep it plain
+        out.set_value(self.to_plain_string());
+        Ok(IsNull::No)
+    }
+}
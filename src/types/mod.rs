@@ -4,12 +4,20 @@ use diesel::{
     sql_types::{self, HasSqlType},
 };
 use js_sys::Uint8Array;
+use wasm_bindgen::JsValue;
 
 use crate::{
     backend::{D1Backend, D1Type},
     value::D1Value,
 };
 
+#[cfg(feature = "bigdecimal")]
+mod bigdecimal;
+#[cfg(feature = "rust_decimal")]
+mod rust_decimal;
+#[cfg(feature = "uuid")]
+mod uuid;
+
 // Boolean
 impl HasSqlType<sql_types::Bool> for D1Backend {
     fn metadata(_lookup: &mut ()) -> D1Type {
@@ -19,11 +27,7 @@ impl HasSqlType<sql_types::Bool> for D1Backend {
 
 impl FromSql<sql_types::Bool, D1Backend> for bool {
     fn from_sql(value: D1Value) -> deserialize::Result<Self> {
-        let bool_number = value.read_number();
-        if !(bool_number == 0.0 || bool_number == 1.0) {
-            panic!("this shouldn't happen bool is not a bool");
-        } 
-        Ok(bool_number != 0.0)
+        value.read_bool()
     }
 }
 
@@ -44,8 +48,7 @@ impl HasSqlType<sql_types::SmallInt> for D1Backend {
 
 impl FromSql<sql_types::SmallInt, D1Backend> for i16 {
     fn from_sql(value: D1Value) -> deserialize::Result<Self> {
-        let text = value.read_number();
-        Ok(text as i16)
+        value.read_integer()
     }
 }
 
@@ -68,8 +71,7 @@ impl HasSqlType<sql_types::Integer> for D1Backend {
 
 impl FromSql<sql_types::Integer, D1Backend> for i32 {
     fn from_sql(value: D1Value) -> deserialize::Result<Self> {
-        let text = value.read_number();
-        Ok(text as i32)
+        value.read_integer()
     }
 }
 
@@ -92,8 +94,7 @@ impl HasSqlType<sql_types::BigInt> for D1Backend {
 
 impl FromSql<sql_types::BigInt, D1Backend> for i64 {
     fn from_sql(value: D1Value) -> deserialize::Result<Self> {
-        let text = value.read_number();
-        Ok(text as i64)
+        value.read_integer()
     }
 }
 
@@ -116,8 +117,8 @@ impl HasSqlType<sql_types::Float> for D1Backend {
 
 impl FromSql<sql_types::Float, D1Backend> for f32 {
     fn from_sql(value: D1Value) -> deserialize::Result<Self> {
-        let text = value.read_number();
-        Ok(text as f32)
+        let number = value.read_number()?;
+        Ok(number as f32)
     }
 }
 
@@ -140,8 +141,7 @@ impl HasSqlType<sql_types::Double> for D1Backend {
 
 impl FromSql<sql_types::Double, D1Backend> for f64 {
     fn from_sql(value: D1Value) -> deserialize::Result<Self> {
-        let text = value.read_number();
-        Ok(text)
+        value.read_number()
     }
 }
 
@@ -154,6 +154,16 @@ impl ToSql<sql_types::Double, D1Backend> for f64 {
 
 // ------
 
+// Numeric (stored as TEXT so no precision is lost going through JS numbers)
+
+impl HasSqlType<sql_types::Numeric> for D1Backend {
+    fn metadata(_lookup: &mut ()) -> D1Type {
+        D1Type::Text
+    }
+}
+
+// ------
+
 // Text
 
 impl HasSqlType<sql_types::Text> for D1Backend {
@@ -164,18 +174,32 @@ impl HasSqlType<sql_types::Text> for D1Backend {
 
 impl FromSql<sql_types::Text, D1Backend> for String {
     fn from_sql(value: D1Value) -> deserialize::Result<Self> {
-        let text = value.read_string();
+        let text = value.read_string()?;
         Ok(text)
     }
 }
 
-impl ToSql<sql_types::Text, D1Backend> for String {
+impl FromSql<sql_types::Text, D1Backend> for Box<str> {
+    fn from_sql(value: D1Value) -> deserialize::Result<Self> {
+        let text = value.read_string()?;
+        Ok(text.into_boxed_str())
+    }
+}
+
+// `String`, `&str` and `Cow<str>` are covered by diesel's blanket impls over this one
+impl ToSql<sql_types::Text, D1Backend> for str {
     fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, D1Backend>) -> serialize::Result {
         out.set_value(self);
         Ok(IsNull::No)
     }
 }
 
+impl ToSql<sql_types::Text, D1Backend> for Box<str> {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, D1Backend>) -> serialize::Result {
+        ToSql::<sql_types::Text, D1Backend>::to_sql(&**self, out)
+    }
+}
+
 // ------
 
 // Blob
@@ -186,22 +210,33 @@ impl HasSqlType<sql_types::Binary> for D1Backend {
     }
 }
 
-impl FromSql<sql_types::Binary, D1Backend> for *const [u8] {
+impl FromSql<sql_types::Binary, D1Backend> for Vec<u8> {
+    fn from_sql(value: D1Value) -> deserialize::Result<Self> {
+        let blob = value.read_blob()?;
+        Ok(blob)
+    }
+}
+
+impl FromSql<sql_types::Binary, D1Backend> for Box<[u8]> {
     fn from_sql(value: D1Value) -> deserialize::Result<Self> {
-        let text = value.read_blob();
-        Ok(text.as_slice() as *const [u8])
+        let blob = value.read_blob()?;
+        Ok(blob.into_boxed_slice())
     }
 }
 
-impl ToSql<sql_types::Binary, D1Backend> for *const [u8] {
+// `Vec<u8>`, `&[u8]`, `[u8; N]` and `Cow<[u8]>` are covered by diesel's blanket impls over this one
+impl ToSql<sql_types::Binary, D1Backend> for [u8] {
     fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, D1Backend>) -> serialize::Result {
-        // SAFETY: safe to do because we don't expect for buffer to change size, `as_ref` should always pass anyway
-        let value = unsafe { js_sys::Uint8Array::new(&Uint8Array::view(self.as_ref().unwrap())) };
-        out.set_value(value);
+        out.set_value(blob_value(self));
         Ok(IsNull::No)
     }
 }
 
+/// Copies `bytes` into a JS-owned buffer, D1 binds `ArrayBuffer`s as BLOBs
+pub(crate) fn blob_value(bytes: &[u8]) -> JsValue {
+    Uint8Array::from(bytes).buffer().into()
+}
+
 // ------ Time related (simplified to only text)
 
 impl HasSqlType<sql_types::Date> for D1Backend {
@@ -222,32 +257,40 @@ impl HasSqlType<sql_types::Timestamp> for D1Backend {
     }
 }
 
-impl FromSql<sql_types::Date, D1Backend> for String {
-    fn from_sql(value: D1Value) -> deserialize::Result<Self> {
-        FromSql::<sql_types::Text, D1Backend>::from_sql(value)
-    }
-}
-
-impl ToSql<sql_types::Date, D1Backend> for String {
-    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, D1Backend>) -> serialize::Result {
-        ToSql::<sql_types::Text, D1Backend>::to_sql(self, out)
-    }
-}
-
-impl FromSql<sql_types::Time, D1Backend> for String {
-    fn from_sql(value: D1Value) -> deserialize::Result<Self> {
-        FromSql::<sql_types::Text, D1Backend>::from_sql(value)
-    }
-}
-
-impl FromSql<sql_types::Timestamp, D1Backend> for String {
-    fn from_sql(value: D1Value) -> deserialize::Result<Self> {
-        FromSql::<sql_types::Text, D1Backend>::from_sql(value)
-    }
-}
-
-impl ToSql<sql_types::Timestamp, D1Backend> for String {
-    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, D1Backend>) -> serialize::Result {
-        ToSql::<sql_types::Text, D1Backend>::to_sql(self, out)
-    }
-}
+/// Date and time types are stored as TEXT, so they share the `Text` impls for
+/// every string-like Rust type
+macro_rules! text_backed_impls {
+    ($($sql_type:ty),*) => {$(
+        impl FromSql<$sql_type, D1Backend> for String {
+            fn from_sql(value: D1Value) -> deserialize::Result<Self> {
+                FromSql::<sql_types::Text, D1Backend>::from_sql(value)
+            }
+        }
+
+        impl FromSql<$sql_type, D1Backend> for Box<str> {
+            fn from_sql(value: D1Value) -> deserialize::Result<Self> {
+                FromSql::<sql_types::Text, D1Backend>::from_sql(value)
+            }
+        }
+
+        impl ToSql<$sql_type, D1Backend> for str {
+            fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, D1Backend>) -> serialize::Result {
+                ToSql::<sql_types::Text, D1Backend>::to_sql(self, out)
+            }
+        }
+
+        impl ToSql<$sql_type, D1Backend> for String {
+            fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, D1Backend>) -> serialize::Result {
+                ToSql::<sql_types::Text, D1Backend>::to_sql(self.as_str(), out)
+            }
+        }
+
+        impl ToSql<$sql_type, D1Backend> for Box<str> {
+            fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, D1Backend>) -> serialize::Result {
+                ToSql::<sql_types::Text, D1Backend>::to_sql(&**self, out)
+            }
+        }
+    )*};
+}
+
+text_backed_impls!(sql_types::Date, sql_types::Time, sql_types::Timestamp);
@@ -0,0 +1,27 @@
+use std::str::FromStr;
+
+use diesel::{
+    deserialize::{self, FromSql},
+    serialize::{self, IsNull, Output, ToSql},
+    sql_types,
+};
+use rust_decimal::Decimal;
+
+use crate::{backend::D1Backend, value::D1Value};
+
+impl FromSql<sql_types::Numeric, D1Backend> for Decimal {
+    fn from_sql(value: D1Value) -> deserialize::Result<Self> {
+        let text = value.read_numeric_text()?;
+        // other tools may have written the value in scientific notation
+        Decimal::from_str(&text)
+            .or_else(|_| Decimal::from_scientific(&text))
+            .map_err(|err| format!("invalid decimal `{text}` in column `{}`: {err}", value.column()).into())
+    }
+}
+
+impl ToSql<sql_types::Numeric, D1Backend> for Decimal {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, D1Backend>) -> serialize::Result {
+        out.set_value(self.to_string());
+        Ok(IsNull::No)
+    }
+}
@@ -0,0 +1,41 @@
+use diesel::{
+    deserialize::{self, FromSql},
+    serialize::{self, IsNull, Output, ToSql},
+    sql_types,
+};
+use uuid::Uuid;
+
+use crate::{backend::D1Backend, value::D1Value};
+
+// Text (hyphenated, e.g. `67e55044-10b1-426f-9247-bb680e5fe0c8`)
+
+impl FromSql<sql_types::Text, D1Backend> for Uuid {
+    fn from_sql(value: D1Value) -> deserialize::Result<Self> {
+        let text = value.read_string()?;
+        Uuid::try_parse(&text).map_err(|err| format!("invalid UUID `{text}` in column `{}`: {err}", value.column()).into())
+    }
+}
+
+impl ToSql<sql_types::Text, D1Backend> for Uuid {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, D1Backend>) -> serialize::Result {
+        out.set_value(self.hyphenated().to_string());
+        Ok(IsNull::No)
+    }
+}
+
+// ------
+
+// Binary (16 raw bytes)
+
+impl FromSql<sql_types::Binary, D1Backend> for Uuid {
+    fn from_sql(value: D1Value) -> deserialize::Result<Self> {
+        let blob = value.read_blob()?;
+        Uuid::from_slice(&blob).map_err(|err| format!("invalid UUID blob in column `{}`: {err}", value.column()).into())
+    }
+}
+
+impl ToSql<sql_types::Binary, D1Backend> for Uuid {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, D1Backend>) -> serialize::Result {
+        ToSql::<sql_types::Binary, D1Backend>::to_sql(&self.as_bytes()[..], out)
+    }
+}
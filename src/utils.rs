@@ -1,6 +1,7 @@
 use std::{future::Future, pin::Pin, task::{Context, Poll}};
 
 use diesel::result::DatabaseErrorInformation;
+use wasm_bindgen::{JsCast, JsValue};
 
 /// Basically, JS promises are never sendable - they just exist in one thread. While this could be a problem
 /// for multi-threaded WASM environments. However, Cloudflare Workers are ALWAYS single-threaded, so we can make
@@ -57,4 +58,176 @@ impl DatabaseErrorInformation for D1Error {
     fn statement_position(&self) -> Option<i32> {
         None
     }
-}
\ No newline at end of file
+}
+/// Pulls a readable message out of a rejected JS promise, D1 puts the SQLite error into `cause`
+pub(crate) fn js_error_message(err: &JsValue) -> String {
+    match err.dyn_ref::<js_sys::Error>() {
+        Some(error) => {
+            let message = String::from(error.message());
+            match error.cause().dyn_ref::<js_sys::Error>() {
+                Some(cause) => format!("{message}: {}", String::from(cause.message())),
+                None => message,
+            }
+        },
+        None => err.as_string().unwrap_or_else(|| format!("{err:?}")),
+    }
+}
+
+/// Splits a SQL script into its statements, so they can be sent to D1 one by one
+///
+/// Semicolons inside string literals, quoted identifiers, comments and `CREATE TRIGGER ... BEGIN ... END`
+/// bodies don't end a statement. Empty statements are dropped.
+pub(crate) fn split_statements(sql: &str) -> Vec<String> {
+    let mut statements = Vec::new();
+    let mut current = String::new();
+    // nesting of `BEGIN ... END` blocks, only tracked for triggers
+    let mut block_depth = 0usize;
+    let mut chars = sql.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\'' | '"' | '`' | '[' => {
+                let close = if c == '[' { ']' } else { c };
+                current.push(c);
+                while let Some(c) = chars.next() {
+                    current.push(c);
+                    if c == close {
+                        // a doubled quote is an escaped quote, not the end of the literal
+                        if close != ']' && chars.peek() == Some(&close) {
+                            current.push(chars.next().unwrap());
+                            continue;
+                        }
+                        break;
+                    }
+                }
+            },
+            '-' if chars.peek() == Some(&'-') => {
+                for c in chars.by_ref() {
+                    if c == '\n' {
+                        current.push('\n');
+                        break;
+                    }
+                }
+            },
+            '/' if chars.peek() == Some(&'*') => {
+                chars.next();
+                let mut previous = '\0';
+                for c in chars.by_ref() {
+                    if previous == '*' && c == '/' {
+                        break;
+                    }
+                    previous = c;
+                }
+                current.push(' ');
+            },
+            ';' if block_depth == 0 => {
+                push_statement(&mut statements, &mut current);
+            },
+            c if c.is_alphabetic() || c == '_' => {
+                let mut word = String::from(c);
+                while let Some(&c) = chars.peek() {
+                    if !(c.is_alphanumeric() || c == '_') {
+                        break;
+                    }
+                    word.push(c);
+                    chars.next();
+                }
+
+                if word.eq_ignore_ascii_case("BEGIN") && is_create_trigger(&current) {
+                    block_depth += 1;
+                } else if word.eq_ignore_ascii_case("CASE") && block_depth > 0 {
+                    // `CASE ... END` inside a trigger body shares the `END` keyword
+                    block_depth += 1;
+                } else if word.eq_ignore_ascii_case("END") && block_depth > 0 {
+                    block_depth -= 1;
+                }
+                current.push_str(&word);
+            },
+            c => current.push(c),
+        }
+    }
+
+    push_statement(&mut statements, &mut current);
+    statements
+}
+
+fn push_statement(statements: &mut Vec<String>, current: &mut String) {
+    let statement = current.trim();
+    if !statement.is_empty() {
+        statements.push(statement.to_owned());
+    }
+    current.clear();
+}
+
+fn is_create_trigger(statement: &str) -> bool {
+    let mut words = statement.split_whitespace().map(str::to_ascii_uppercase);
+    words.next().as_deref() == Some("CREATE")
+        && words.take(3).any(|word| word == "TRIGGER")
+}
+
+/// Quotes an identifier for hand-written SQL, the query builder uses backticks instead
+pub(crate) fn quote_identifier(identifier: &str) -> String {
+    format!("\"{}\"", identifier.replace('"', "\"\""))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_on_semicolons() {
+        assert_eq!(
+            split_statements("CREATE TABLE a (id INTEGER);\n\n;INSERT INTO a VALUES (1);  \n"),
+            ["CREATE TABLE a (id INTEGER)", "INSERT INTO a VALUES (1)"]
+        );
+        assert_eq!(split_statements("SELECT 1"), ["SELECT 1"]);
+        assert!(split_statements(" ; \n;").is_empty());
+    }
+
+    #[test]
+    fn ignores_semicolons_in_quotes() {
+        assert_eq!(
+            split_statements(
+                "INSERT INTO a VALUES ('a;b', 'it''s;'); \
+                SELECT \"c;d\", `e;f`, [g;h] FROM a"
+            ),
+            [
+                "INSERT INTO a VALUES ('a;b', 'it''s;')",
+                "SELECT \"c;d\", `e;f`, [g;h] FROM a",
+            ]
+        );
+        // an unterminated literal runs to the end
+        assert_eq!(split_statements("SELECT 'a;b"), ["SELECT 'a;b"]);
+    }
+
+    #[test]
+    fn drops_comments() {
+        assert_eq!(
+            split_statements("-- first; not a statement\nSELECT 1; /* a; b */ SELECT 2 -- trailing;\n;"),
+            ["SELECT 1", "SELECT 2"]
+        );
+        assert_eq!(split_statements("SELECT 1 /* unterminated; comment"), ["SELECT 1"]);
+        assert_eq!(split_statements("SELECT 1 - -1;"), ["SELECT 1 - -1"]);
+    }
+
+    #[test]
+    fn keeps_trigger_bodies_together() {
+        let trigger = "CREATE TRIGGER grade AFTER INSERT ON scores BEGIN \
+            UPDATE scores SET grade = CASE WHEN new.points > 50 THEN 'pass' ELSE 'fail' END WHERE id = new.id; \
+            INSERT INTO log VALUES (CASE new.points WHEN 0 THEN 'zero' END); \
+            END";
+        assert_eq!(split_statements(&format!("{trigger}; SELECT 1;")), [trigger, "SELECT 1"]);
+    }
+
+    #[test]
+    fn begin_outside_triggers_is_a_statement() {
+        assert_eq!(
+            split_statements("BEGIN; INSERT INTO a VALUES (1); END; BEGIN TRANSACTION; COMMIT;"),
+            ["BEGIN", "INSERT INTO a VALUES (1)", "END", "BEGIN TRANSACTION", "COMMIT"]
+        );
+        assert_eq!(
+            split_statements("SELECT CASE WHEN 1 THEN 2 END; SELECT 3"),
+            ["SELECT CASE WHEN 1 THEN 2 END", "SELECT 3"]
+        );
+    }
+}
@@ -1,40 +1,259 @@
+use std::{error::Error, fmt};
+
+use diesel::deserialize;
 use wasm_bindgen::{JsCast, JsValue};
-use js_sys::Uint8Array;
+use js_sys::{Array, ArrayBuffer, Uint8Array};
 
 pub struct D1Value {
-    _row: JsValue
+    _row: JsValue,
+    column: String,
+    decoding_mode: D1DecodingMode,
+}
+
+/// How strictly stored values have to match the Rust type they're read into
+#[derive(Debug, Copy, Clone, Hash, PartialEq, Eq, Default)]
+pub enum D1DecodingMode {
+    /// Every Rust type accepts exactly one kind of JS value
+    #[default]
+    Strict,
+    /// Follows SQLite's type affinity rules, for tables whose contents don't match their
+    /// declared types: numeric text is parsed into numbers, numbers are formatted as text,
+    /// REALs are truncated into integers and common boolean spellings (`t`/`f`, `yes`/`no`,
+    /// `on`/`off`, ...) are accepted
+    Lenient,
+}
+
+/// The SQLite storage class a [`D1Value`] corresponds to
+#[derive(Debug, Copy, Clone, Hash, PartialEq, Eq)]
+pub enum D1ValueType {
+    Null,
+    Integer,
+    Real,
+    Text,
+    Blob,
+}
+
+/// Returned by `FromSql` impls when a column holds something other than what the Rust type expects
+#[derive(Debug, Clone, PartialEq)]
+pub enum D1ValueError {
+    /// The JS value has a different type than the one expected
+    UnexpectedType {
+        column: String,
+        expected: &'static str,
+        actual: &'static str,
+    },
+    /// The value has the right type but doesn't fit into the requested Rust type
+    OutOfRange {
+        column: String,
+        value: String,
+        target: &'static str,
+    },
 }
 
+impl fmt::Display for D1ValueError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            D1ValueError::UnexpectedType { column, expected, actual } => write!(
+                f,
+                "unexpected value for column `{column}`: expected {expected}, found {actual}"
+            ),
+            D1ValueError::OutOfRange { column, value, target } => write!(
+                f,
+                "value {value} of column `{column}` cannot be represented as {target}"
+            ),
+        }
+    }
+}
 
+impl Error for D1ValueError {}
 
 impl D1Value {
-    pub fn new(row: JsValue) -> Self {
-        Self { _row: row }
+    pub fn new(row: JsValue, column: String, decoding_mode: D1DecodingMode) -> Self {
+        Self { _row: row, column, decoding_mode }
     }
-    
-    pub (crate) fn read_string(&self) -> String {
-        self._row.as_string().unwrap()
+
+    /// Name of the column this value was read from
+    pub fn column(&self) -> &str {
+        &self.column
     }
 
-    pub (crate) fn read_bool(&self) -> bool {
-        self._row.as_bool().unwrap()
+    /// Storage class of the value, as far as it can be told from the JS value D1 returned
+    ///
+    /// D1 hands every number back as a JS number, so whole numbers are reported as `Integer`
+    /// and everything else as `Real`. Booleans only show up for values bound from JS and are
+    /// reported as `Integer`, like SQLite stores them.
+    pub fn value_type(&self) -> D1ValueType {
+        let value = &self._row;
+        if self.check_null() {
+            D1ValueType::Null
+        } else if value.as_bool().is_some() {
+            D1ValueType::Integer
+        } else if let Some(number) = value.as_f64() {
+            if is_integer(number) {
+                D1ValueType::Integer
+            } else {
+                D1ValueType::Real
+            }
+        } else if value.is_string() {
+            D1ValueType::Text
+        } else if value.is_instance_of::<Uint8Array>()
+            || value.is_instance_of::<ArrayBuffer>()
+            || Array::is_array(value)
+        {
+            D1ValueType::Blob
+        } else {
+            // not something D1 returns, reading it as text reports the actual JS type
+            D1ValueType::Text
+        }
+    }
+
+    pub (crate) fn read_string(&self) -> deserialize::Result<String> {
+        if let Some(text) = self._row.as_string() {
+            return Ok(text);
+        }
+
+        match self._row.as_f64() {
+            Some(number) if self.is_lenient() => Ok(number.to_string()),
+            _ => Err(self.unexpected_type("string")),
+        }
+    }
+
+    /// SQLite has no boolean type, so both JS booleans and the integers 0/1 are accepted
+    pub (crate) fn read_bool(&self) -> deserialize::Result<bool> {
+        if let Some(value) = self._row.as_bool() {
+            return Ok(value);
+        }
+
+        if self.is_lenient() {
+            if let Some(text) = self._row.as_string() {
+                return match text.trim().to_ascii_lowercase().as_str() {
+                    "1" | "t" | "true" | "y" | "yes" | "on" => Ok(true),
+                    "0" | "f" | "false" | "n" | "no" | "off" => Ok(false),
+                    _ => Err(self.unexpected_type("boolean")),
+                };
+            }
+        }
+
+        match self._row.as_f64() {
+            Some(number) if number == 0.0 => Ok(false),
+            Some(number) if number == 1.0 => Ok(true),
+            // SQLite treats every other number as true
+            Some(_) if self.is_lenient() => Ok(true),
+            Some(number) => Err(self.out_of_range(number, "bool")),
+            None => Err(self.unexpected_type("boolean")),
+        }
     }
 
     /// JS numbers are always f64, this might cause precision issues when crossing boundaries
-    pub (crate) fn read_number(&self) -> f64 {
-        self._row.as_f64().unwrap()
+    pub (crate) fn read_number(&self) -> deserialize::Result<f64> {
+        if let Some(number) = self._row.as_f64() {
+            return Ok(number);
+        }
+
+        let parsed = match self._row.as_string() {
+            Some(text) if self.is_lenient() => text.trim().parse::<f64>().ok(),
+            _ => None,
+        };
+        parsed.ok_or_else(|| self.unexpected_type("number"))
+    }
+
+    /// Reads a whole number and narrows it to `T`, failing instead of truncating or wrapping
+    pub (crate) fn read_integer<T>(&self) -> deserialize::Result<T>
+    where
+        T: TryFrom<i64>,
+    {
+        let mut number = self.read_number()?;
+        if self.is_lenient() {
+            // like SQLite's CAST(... AS INTEGER)
+            number = number.trunc();
+        }
+        if !is_integer(number) {
+            return Err(self.out_of_range(number, std::any::type_name::<T>()));
+        }
+
+        T::try_from(number as i64).map_err(|_| self.out_of_range(number, std::any::type_name::<T>()))
+    }
+
+    /// Numeric columns are stored as TEXT, but values written by other tools may come back
+    /// as REAL or INTEGER, so both representations are turned into their decimal text form
+    #[cfg(any(feature = "rust_decimal", feature = "bigdecimal"))]
+    pub (crate) fn read_numeric_text(&self) -> deserialize::Result<String> {
+        match self._row.as_f64() {
+            Some(number) => Ok(number.to_string()),
+            None => self._row
+                .as_string()
+                .ok_or_else(|| self.unexpected_type("number or numeric string")),
+        }
     }
 
     pub (crate) fn check_null(&self) -> bool {
         // not sure if undefined works
-        self._row.is_null() || self._row.is_undefined() 
+        self._row.is_null() || self._row.is_undefined()
     }
 
-    pub (crate) fn read_blob(&self) -> Vec<u8> {
-        if !self._row.is_instance_of::<Uint8Array>() {
-            panic!("JSValue is not uint8arrary");
+    pub (crate) fn read_blob(&self) -> deserialize::Result<Vec<u8>> {
+        // D1 hands BLOBs back as plain arrays of numbers, but accept the typed variants too
+        if let Some(array) = self._row.dyn_ref::<Uint8Array>() {
+            Ok(array.to_vec())
+        } else if let Some(buffer) = self._row.dyn_ref::<ArrayBuffer>() {
+            Ok(Uint8Array::new(buffer).to_vec())
+        } else if Array::is_array(&self._row) {
+            Ok(Uint8Array::new(&self._row).to_vec())
+        } else if let Some(text) = self._row.as_string().filter(|_| self.is_lenient()) {
+            Ok(text.into_bytes())
+        } else {
+            Err(self.unexpected_type("blob"))
         }
-        // hummm hopefully _row is reference counted ahah
-        Uint8Array::from(self._row.clone()).to_vec()
     }
-}
\ No newline at end of file
+
+    fn is_lenient(&self) -> bool {
+        self.decoding_mode == D1DecodingMode::Lenient
+    }
+
+    fn unexpected_type(&self, expected: &'static str) -> Box<dyn Error + Send + Sync> {
+        Box::new(D1ValueError::UnexpectedType {
+            column: self.column.clone(),
+            expected,
+            actual: self.js_type_name(),
+        })
+    }
+
+    fn out_of_range(&self, value: f64, target: &'static str) -> Box<dyn Error + Send + Sync> {
+        Box::new(D1ValueError::OutOfRange {
+            column: self.column.clone(),
+            value: value.to_string(),
+            target,
+        })
+    }
+
+    fn js_type_name(&self) -> &'static str {
+        let value = &self._row;
+        if value.is_null() {
+            "null"
+        } else if value.is_undefined() {
+            "undefined"
+        } else if value.as_bool().is_some() {
+            "boolean"
+        } else if value.as_f64().is_some() {
+            "number"
+        } else if value.is_string() {
+            "string"
+        } else if value.is_bigint() {
+            "bigint"
+        } else if value.is_instance_of::<Uint8Array>() {
+            "Uint8Array"
+        } else if value.is_instance_of::<ArrayBuffer>() {
+            "ArrayBuffer"
+        } else if Array::is_array(value) {
+            "array"
+        } else {
+            "object"
+        }
+    }
+}
+
+/// Whether `number` is a whole number that fits into an `i64`
+fn is_integer(number: f64) -> bool {
+    // `i64::MAX as f64` rounds up to 2^63, which is already out of range
+    number.fract() == 0.0 && number >= i64::MIN as f64 && number < i64::MAX as f64
+}